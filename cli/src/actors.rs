@@ -3,8 +3,12 @@ mod fetch_actor;
 pub mod messages;
 mod output_actor;
 mod performance_actor;
+mod quality_actor;
+mod sqlite_actor;
 
 pub use deque_actor::InMemoryQuoteWriter;
 pub use fetch_actor::FetchActor;
 pub use output_actor::OutputActor;
 pub use performance_actor::PerformanceActor;
+pub use quality_actor::{QualityActor, QualityThresholds};
+pub use sqlite_actor::SqliteWriter;
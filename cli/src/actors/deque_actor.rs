@@ -45,7 +45,7 @@ mod tests {
         let store = Arc::new(RwLock::new(BoundedVecDeque::new(10)));
         let actor = InMemoryQuoteWriter::new(store.clone());
         let mut actor_addr = actor.start().await.unwrap();
-        let s = PerformanceIndicators::new(20, &vec![], Ticker::from("test"), Utc::now());
+        let s = PerformanceIndicators::new(20, &[], Ticker::from("test"), Utc::now());
         actor_addr.call(s.clone()).await.unwrap();
         actor_addr.stop(None).unwrap();
         actor_addr.wait_for_stop().await;
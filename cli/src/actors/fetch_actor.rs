@@ -1,109 +1,508 @@
-use crate::actors::messages::{Fetch, PerformanceData};
+use crate::actors::messages::{
+    AddTicker, Backfill, Bars, Drain, Fetch, ListTickers, PerformanceData, RemoveTicker,
+    RunningTotals, WatchlistEntry,
+};
+use crate::config::DEFAULT_GROUP;
+use crate::failures::{FailureSource, FailureTracker, SharedFailures};
+use crate::metadata::{currency_of, SharedMetadata};
+use crate::schedule::{Schedule, TickerGroup};
+use crate::ticker_file::format_ticker_file;
 use anyhow::Result;
 use async_trait::async_trait;
 use chrono::prelude::*;
 use futures::{stream, stream::StreamExt};
+use lib::calendar::{ExchangeCalendar, MarketStatus};
+use lib::corporate_actions::{dividends_per_bar, CorporateAction};
+use lib::currency::{convert, fx_ticker, Conversion, Currency};
+use lib::quote_provider::{Bar, Interval, QuoteProvider};
 use lib::ticker::Ticker;
-use log::error;
-use std::time::Duration;
-use xactor::{Actor, Addr, Context, Handler};
-use yahoo_finance_api::{YResponse, YahooConnector, YahooError};
+use log::{debug, error, info};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::Arc;
+use xactor::{message, Actor, Addr, Broker, Context, Handler};
 
-pub struct FetchActor<T: YahooFinanceApi, H: Handler<PerformanceData>> {
+/// Fetches a single ticker group and schedules its next fetch
+#[message]
+#[derive(Clone)]
+struct ScheduledFetch {
+    group: usize,
+}
+
+pub struct FetchActor<T: QuoteProvider, H: Handler<PerformanceData>> {
     sender: Addr<H>,
-    yahoo_api: T,
-    tickers: Vec<Ticker>,
+    provider: T,
+    groups: Vec<TickerGroup>,
     from: DateTime<Utc>,
+    until: Option<DateTime<Utc>>,
+    draining: bool,
+    interval: Interval,
+    window: usize,
+    calendar: Option<ExchangeCalendar>,
+    off_hours_interval: Option<chrono::Duration>,
+    last_fetch: HashMap<String, (DateTime<Utc>, MarketStatus)>,
+    failures: SharedFailures,
+    watchlist: Option<PathBuf>,
+    metadata: SharedMetadata,
+    report_currency: Option<Currency>,
+    bars: Option<Addr<Broker<Bars>>>,
 }
 
-impl<T: YahooFinanceApi, H: Handler<PerformanceData>> FetchActor<T, H> {
-    pub fn new(sender: Addr<H>, yahoo_api: T, tickers: Vec<Ticker>, from: DateTime<Utc>) -> Self {
+impl<T: QuoteProvider, H: Handler<PerformanceData>> FetchActor<T, H> {
+    pub fn new(sender: Addr<H>, provider: T, tickers: Vec<Ticker>, from: DateTime<Utc>) -> Self {
         FetchActor {
             sender,
-            yahoo_api,
-            tickers,
+            provider,
+            groups: vec![TickerGroup::new(
+                DEFAULT_GROUP.to_owned(),
+                tickers,
+                Schedule::default(),
+            )],
             from,
+            until: None,
+            draining: false,
+            interval: Interval::default(),
+            window: 30,
+            calendar: None,
+            off_hours_interval: None,
+            last_fetch: HashMap::new(),
+            failures: Arc::new(FailureTracker::default()),
+            watchlist: None,
+            metadata: SharedMetadata::default(),
+            report_currency: None,
+            bars: None,
+        }
+    }
+
+    /// Replaces the tickers passed to `new` with groups that are each fetched on their own schedule
+    pub fn with_groups(mut self, groups: Vec<TickerGroup>) -> Self {
+        self.groups = groups;
+        self
+    }
+
+    /// Makes this a bounded historical run: `Fetch` only fetches up to `until`, and groups aren't
+    /// polled on their schedules
+    pub fn with_until(mut self, until: Option<DateTime<Utc>>) -> Self {
+        self.until = until;
+        self
+    }
+
+    pub fn with_interval(mut self, interval: Interval) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// The number of bars window-based indicators are calculated over
+    pub fn with_window(mut self, window: usize) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Restricts polling to the calendar's trading sessions. Outside of them, one more fetch
+    /// picks up the close and then polling is suspended, or slowed to `off_hours_interval`.
+    pub fn with_calendar(
+        mut self,
+        calendar: Option<ExchangeCalendar>,
+        off_hours_interval: Option<chrono::Duration>,
+    ) -> Self {
+        self.calendar = calendar;
+        self.off_hours_interval = off_hours_interval;
+        self
+    }
+
+    /// Shares failure records with the API and applies the tracker's quarantine to fetches
+    pub fn with_failures(mut self, failures: SharedFailures) -> Self {
+        self.failures = failures;
+        self
+    }
+
+    /// Saves the tickers to `watchlist` whenever they are changed through `AddTicker` or
+    /// `RemoveTicker`
+    pub fn with_watchlist(mut self, watchlist: Option<PathBuf>) -> Self {
+        self.watchlist = watchlist;
+        self
+    }
+
+    /// Converts prices to `report_currency` using FX series fetched from the same provider. Each
+    /// ticker's own currency comes from `metadata`, else its exchange suffix.
+    pub fn with_report_currency(
+        mut self,
+        report_currency: Option<Currency>,
+        metadata: SharedMetadata,
+    ) -> Self {
+        self.report_currency = report_currency;
+        self.metadata = metadata;
+        self
+    }
+
+    /// Publishes the bars of every successful fetch to `broker`
+    pub fn with_bars(mut self, broker: Option<Addr<Broker<Bars>>>) -> Self {
+        self.bars = broker;
+        self
+    }
+
+    fn is_watched(&self, ticker: &Ticker) -> bool {
+        self.groups
+            .iter()
+            .any(|group| group.tickers.contains(ticker))
+    }
+
+    /// Writes the watchlist to a temporary file next to it and renames it into place, so a crash
+    /// mid-write never leaves a truncated watchlist to be loaded on the next start
+    fn persist_watchlist(&self) {
+        if let Some(watchlist) = &self.watchlist {
+            let mut temp = watchlist.clone().into_os_string();
+            temp.push(".tmp");
+            let saved = std::fs::write(&temp, format_ticker_file(&self.groups))
+                .and_then(|_| std::fs::rename(&temp, watchlist));
+            if let Err(e) = saved {
+                error!("Failed to save watchlist to {:?}: {:?}", watchlist, e);
+            }
+        }
+    }
+
+    fn should_fetch(&self, group: &str, at: DateTime<Utc>, status: MarketStatus) -> bool {
+        match (status, self.last_fetch.get(group)) {
+            (MarketStatus::Open, _) | (_, None) | (_, Some((_, MarketStatus::Open))) => true,
+            (MarketStatus::Closed, Some((last, MarketStatus::Closed))) => self
+                .off_hours_interval
+                .is_some_and(|off_hours_interval| at - *last >= off_hours_interval),
         }
     }
 }
 
+impl<T: QuoteProvider + Send + Sync + 'static, H: Handler<PerformanceData>> FetchActor<T, H> {
+    /// Fetches the FX series needed to convert `currencies` to the reporting currency, once per pair
+    async fn fetch_fx(
+        &self,
+        currencies: impl Iterator<Item = &Currency>,
+        until: DateTime<Utc>,
+    ) -> HashMap<(Currency, Currency), Vec<Bar>> {
+        let report_currency = match self.report_currency {
+            Some(report_currency) => report_currency,
+            None => return HashMap::new(),
+        };
+        let pairs: HashSet<(Currency, Currency)> = currencies
+            .filter_map(|currency| Conversion::new(*currency, report_currency).pair)
+            .collect();
+        let mut fx = HashMap::new();
+        for (from, to) in pairs {
+            let fx_ticker = fx_ticker(from, to);
+            match self
+                .provider
+                .get_quote_history(&fx_ticker, self.from, until, self.interval)
+                .await
+            {
+                Ok(bars) => {
+                    fx.insert((from, to), bars);
+                }
+                Err(e) => error!("Failed to retrieve FX rates {}: {:?}", fx_ticker, e),
+            }
+        }
+        fx
+    }
+
+    fn schedule_next(&self, ctx: &mut Context<Self>, group: usize) {
+        if self.until.is_some() || self.draining {
+            return;
+        }
+        if let Some(delay) = self.groups[group].schedule.delay_after(Utc::now()) {
+            ctx.send_later(ScheduledFetch { group }, delay);
+        }
+    }
+
+    /// Fetches a group's tickers up to `until`, sending their performance data as of `until`, or
+    /// as of each bar if `history` is set
+    async fn fetch_group(&mut self, group: usize, until: DateTime<Utc>, history: bool) {
+        let name = self.groups[group].name.clone();
+        let market_status = self
+            .calendar
+            .as_ref()
+            .filter(|_| !history)
+            .map(|calendar| calendar.status_at(until));
+        if let Some(status) = market_status {
+            if !self.should_fetch(&name, until, status) {
+                debug!(
+                    "Skipping fetch of {} at {} while the market is closed",
+                    name, until
+                );
+                return;
+            }
+            self.last_fetch.insert(name, (until, status));
+        }
+
+        let provider = &self.provider;
+        let from = self.from;
+        let interval = self.interval;
+        let window = self.window;
+        let sender = &self.sender;
+        let failures = &self.failures;
+        let tickers: Vec<Ticker> = self.groups[group]
+            .tickers
+            .iter()
+            .filter(|ticker| failures.should_attempt(ticker, until))
+            .cloned()
+            .collect();
+        let currencies: HashMap<Ticker, Currency> = tickers
+            .iter()
+            .map(|ticker| {
+                (
+                    ticker.clone(),
+                    currency_of(ticker, self.metadata.get(ticker)),
+                )
+            })
+            .collect();
+        let fx = &self.fetch_fx(currencies.values(), until).await;
+        let currencies = &currencies;
+        let report_currency = self.report_currency;
+        let bars = &self.bars;
+        stream::iter(tickers)
+            .for_each_concurrent(None, |ticker| async move {
+                let (quotes, corporate_actions) =
+                    match provider.get_history(&ticker, from, until, interval).await {
+                        Err(e) => {
+                            error!("Failed to retrieve quotes for {:?}: {:?}", ticker, e);
+                            failures.record_failure(&ticker, FailureSource::Fetch, e, until);
+                            return;
+                        }
+                        Ok(history) => (history.bars, history.corporate_actions),
+                    };
+                failures.record_success(&ticker, FailureSource::Fetch);
+                if let Some(mut broker) = bars.clone() {
+                    let published = Bars::new(ticker.clone(), interval, quotes.clone());
+                    if let Err(e) = broker.publish(published) {
+                        error!("Failed to publish bars for {:?}: {:?}", ticker, e);
+                    }
+                }
+                let corporate_actions = corporate_actions.unwrap_or_else(|e| {
+                    error!(
+                        "Failed to retrieve corporate actions for {:?}: {:?}",
+                        ticker, e
+                    );
+                    vec![]
+                });
+                let series: Vec<f64> = quotes.iter().map(|q| q.adjclose).collect();
+                let closes: Vec<f64> = quotes.iter().map(|q| q.close).collect();
+                let timestamps: Vec<DateTime<Utc>> = quotes.iter().map(|q| q.timestamp).collect();
+                let volumes: Vec<u64> = quotes.iter().map(|q| q.volume).collect();
+                let dividends = dividends_per_bar(&timestamps, &corporate_actions);
+                let mut currency = currencies[&ticker];
+                let (series, closes, dividends) = match report_currency {
+                    Some(report_currency) => {
+                        let conversion = Conversion::new(currency, report_currency);
+                        let rates = conversion
+                            .pair
+                            .and_then(|pair| fx.get(&pair))
+                            .map(Vec::as_slice)
+                            .unwrap_or_default();
+                        match (
+                            convert(&series, &timestamps, conversion, rates),
+                            convert(&closes, &timestamps, conversion, rates),
+                            convert(&dividends, &timestamps, conversion, rates),
+                        ) {
+                            (Some(series), Some(closes), Some(dividends)) => {
+                                currency = report_currency;
+                                (series, closes, dividends)
+                            }
+                            _ => {
+                                error!(
+                                    "No {} to {} rates, reporting {:?} in {}",
+                                    currency, report_currency, ticker, currency
+                                );
+                                (series, closes, dividends)
+                            }
+                        }
+                    }
+                    None => (series, closes, dividends),
+                };
+                let send = |bars: Range<usize>,
+                            to: DateTime<Utc>,
+                            corporate_actions: Vec<CorporateAction>,
+                            totals: Option<RunningTotals>| {
+                    let performance_data = PerformanceData::new(
+                        ticker.clone(),
+                        window,
+                        series[bars.clone()].to_vec(),
+                        to,
+                    )
+                    .with_currency(currency)
+                    .with_market_status(market_status)
+                    .with_corporate_actions(
+                        closes[bars.clone()].to_vec(),
+                        dividends[bars.clone()].to_vec(),
+                        corporate_actions,
+                    )
+                    .with_timestamps(timestamps[bars.clone()].to_vec(), interval)
+                    .with_volumes(volumes[bars].to_vec())
+                    .with_totals(totals);
+                    if let Err(e) = sender.send(performance_data) {
+                        error!("Failed to send quotes to actor: {:?}", e)
+                    }
+                };
+                if !history {
+                    send(0..timestamps.len(), until, corporate_actions, None);
+                    return;
+                }
+                // Each bar goes with the bars its window reads and running totals over the ones
+                // before, so backfilling stays linear in the length of the series
+                let mut corporate_actions = corporate_actions;
+                corporate_actions.sort_by_key(CorporateAction::date);
+                let mut totals: Option<RunningTotals> = None;
+                for end in 1..=timestamps.len() {
+                    let bar = end - 1;
+                    match totals.as_mut() {
+                        Some(totals) => totals.push(series[bar], closes[bar], dividends[bar]),
+                        None => totals = Some(RunningTotals::new(series[bar], closes[bar])),
+                    }
+                    let to = timestamps[bar];
+                    let known = corporate_actions.partition_point(|action| action.date() <= to);
+                    send(
+                        end.saturating_sub(window + 1)..end,
+                        to,
+                        corporate_actions[..known].to_vec(),
+                        totals,
+                    );
+                }
+            })
+            .await;
+    }
+}
+
 #[async_trait]
-impl<T: YahooFinanceApi + Send + Sync + 'static, H: Handler<PerformanceData>> Actor
+impl<T: QuoteProvider + Send + Sync + 'static, H: Handler<PerformanceData>> Actor
     for FetchActor<T, H>
 {
     async fn started(&mut self, ctx: &mut Context<Self>) -> Result<()> {
-        ctx.send_interval_with(Fetch::new, Duration::from_secs(30));
+        for group in 0..self.groups.len() {
+            self.schedule_next(ctx, group);
+        }
         Ok(())
     }
 }
 
 #[async_trait]
-impl<T: YahooFinanceApi + Send + Sync + 'static, H: Handler<PerformanceData>> Handler<Fetch>
+impl<T: QuoteProvider + Send + Sync + 'static, H: Handler<PerformanceData>> Handler<Fetch>
     for FetchActor<T, H>
 {
     async fn handle(&mut self, _ctx: &mut Context<Self>, msg: Fetch) -> () {
-        let provider = &self.yahoo_api;
-        let from = self.from;
-        let until = msg.until();
-        let sender = &self.sender;
-        stream::iter(self.tickers.clone())
-            .for_each_concurrent(None, |ticker| async move {
-                let quotes = match provider
-                    .get_quote_history(ticker.as_str(), from, until)
-                    .await
-                    .and_then(|x| x.quotes())
-                {
-                    Err(e) => {
-                        error!("Failed to retrieve quotes for {:?}: {:?}", ticker, e);
-                        return;
-                    }
-                    Ok(o) => o,
-                };
-                let series: Vec<f64> = quotes.iter().map(|q| q.adjclose).collect();
-                let performance_data = PerformanceData::new(ticker, 30, series, until);
-                if let Err(e) = sender.send(performance_data) {
-                    error!("Failed to send quotes to actor: {:?}", e)
-                }
-            })
-            .await;
+        let until = match self.until {
+            Some(until) => until.min(msg.until()),
+            None => msg.until(),
+        };
+        for group in 0..self.groups.len() {
+            self.fetch_group(group, until, false).await;
+        }
     }
 }
 
 #[async_trait]
-pub trait YahooFinanceApi {
-    async fn get_quote_history(
-        &self,
-        ticker: &str,
-        start: DateTime<Utc>,
-        end: DateTime<Utc>,
-    ) -> Result<YResponse, YahooError>;
+impl<T: QuoteProvider + Send + Sync + 'static, H: Handler<PerformanceData>> Handler<Backfill>
+    for FetchActor<T, H>
+{
+    async fn handle(&mut self, _ctx: &mut Context<Self>, msg: Backfill) -> () {
+        let until = match self.until {
+            Some(until) => until.min(msg.until()),
+            None => msg.until(),
+        };
+        for group in 0..self.groups.len() {
+            self.fetch_group(group, until, true).await;
+        }
+    }
 }
 
 #[async_trait]
-impl YahooFinanceApi for YahooConnector {
-    async fn get_quote_history(
-        &self,
-        ticker: &str,
-        start: DateTime<Utc>,
-        end: DateTime<Utc>,
-    ) -> Result<YResponse, YahooError> {
-        self.get_quote_history(ticker, start, end).await
+impl<T: QuoteProvider + Send + Sync + 'static, H: Handler<PerformanceData>> Handler<ScheduledFetch>
+    for FetchActor<T, H>
+{
+    async fn handle(&mut self, ctx: &mut Context<Self>, msg: ScheduledFetch) -> () {
+        if self.draining {
+            return;
+        }
+        self.fetch_group(msg.group, Utc::now(), false).await;
+        self.schedule_next(ctx, msg.group);
+    }
+}
+
+/// Stops polling, so no more performance data is sent once in-flight fetches are done
+#[async_trait]
+impl<T: QuoteProvider + Send + Sync + 'static, H: Handler<PerformanceData>> Handler<Drain>
+    for FetchActor<T, H>
+{
+    async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: Drain) -> () {
+        self.draining = true;
+    }
+}
+
+#[async_trait]
+impl<T: QuoteProvider + Send + Sync + 'static, H: Handler<PerformanceData>> Handler<AddTicker>
+    for FetchActor<T, H>
+{
+    async fn handle(&mut self, ctx: &mut Context<Self>, msg: AddTicker) -> bool {
+        if self.is_watched(&msg.ticker) {
+            return false;
+        }
+        let name = msg.group.unwrap_or_else(|| DEFAULT_GROUP.to_owned());
+        info!("Adding {} to group {}", msg.ticker, name);
+        match self.groups.iter_mut().find(|group| group.name == name) {
+            Some(group) => group.tickers.push(msg.ticker),
+            None => {
+                self.groups.push(TickerGroup::new(
+                    name,
+                    vec![msg.ticker],
+                    Schedule::default(),
+                ));
+                self.schedule_next(ctx, self.groups.len() - 1);
+            }
+        }
+        self.persist_watchlist();
+        true
+    }
+}
+
+#[async_trait]
+impl<T: QuoteProvider + Send + Sync + 'static, H: Handler<PerformanceData>> Handler<RemoveTicker>
+    for FetchActor<T, H>
+{
+    async fn handle(&mut self, _ctx: &mut Context<Self>, msg: RemoveTicker) -> bool {
+        if !self.is_watched(&msg.ticker) {
+            return false;
+        }
+        info!("Removing {}", msg.ticker);
+        for group in self.groups.iter_mut() {
+            group.tickers.retain(|ticker| *ticker != msg.ticker);
+        }
+        self.persist_watchlist();
+        true
+    }
+}
+
+#[async_trait]
+impl<T: QuoteProvider + Send + Sync + 'static, H: Handler<PerformanceData>> Handler<ListTickers>
+    for FetchActor<T, H>
+{
+    async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: ListTickers) -> Vec<WatchlistEntry> {
+        self.groups
+            .iter()
+            .flat_map(|group| {
+                group.tickers.iter().map(move |ticker| WatchlistEntry {
+                    ticker: ticker.clone(),
+                    group: group.name.clone(),
+                })
+            })
+            .collect()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::actors::messages::{Fetch, PerformanceData};
-    use async_std;
-    use async_trait::async_trait;
-    use lib::ticker::Ticker;
-    use serde_json;
-    use std::fs::File;
-    use std::io::BufReader;
+    use crate::actors::messages::PerformanceIndicators;
+    use crate::providers::FileProvider;
+    use lib::corporate_actions::CorporateAction;
+    use lib::quote_provider::{Bar, ProviderError};
+    use std::path::PathBuf;
     use std::sync::{Arc, Mutex};
-    use xactor::{Actor, Context, Handler};
-    use yahoo_finance_api::YResponse;
     struct MockPerformanceDataActor {
         buf: Arc<Mutex<Vec<PerformanceData>>>,
     }
@@ -119,29 +518,44 @@ mod tests {
         async fn handle(&mut self, _ctx: &mut Context<Self>, msg: PerformanceData) -> () {
             let mut data = self.buf.lock().unwrap();
             data.push(msg);
-            ()
         }
     }
 
-    struct MockYahooConnector;
+    struct MockQuoteProvider;
 
     #[async_trait]
-    impl YahooFinanceApi for MockYahooConnector {
+    impl QuoteProvider for MockQuoteProvider {
         async fn get_quote_history(
             &self,
-            _ticker: &str,
-            _start: DateTime<Utc>,
+            _ticker: &Ticker,
+            start: DateTime<Utc>,
             _end: DateTime<Utc>,
-        ) -> std::result::Result<YResponse, YahooError> {
-            let file = File::open(format!(
-                "{}/src/mockYahooData.json",
-                env!("CARGO_MANIFEST_DIR")
-            ))
-            .unwrap();
-            let reader = BufReader::new(file);
+            _interval: Interval,
+        ) -> std::result::Result<Vec<Bar>, ProviderError> {
+            Ok([1f64, 2f64, 3f64]
+                .iter()
+                .map(|&price| Bar {
+                    timestamp: start,
+                    open: price,
+                    high: price,
+                    low: price,
+                    close: price,
+                    adjclose: price,
+                    volume: 0,
+                })
+                .collect())
+        }
 
-            let u = serde_json::from_reader(reader).unwrap();
-            YResponse::from_json(u)
+        async fn get_corporate_actions(
+            &self,
+            _ticker: &Ticker,
+            start: DateTime<Utc>,
+            _end: DateTime<Utc>,
+        ) -> std::result::Result<Vec<CorporateAction>, ProviderError> {
+            Ok(vec![CorporateAction::Dividend {
+                date: start,
+                amount: 0.5,
+            }])
         }
     }
 
@@ -154,10 +568,10 @@ mod tests {
         let mut mock_performance_data_actor_addr =
             mock_performance_data_actor.start().await.unwrap();
 
-        let mock_yahoo_api = MockYahooConnector;
+        let mock_provider = MockQuoteProvider;
         let fetch_actor = FetchActor::new(
             mock_performance_data_actor_addr.clone(),
-            mock_yahoo_api,
+            mock_provider,
             tickers,
             Utc::now(),
         );
@@ -182,17 +596,357 @@ mod tests {
             Fetch::from_datetime(now),
         )
         .await;
-        let message = sent_messages.into_iter().nth(0).unwrap();
+        let message = sent_messages.into_iter().next().unwrap();
 
         let expected = PerformanceData::new(
             Ticker::new("test".to_string()),
             30,
             vec![1f64, 2f64, 3f64],
             now,
-        );
+        )
+        .with_corporate_actions(
+            vec![1f64, 2f64, 3f64],
+            vec![0.5f64, 0f64, 0f64],
+            vec![CorporateAction::Dividend {
+                date: message.corporate_actions()[0].date(),
+                amount: 0.5,
+            }],
+        )
+        .with_timestamps(message.timestamps().to_vec(), Interval::OneDay)
+        .with_volumes(vec![0; 3]);
         assert_eq!(message, expected);
     }
 
+    #[async_std::test]
+    async fn fetch_actor_fetches_no_later_than_until() {
+        let buf = Arc::new(Mutex::new(vec![]));
+        let mut mock_performance_data_actor_addr = MockPerformanceDataActor::new(buf.clone())
+            .start()
+            .await
+            .unwrap();
+        let provider = FileProvider::new(PathBuf::from(format!(
+            "{}/src/providers/testdata",
+            env!("CARGO_MANIFEST_DIR")
+        )));
+        let until = Utc.with_ymd_and_hms(2021, 1, 5, 0, 0, 0).unwrap();
+        let mut fetch_actor_addr = FetchActor::new(
+            mock_performance_data_actor_addr.clone(),
+            provider,
+            vec![Ticker::from("TEST")],
+            Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap(),
+        )
+        .with_until(Some(until))
+        .start()
+        .await
+        .unwrap();
+
+        fetch_actor_addr.call(Fetch::new()).await.unwrap();
+        fetch_actor_addr.stop(None).unwrap();
+        fetch_actor_addr.wait_for_stop().await;
+        mock_performance_data_actor_addr.stop(None).unwrap();
+        mock_performance_data_actor_addr.wait_for_stop().await;
+
+        let sent_messages = buf.lock().unwrap();
+        assert_eq!(sent_messages.len(), 1);
+        assert_eq!(sent_messages[0].to(), until);
+        assert_eq!(sent_messages[0].performance_data(), &[10f64, 11f64]);
+    }
+
+    #[async_std::test]
+    async fn fetch_actor_backfills_performance_data_as_of_each_bar() {
+        let buf = Arc::new(Mutex::new(vec![]));
+        let mut mock_performance_data_actor_addr = MockPerformanceDataActor::new(buf.clone())
+            .start()
+            .await
+            .unwrap();
+        let provider = FileProvider::new(PathBuf::from(format!(
+            "{}/src/providers/testdata",
+            env!("CARGO_MANIFEST_DIR")
+        )));
+        let mut fetch_actor_addr = FetchActor::new(
+            mock_performance_data_actor_addr.clone(),
+            provider,
+            vec![Ticker::from("TEST")],
+            Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap(),
+        )
+        .start()
+        .await
+        .unwrap();
+
+        fetch_actor_addr
+            .call(Backfill::new(
+                Utc.with_ymd_and_hms(2021, 1, 6, 0, 0, 0).unwrap(),
+            ))
+            .await
+            .unwrap();
+        fetch_actor_addr.stop(None).unwrap();
+        fetch_actor_addr.wait_for_stop().await;
+        mock_performance_data_actor_addr.stop(None).unwrap();
+        mock_performance_data_actor_addr.wait_for_stop().await;
+
+        let sent: Vec<(DateTime<Utc>, Vec<f64>)> = buf
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|data| (data.to(), data.performance_data().to_vec()))
+            .collect();
+        assert_eq!(
+            sent,
+            vec![
+                (
+                    Utc.with_ymd_and_hms(2021, 1, 4, 0, 0, 0).unwrap(),
+                    vec![10f64]
+                ),
+                (
+                    Utc.with_ymd_and_hms(2021, 1, 5, 0, 0, 0).unwrap(),
+                    vec![10f64, 11f64]
+                ),
+                (
+                    Utc.with_ymd_and_hms(2021, 1, 6, 0, 0, 0).unwrap(),
+                    vec![10f64, 11f64, 12f64]
+                ),
+            ]
+        );
+    }
+
+    /// Bars a minute apart from `origin`, whatever the range asked for, with a dividend on the
+    /// tenth
+    struct LongSeriesProvider {
+        origin: DateTime<Utc>,
+        bars: usize,
+    }
+
+    impl LongSeriesProvider {
+        fn prices(&self) -> Vec<f64> {
+            (0..self.bars).map(|bar| (100 + bar % 97) as f64).collect()
+        }
+    }
+
+    #[async_trait]
+    impl QuoteProvider for LongSeriesProvider {
+        async fn get_quote_history(
+            &self,
+            _ticker: &Ticker,
+            _start: DateTime<Utc>,
+            _end: DateTime<Utc>,
+            _interval: Interval,
+        ) -> std::result::Result<Vec<Bar>, ProviderError> {
+            Ok(self
+                .prices()
+                .into_iter()
+                .enumerate()
+                .map(|(bar, price)| Bar {
+                    timestamp: self.origin + chrono::Duration::minutes(bar as i64),
+                    open: price,
+                    high: price,
+                    low: price,
+                    close: price,
+                    adjclose: price,
+                    volume: 0,
+                })
+                .collect())
+        }
+
+        async fn get_corporate_actions(
+            &self,
+            _ticker: &Ticker,
+            _start: DateTime<Utc>,
+            _end: DateTime<Utc>,
+        ) -> std::result::Result<Vec<CorporateAction>, ProviderError> {
+            Ok(vec![CorporateAction::Dividend {
+                date: self.origin + chrono::Duration::minutes(10),
+                amount: 0.5,
+            }])
+        }
+    }
+
+    #[async_std::test]
+    async fn fetch_actor_backfills_a_long_series_with_its_window_and_running_totals() {
+        let buf = Arc::new(Mutex::new(vec![]));
+        let mut mock_performance_data_actor_addr = MockPerformanceDataActor::new(buf.clone())
+            .start()
+            .await
+            .unwrap();
+        let origin = Utc.with_ymd_and_hms(2021, 1, 4, 0, 0, 0).unwrap();
+        let provider = LongSeriesProvider {
+            origin,
+            bars: 100_000,
+        };
+        let prices = provider.prices();
+        let mut fetch_actor_addr = FetchActor::new(
+            mock_performance_data_actor_addr.clone(),
+            provider,
+            vec![Ticker::from("TEST")],
+            origin,
+        )
+        .with_interval(Interval::OneMinute)
+        .with_window(5)
+        .start()
+        .await
+        .unwrap();
+
+        fetch_actor_addr
+            .call(Backfill::new(origin + chrono::Duration::days(100)))
+            .await
+            .unwrap();
+        fetch_actor_addr.stop(None).unwrap();
+        fetch_actor_addr.wait_for_stop().await;
+        mock_performance_data_actor_addr.stop(None).unwrap();
+        mock_performance_data_actor_addr.wait_for_stop().await;
+
+        let sent = buf.lock().unwrap();
+        assert_eq!(sent.len(), prices.len());
+        assert!(sent.iter().all(|data| data.performance_data().len() <= 6));
+        assert!(sent[9].corporate_actions().is_empty());
+        assert_eq!(sent[10].corporate_actions().len(), 1);
+
+        let indicators = |data: &PerformanceData| {
+            PerformanceIndicators::new(5, data.performance_data(), data.ticker().clone(), data.to())
+                .with_corporate_actions(data.closes(), data.dividends(), data.corporate_actions())
+                .with_totals(data.totals())
+        };
+        let last = sent.last().unwrap();
+        let timestamps: Vec<DateTime<Utc>> = (0..prices.len())
+            .map(|bar| origin + chrono::Duration::minutes(bar as i64))
+            .collect();
+        let whole = PerformanceData::new(Ticker::from("TEST"), 5, prices.clone(), last.to())
+            .with_corporate_actions(
+                prices.clone(),
+                dividends_per_bar(&timestamps, last.corporate_actions()),
+                last.corporate_actions().to_vec(),
+            );
+        assert_eq!(indicators(last), indicators(&whole));
+    }
+
+    #[async_std::test]
+    async fn fetch_actor_stops_polling_once_drained() {
+        let buf = Arc::new(Mutex::new(vec![]));
+        let mut mock_performance_data_actor_addr = MockPerformanceDataActor::new(buf.clone())
+            .start()
+            .await
+            .unwrap();
+        let mut fetch_actor_addr = FetchActor::new(
+            mock_performance_data_actor_addr.clone(),
+            MockQuoteProvider,
+            vec![],
+            Utc::now(),
+        )
+        .with_groups(vec![TickerGroup::new(
+            DEFAULT_GROUP.to_owned(),
+            vec![Ticker::from("TEST")],
+            Schedule::every(chrono::Duration::milliseconds(10)),
+        )])
+        .start()
+        .await
+        .unwrap();
+
+        fetch_actor_addr.call(Drain).await.unwrap();
+        async_std::task::sleep(std::time::Duration::from_millis(100)).await;
+        fetch_actor_addr.stop(None).unwrap();
+        fetch_actor_addr.wait_for_stop().await;
+        mock_performance_data_actor_addr.stop(None).unwrap();
+        mock_performance_data_actor_addr.wait_for_stop().await;
+
+        assert!(buf.lock().unwrap().is_empty());
+    }
+
+    #[async_std::test]
+    async fn fetch_actor_sends_series_from_file_provider() {
+        let buf = Arc::new(Mutex::new(vec![]));
+        let mut mock_performance_data_actor_addr = MockPerformanceDataActor::new(buf.clone())
+            .start()
+            .await
+            .unwrap();
+        let provider = FileProvider::new(PathBuf::from(format!(
+            "{}/src/providers/testdata",
+            env!("CARGO_MANIFEST_DIR")
+        )));
+        let until = Utc.with_ymd_and_hms(2021, 1, 6, 0, 0, 0).unwrap();
+        let mut fetch_actor_addr = FetchActor::new(
+            mock_performance_data_actor_addr.clone(),
+            provider,
+            vec![Ticker::from("TEST")],
+            Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap(),
+        )
+        .start()
+        .await
+        .unwrap();
+
+        fetch_actor_addr
+            .call(Fetch::from_datetime(until))
+            .await
+            .unwrap();
+        fetch_actor_addr.stop(None).unwrap();
+        fetch_actor_addr.wait_for_stop().await;
+        mock_performance_data_actor_addr.stop(None).unwrap();
+        mock_performance_data_actor_addr.wait_for_stop().await;
+
+        let expected =
+            PerformanceData::new(Ticker::from("TEST"), 30, vec![10f64, 11f64, 12f64], until)
+                .with_corporate_actions(vec![10.5f64, 11.5f64, 12.5f64], vec![0f64; 3], vec![])
+                .with_timestamps(
+                    (4..=6)
+                        .map(|day| Utc.with_ymd_and_hms(2021, 1, day, 0, 0, 0).unwrap())
+                        .collect(),
+                    Interval::OneDay,
+                )
+                .with_volumes(vec![1000, 1200, 900]);
+        assert_eq!(buf.lock().unwrap().clone(), vec![expected]);
+    }
+
+    #[async_std::test]
+    async fn fetch_actor_suspends_polling_while_market_is_closed() {
+        let buf = Arc::new(Mutex::new(vec![]));
+        let mut mock_performance_data_actor_addr = MockPerformanceDataActor::new(buf.clone())
+            .start()
+            .await
+            .unwrap();
+        let mut fetch_actor_addr = FetchActor::new(
+            mock_performance_data_actor_addr.clone(),
+            MockQuoteProvider,
+            vec![Ticker::from("TEST")],
+            Utc::now(),
+        )
+        .with_calendar(Some(ExchangeCalendar::nyse()), None)
+        .start()
+        .await
+        .unwrap();
+
+        // A Saturday, then the following Monday at 10:00 in New York
+        let saturday = Utc.with_ymd_and_hms(2021, 8, 14, 15, 0, 0).unwrap();
+        for fetch in [
+            saturday,
+            saturday + chrono::Duration::hours(1),
+            Utc.with_ymd_and_hms(2021, 8, 16, 14, 0, 0).unwrap(),
+        ] {
+            fetch_actor_addr
+                .call(Fetch::from_datetime(fetch))
+                .await
+                .unwrap();
+        }
+        fetch_actor_addr.stop(None).unwrap();
+        fetch_actor_addr.wait_for_stop().await;
+        mock_performance_data_actor_addr.stop(None).unwrap();
+        mock_performance_data_actor_addr.wait_for_stop().await;
+
+        let statuses: Vec<_> = buf
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|data| (data.to(), data.market_status()))
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                (saturday, Some(MarketStatus::Closed)),
+                (
+                    Utc.with_ymd_and_hms(2021, 8, 16, 14, 0, 0).unwrap(),
+                    Some(MarketStatus::Open)
+                )
+            ]
+        );
+    }
+
     #[async_std::test]
     async fn fetch_actor_retrieves_multiple_tickers() {
         let sent_messages = create_buf_and_actors_and_call_actor_with(
@@ -205,4 +959,203 @@ mod tests {
         .await;
         assert_eq!(sent_messages.len(), 2);
     }
+
+    struct FailingQuoteProvider {
+        calls: Arc<Mutex<usize>>,
+    }
+
+    #[async_trait]
+    impl QuoteProvider for FailingQuoteProvider {
+        async fn get_quote_history(
+            &self,
+            ticker: &Ticker,
+            _start: DateTime<Utc>,
+            _end: DateTime<Utc>,
+            _interval: Interval,
+        ) -> std::result::Result<Vec<Bar>, ProviderError> {
+            *self.calls.lock().unwrap() += 1;
+            Err(ProviderError::NoData(ticker.to_string()))
+        }
+    }
+
+    #[async_std::test]
+    async fn fetch_actor_quarantines_failing_tickers_and_retries_them_later() {
+        let buf = Arc::new(Mutex::new(vec![]));
+        let calls = Arc::new(Mutex::new(0));
+        let failures = Arc::new(FailureTracker::new(1, chrono::Duration::minutes(15)));
+        let mut mock_performance_data_actor_addr = MockPerformanceDataActor::new(buf.clone())
+            .start()
+            .await
+            .unwrap();
+        let mut fetch_actor_addr = FetchActor::new(
+            mock_performance_data_actor_addr.clone(),
+            FailingQuoteProvider {
+                calls: calls.clone(),
+            },
+            vec![Ticker::from("TEST")],
+            Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap(),
+        )
+        .with_failures(failures.clone())
+        .start()
+        .await
+        .unwrap();
+
+        let first = Utc.with_ymd_and_hms(2021, 8, 10, 12, 0, 0).unwrap();
+        for fetch in [
+            first,
+            first + chrono::Duration::minutes(1),
+            first + chrono::Duration::minutes(15),
+        ] {
+            fetch_actor_addr
+                .call(Fetch::from_datetime(fetch))
+                .await
+                .unwrap();
+        }
+        fetch_actor_addr.stop(None).unwrap();
+        fetch_actor_addr.wait_for_stop().await;
+        mock_performance_data_actor_addr.stop(None).unwrap();
+        mock_performance_data_actor_addr.wait_for_stop().await;
+
+        assert_eq!(*calls.lock().unwrap(), 2);
+        assert!(buf.lock().unwrap().is_empty());
+        assert_eq!(failures.records().len(), 1);
+    }
+
+    #[async_std::test]
+    async fn fetch_actor_adds_and_removes_tickers_and_persists_watchlist() {
+        let dir = tempfile::tempdir().unwrap();
+        let watchlist = dir.path().join("watchlist.txt");
+        let mut mock_performance_data_actor_addr =
+            MockPerformanceDataActor::new(Arc::new(Mutex::new(vec![])))
+                .start()
+                .await
+                .unwrap();
+        let mut fetch_actor_addr = FetchActor::new(
+            mock_performance_data_actor_addr.clone(),
+            MockQuoteProvider,
+            vec![Ticker::from("IBM")],
+            Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap(),
+        )
+        .with_watchlist(Some(watchlist.clone()))
+        .start()
+        .await
+        .unwrap();
+
+        let add = |ticker: &str, group: Option<&str>| AddTicker {
+            ticker: Ticker::from(ticker),
+            group: group.map(str::to_owned),
+        };
+        assert!(fetch_actor_addr.call(add("MSFT", None)).await.unwrap());
+        assert!(fetch_actor_addr
+            .call(add("XOM", Some("energy")))
+            .await
+            .unwrap());
+        assert!(!fetch_actor_addr.call(add("IBM", None)).await.unwrap());
+        assert!(fetch_actor_addr
+            .call(RemoveTicker {
+                ticker: Ticker::from("IBM")
+            })
+            .await
+            .unwrap());
+        assert!(!fetch_actor_addr
+            .call(RemoveTicker {
+                ticker: Ticker::from("IBM")
+            })
+            .await
+            .unwrap());
+        let entries = fetch_actor_addr.call(ListTickers).await.unwrap();
+
+        fetch_actor_addr.stop(None).unwrap();
+        fetch_actor_addr.wait_for_stop().await;
+        mock_performance_data_actor_addr.stop(None).unwrap();
+        mock_performance_data_actor_addr.wait_for_stop().await;
+
+        assert_eq!(
+            entries,
+            vec![
+                WatchlistEntry {
+                    ticker: Ticker::from("MSFT"),
+                    group: DEFAULT_GROUP.to_owned()
+                },
+                WatchlistEntry {
+                    ticker: Ticker::from("XOM"),
+                    group: "energy".to_owned()
+                },
+            ]
+        );
+        assert_eq!(
+            std::fs::read_to_string(&watchlist).unwrap(),
+            "MSFT\n[energy]\nXOM\n"
+        );
+    }
+
+    struct FxQuoteProvider;
+
+    #[async_trait]
+    impl QuoteProvider for FxQuoteProvider {
+        async fn get_quote_history(
+            &self,
+            ticker: &Ticker,
+            _start: DateTime<Utc>,
+            _end: DateTime<Utc>,
+            _interval: Interval,
+        ) -> std::result::Result<Vec<Bar>, ProviderError> {
+            let closes = match ticker.as_str() {
+                "VOD.L" => vec![100f64, 200f64],
+                "GBPUSD=X" => vec![1.25f64],
+                _ => return Err(ProviderError::NoData(ticker.to_string())),
+            };
+            Ok(closes
+                .into_iter()
+                .enumerate()
+                .map(|(day, price)| Bar {
+                    timestamp: Utc
+                        .with_ymd_and_hms(2021, 1, 4 + day as u32, 0, 0, 0)
+                        .unwrap(),
+                    open: price,
+                    high: price,
+                    low: price,
+                    close: price,
+                    adjclose: price,
+                    volume: 0,
+                })
+                .collect())
+        }
+    }
+
+    #[async_std::test]
+    async fn fetch_actor_converts_prices_to_report_currency() {
+        let buf = Arc::new(Mutex::new(vec![]));
+        let mut mock_performance_data_actor_addr = MockPerformanceDataActor::new(buf.clone())
+            .start()
+            .await
+            .unwrap();
+        let mut fetch_actor_addr = FetchActor::new(
+            mock_performance_data_actor_addr.clone(),
+            FxQuoteProvider,
+            vec![Ticker::from("VOD.L")],
+            Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap(),
+        )
+        .with_report_currency(Some(Currency::USD), SharedMetadata::default())
+        .start()
+        .await
+        .unwrap();
+
+        fetch_actor_addr
+            .call(Fetch::from_datetime(
+                Utc.with_ymd_and_hms(2021, 1, 6, 0, 0, 0).unwrap(),
+            ))
+            .await
+            .unwrap();
+        fetch_actor_addr.stop(None).unwrap();
+        fetch_actor_addr.wait_for_stop().await;
+        mock_performance_data_actor_addr.stop(None).unwrap();
+        mock_performance_data_actor_addr.wait_for_stop().await;
+
+        let sent = buf.lock().unwrap().clone();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].currency(), Currency::USD);
+        assert_eq!(sent[0].performance_data(), &[1.25f64, 2.5f64]);
+        assert_eq!(sent[0].closes(), &[1.25f64, 2.5f64]);
+    }
 }
@@ -1,7 +1,12 @@
 use chrono::prelude::*;
+use lib::calendar::MarketStatus;
+use lib::corporate_actions::{price_return, total_return, CorporateAction};
+use lib::currency::Currency;
 use lib::formatter::{Percentage, Price};
 use lib::performance_indicators::*;
-use lib::ticker::Ticker;
+use lib::quality::QualityFlags;
+use lib::quote_provider::{Bar, Interval};
+use lib::ticker::{Ticker, TickerMetadata};
 use serde::Serialize;
 use xactor::message;
 
@@ -12,6 +17,56 @@ pub struct PerformanceData {
     window: usize,
     performance_data: Vec<f64>,
     to: DateTime<Utc>,
+    market_status: Option<MarketStatus>,
+    closes: Vec<f64>,
+    dividends: Vec<f64>,
+    corporate_actions: Vec<CorporateAction>,
+    timestamps: Vec<DateTime<Utc>>,
+    volumes: Vec<u64>,
+    interval: Interval,
+    quality: QualityFlags,
+    currency: Currency,
+    totals: Option<RunningTotals>,
+}
+
+/// Running values over a whole series, kept bar by bar while backfilling so that each bar can be
+/// sent with only the trailing bars its window reads
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RunningTotals {
+    bars: usize,
+    first: f64,
+    last: f64,
+    min: f64,
+    max: f64,
+    first_close: f64,
+    last_close: f64,
+    /// Growth from the first close with every dividend reinvested
+    growth: f64,
+}
+
+impl RunningTotals {
+    pub fn new(price: f64, close: f64) -> Self {
+        RunningTotals {
+            bars: 1,
+            first: price,
+            last: price,
+            min: price,
+            max: price,
+            first_close: close,
+            last_close: close,
+            growth: 1f64,
+        }
+    }
+
+    /// Adds the next bar, with the dividend going ex on it
+    pub fn push(&mut self, price: f64, close: f64, dividend: f64) {
+        self.bars += 1;
+        self.last = price;
+        self.min = self.min.min(price);
+        self.max = self.max.max(price);
+        self.growth *= (close + dividend) / self.last_close;
+        self.last_close = close;
+    }
 }
 
 impl PerformanceData {
@@ -26,9 +81,68 @@ impl PerformanceData {
             window,
             performance_data,
             to,
+            market_status: None,
+            closes: vec![],
+            dividends: vec![],
+            corporate_actions: vec![],
+            timestamps: vec![],
+            volumes: vec![],
+            interval: Interval::default(),
+            quality: QualityFlags::default(),
+            currency: Currency::default(),
+            totals: None,
         }
     }
 
+    /// The currency the series' prices are in
+    pub fn with_currency(mut self, currency: Currency) -> Self {
+        self.currency = currency;
+        self
+    }
+
+    /// Attaches the time of each bar in the series and the interval between them
+    pub fn with_timestamps(mut self, timestamps: Vec<DateTime<Utc>>, interval: Interval) -> Self {
+        self.timestamps = timestamps;
+        self.interval = interval;
+        self
+    }
+
+    /// Attaches the volume traded in each bar of the series
+    pub fn with_volumes(mut self, volumes: Vec<u64>) -> Self {
+        self.volumes = volumes;
+        self
+    }
+
+    /// Attaches running values over the whole series the performance data is the tail of
+    pub fn with_totals(mut self, totals: Option<RunningTotals>) -> Self {
+        self.totals = totals;
+        self
+    }
+
+    pub fn with_quality(mut self, quality: QualityFlags) -> Self {
+        self.quality = quality;
+        self
+    }
+
+    /// Attaches the unadjusted-for-dividends closes, the dividend going ex on each bar and
+    /// every corporate action in the window, for price and total return
+    pub fn with_corporate_actions(
+        mut self,
+        closes: Vec<f64>,
+        dividends: Vec<f64>,
+        corporate_actions: Vec<CorporateAction>,
+    ) -> Self {
+        self.closes = closes;
+        self.dividends = dividends;
+        self.corporate_actions = corporate_actions;
+        self
+    }
+
+    pub fn with_market_status(mut self, market_status: Option<MarketStatus>) -> Self {
+        self.market_status = market_status;
+        self
+    }
+
     pub fn ticker(&self) -> &Ticker {
         &self.ticker
     }
@@ -44,6 +158,46 @@ impl PerformanceData {
     pub fn to(&self) -> DateTime<Utc> {
         self.to
     }
+
+    pub fn market_status(&self) -> Option<MarketStatus> {
+        self.market_status
+    }
+
+    pub fn closes(&self) -> &[f64] {
+        &self.closes
+    }
+
+    pub fn dividends(&self) -> &[f64] {
+        &self.dividends
+    }
+
+    pub fn corporate_actions(&self) -> &[CorporateAction] {
+        &self.corporate_actions
+    }
+
+    pub fn timestamps(&self) -> &[DateTime<Utc>] {
+        &self.timestamps
+    }
+
+    pub fn volumes(&self) -> &[u64] {
+        &self.volumes
+    }
+
+    pub fn interval(&self) -> Interval {
+        self.interval
+    }
+
+    pub fn quality(&self) -> &QualityFlags {
+        &self.quality
+    }
+
+    pub fn currency(&self) -> Currency {
+        self.currency
+    }
+
+    pub fn totals(&self) -> Option<&RunningTotals> {
+        self.totals.as_ref()
+    }
 }
 
 #[message]
@@ -57,6 +211,19 @@ pub struct PerformanceIndicators {
     n_window_sma: Option<Price>,
     percentage_change: Option<Percentage>,
     abs_change: Option<Price>,
+    /// The currency of every price, which raw prices don't carry themselves
+    price_currency: Currency,
+    market_status: Option<MarketStatus>,
+    price_return: Option<Percentage>,
+    total_return: Option<Percentage>,
+    split_in_window: bool,
+    ex_dividend_in_window: bool,
+    quality: QualityFlags,
+    name: Option<String>,
+    exchange: Option<String>,
+    currency: Option<String>,
+    sector: Option<String>,
+    industry: Option<String>,
 }
 
 impl PerformanceIndicators {
@@ -67,23 +234,126 @@ impl PerformanceIndicators {
         time: DateTime<Utc>,
     ) -> PerformanceIndicators {
         let (percentage_change, abs_change) = match price_diff(series) {
-            Some((percentage_change, abs_change)) => {
-                (Some(Percentage(percentage_change)), Some(Price(abs_change)))
-            }
+            Some((percentage_change, abs_change)) => (
+                Some(Percentage(percentage_change)),
+                Some(Price::usd(abs_change)),
+            ),
             None => (None, None),
         };
 
         PerformanceIndicators {
             ticker,
             time,
-            current_price: series.last().map(|x| Price(*x)),
-            min: min(series).map(Price),
-            max: max(series).map(Price),
+            current_price: series.last().copied().map(Price::usd),
+            min: min(series).map(Price::usd),
+            max: max(series).map(Price::usd),
             n_window_sma: n_window_sma(window, series)
-                .and_then(|vec| vec.into_iter().map(Price).last()),
+                .and_then(|vec| vec.last().copied().map(Price::usd)),
             percentage_change,
             abs_change,
+            price_currency: Currency::USD,
+            market_status: None,
+            price_return: None,
+            total_return: None,
+            split_in_window: false,
+            ex_dividend_in_window: false,
+            quality: QualityFlags::default(),
+            name: None,
+            exchange: None,
+            currency: None,
+            sector: None,
+            industry: None,
+        }
+    }
+
+    pub fn with_market_status(mut self, market_status: Option<MarketStatus>) -> Self {
+        self.market_status = market_status;
+        self
+    }
+
+    /// Labels every price with `currency`, which the series was already converted to
+    pub fn with_currency(mut self, currency: Currency) -> Self {
+        for price in [
+            &mut self.current_price,
+            &mut self.min,
+            &mut self.max,
+            &mut self.n_window_sma,
+            &mut self.abs_change,
+        ]
+        .iter_mut()
+        .flat_map(|price| price.iter_mut())
+        {
+            price.1 = currency;
         }
+        self.price_currency = currency;
+        self
+    }
+
+    pub fn with_corporate_actions(
+        mut self,
+        closes: &[f64],
+        dividends: &[f64],
+        corporate_actions: &[CorporateAction],
+    ) -> Self {
+        self.price_return = price_return(closes).map(Percentage);
+        self.total_return = total_return(closes, dividends).map(Percentage);
+        self.split_in_window = corporate_actions.iter().any(CorporateAction::is_split);
+        self.ex_dividend_in_window = corporate_actions.iter().any(CorporateAction::is_dividend);
+        self
+    }
+
+    /// Extends the indicators of the tail of a series to the whole of it, given running totals
+    /// over it. Prices are in the currency already set.
+    pub fn with_totals(mut self, totals: Option<&RunningTotals>) -> Self {
+        if let Some(totals) = totals {
+            let currency = self.price_currency;
+            self.min = Some(Price(totals.min, currency));
+            self.max = Some(Price(totals.max, currency));
+            if totals.bars >= 2 {
+                if let Some((percentage_change, abs_change)) =
+                    price_diff(&[totals.first, totals.last])
+                {
+                    self.percentage_change = Some(Percentage(percentage_change));
+                    self.abs_change = Some(Price(abs_change, currency));
+                }
+                self.price_return =
+                    price_return(&[totals.first_close, totals.last_close]).map(Percentage);
+                self.total_return = Some(Percentage((totals.growth - 1f64) * 100f64));
+            }
+        }
+        self
+    }
+
+    pub fn with_quality(mut self, quality: QualityFlags) -> Self {
+        self.quality = quality;
+        self
+    }
+
+    pub fn with_metadata(mut self, metadata: Option<&TickerMetadata>) -> Self {
+        if let Some(metadata) = metadata {
+            self.name = metadata.name.clone();
+            self.exchange = metadata.exchange.clone();
+            self.currency = metadata.currency.clone();
+            self.sector = metadata.sector.clone();
+            self.industry = metadata.industry.clone();
+        }
+        self
+    }
+
+    pub fn ticker(&self) -> &Ticker {
+        &self.ticker
+    }
+
+    pub fn time(&self) -> DateTime<Utc> {
+        self.time
+    }
+
+    pub fn percentage_change(&self) -> Option<Percentage> {
+        self.percentage_change
+    }
+
+    pub fn sector(&self) -> Option<&str> {
+        self.sector.as_deref()
     }
 }
 
@@ -112,29 +382,183 @@ impl Default for Fetch {
     }
 }
 
+/// Fetches every group up to `until` and sends the performance data as of each bar, rather than
+/// only the latest, to backfill the output
+#[message]
+#[derive(Clone)]
+pub struct Backfill {
+    until: DateTime<Utc>,
+}
+
+impl Backfill {
+    pub fn new(until: DateTime<Utc>) -> Self {
+        Backfill { until }
+    }
+
+    pub fn until(&self) -> DateTime<Utc> {
+        self.until
+    }
+}
+
+/// The bars of a ticker's latest fetch, as the provider returned them, published for stores of
+/// raw history
+#[message]
+#[derive(Clone, PartialEq, Debug)]
+pub struct Bars {
+    ticker: Ticker,
+    interval: Interval,
+    bars: Vec<Bar>,
+}
+
+impl Bars {
+    pub fn new(ticker: Ticker, interval: Interval, bars: Vec<Bar>) -> Self {
+        Bars {
+            ticker,
+            interval,
+            bars,
+        }
+    }
+
+    pub fn ticker(&self) -> &Ticker {
+        &self.ticker
+    }
+
+    pub fn interval(&self) -> Interval {
+        self.interval
+    }
+
+    pub fn bars(&self) -> &[Bar] {
+        &self.bars
+    }
+}
+
+/// Handled after every message sent to an actor before it, so calling it waits for those to be
+/// handled. The fetch actor also stops polling, and output actors flush.
+#[message]
+#[derive(Clone)]
+pub struct Drain;
+
+/// Adds a ticker to a group, creating the group if needed. Returns false if already watched.
+#[message(result = "bool")]
+pub struct AddTicker {
+    pub ticker: Ticker,
+    pub group: Option<String>,
+}
+
+/// Stops watching a ticker. Returns false if it wasn't watched.
+#[message(result = "bool")]
+pub struct RemoveTicker {
+    pub ticker: Ticker,
+}
+
+#[message(result = "Vec<WatchlistEntry>")]
+pub struct ListTickers;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WatchlistEntry {
+    pub ticker: Ticker,
+    pub group: String,
+}
+
 #[cfg(test)]
 mod test {
     use super::PerformanceIndicators;
     use chrono::Utc;
-    use lib::formatter::{Percentage, Price};
-    use lib::ticker::Ticker;
+    use lib::corporate_actions::CorporateAction;
+    use lib::currency::Currency;
+    use lib::formatter::{with_number_format, NumberFormat, Percentage, Price};
+    use lib::quality::QualityFlags;
+    use lib::ticker::{Ticker, TickerMetadata};
     #[test]
     fn performance_indicators_constructor_has_correct_metrics() {
         let series = [15f64, 13f64, 2f64, 7.5f64];
         let time = Utc::now();
         let expected = PerformanceIndicators {
             ticker: Ticker::from("TEST"),
-            current_price: Some(Price(7.5f64)),
+            current_price: Some(Price::usd(7.5f64)),
             time,
-            min: Some(Price(2f64)),
-            max: Some(Price(15f64)),
-            n_window_sma: Some(Price(4.75f64)),
+            min: Some(Price::usd(2f64)),
+            max: Some(Price::usd(15f64)),
+            n_window_sma: Some(Price::usd(4.75f64)),
             percentage_change: Some(Percentage(50f64)),
-            abs_change: Some(Price(-7.5f64)),
+            abs_change: Some(Price::usd(-7.5f64)),
+            price_currency: Currency::USD,
+            market_status: None,
+            price_return: None,
+            total_return: None,
+            split_in_window: false,
+            ex_dividend_in_window: false,
+            quality: QualityFlags::default(),
+            name: None,
+            exchange: None,
+            currency: None,
+            sector: None,
+            industry: None,
         };
         assert_eq!(
             PerformanceIndicators::new(2, &series, Ticker::from("TEST"), time),
             expected
         );
     }
+
+    #[test]
+    fn performance_indicators_flags_corporate_actions_in_window() {
+        let time = Utc::now();
+        let indicators = PerformanceIndicators::new(2, &[10f64, 11f64], Ticker::from("TEST"), time)
+            .with_corporate_actions(
+                &[10f64, 10f64, 12f64],
+                &[0f64, 1f64, 0f64],
+                &[CorporateAction::Dividend {
+                    date: time,
+                    amount: 1f64,
+                }],
+            );
+        let price_return = indicators.price_return.unwrap().0;
+        assert!((price_return - 20f64).abs() < 1e-9);
+        assert!(indicators.total_return.unwrap().0 > price_return);
+        assert!(indicators.ex_dividend_in_window);
+        assert!(!indicators.split_in_window);
+    }
+
+    #[test]
+    fn performance_indicators_joins_metadata_when_present() {
+        let time = Utc::now();
+        let metadata = TickerMetadata {
+            name: Some("Microsoft Corp".to_owned()),
+            sector: Some("Information Technology".to_owned()),
+            ..TickerMetadata::default()
+        };
+        let indicators = PerformanceIndicators::new(2, &[10f64], Ticker::from("MSFT"), time)
+            .with_metadata(Some(&metadata));
+        assert_eq!(indicators.name.as_deref(), Some("Microsoft Corp"));
+        assert_eq!(indicators.sector(), Some("Information Technology"));
+        assert_eq!(indicators.exchange, None);
+
+        let indicators =
+            PerformanceIndicators::new(2, &[10f64], Ticker::from("MSFT"), time).with_metadata(None);
+        assert_eq!(indicators.sector(), None);
+    }
+
+    #[test]
+    fn performance_indicators_labels_prices_with_currency() {
+        let indicators =
+            PerformanceIndicators::new(2, &[10f64, 12f64], Ticker::from("VOD.L"), Utc::now())
+                .with_currency(Currency::GBX);
+        assert_eq!(
+            indicators.current_price.map(|price| price.to_string()),
+            Some("12.00p".to_owned())
+        );
+        assert_eq!(
+            indicators.abs_change.map(|price| price.to_string()),
+            Some("2.00p".to_owned())
+        );
+
+        let formatted = serde_json::to_value(&indicators).unwrap();
+        assert_eq!(formatted["current_price"], "12.00p");
+        assert_eq!(formatted["price_currency"], "GBX");
+        let raw =
+            with_number_format(NumberFormat::Raw, || serde_json::to_value(&indicators)).unwrap();
+        assert_eq!(raw["current_price"], 12f64);
+        assert_eq!(raw["price_currency"], "GBX");
+    }
 }
@@ -1,9 +1,14 @@
+use crate::actors::messages::Drain;
+use crate::failures::{FailureSource, SharedFailures};
+use crate::schedule::Schedule;
+use crate::sinks::Sink;
 use anyhow::Result;
 use async_trait::async_trait;
+use chrono::Utc;
+use lib::formatter::{with_number_format, NumberFormat};
+use lib::ticker::Ticker;
 use log::error;
 use serde::Serialize;
-use std::io::Write;
-use std::marker::PhantomData;
 use std::time::Duration;
 use xactor::{message, Actor, Context, Handler, Message};
 
@@ -11,60 +16,135 @@ use xactor::{message, Actor, Context, Handler, Message};
 #[derive(Clone)]
 struct Flush;
 
-pub struct OutputActor<W: Write, T: Serialize + Message<Result = ()>> {
-    csv_writer: csv::Writer<W>,
-    _phantom: PhantomData<T>,
+/// Flushes and schedules the next flush
+#[message]
+#[derive(Clone)]
+struct ScheduledFlush;
+
+/// Extracts the ticker a message is about, for failure tracking
+type TickerOf<T> = fn(&T) -> Ticker;
+
+pub struct OutputActor<T: Serialize + Message<Result = ()>> {
+    sink: Box<dyn Sink<T>>,
+    flush_schedule: Schedule,
+    failures: Option<(SharedFailures, TickerOf<T>)>,
+    number_format: NumberFormat,
 }
 
-impl<W: Write, T: Serialize + Message<Result = ()>> OutputActor<W, T> {
-    pub fn new(writer: W) -> Self {
+impl<T: Serialize + Message<Result = ()>> OutputActor<T> {
+    pub fn new(sink: Box<dyn Sink<T>>) -> Self {
         OutputActor {
-            csv_writer: csv::Writer::from_writer(writer),
-            _phantom: PhantomData,
+            sink,
+            flush_schedule: Schedule::every(chrono::Duration::seconds(15)),
+            failures: None,
+            number_format: NumberFormat::default(),
+        }
+    }
+
+    pub fn with_flush_schedule(mut self, flush_schedule: Schedule) -> Self {
+        self.flush_schedule = flush_schedule;
+        self
+    }
+
+    /// Writes prices and percentages as plain numbers with `NumberFormat::Raw`
+    pub fn with_number_format(mut self, number_format: NumberFormat) -> Self {
+        self.number_format = number_format;
+        self
+    }
+
+    /// Records messages that fail to serialize against the ticker returned by `ticker_of`
+    pub fn with_failures(mut self, failures: SharedFailures, ticker_of: TickerOf<T>) -> Self {
+        self.failures = Some((failures, ticker_of));
+        self
+    }
+
+    fn flush(&mut self) {
+        if let Err(e) = self.sink.flush() {
+            error!("Failed to flush writer: {:?}", e);
+        }
+    }
+
+    fn finish(&mut self) {
+        if let Err(e) = self.sink.finish() {
+            error!("Failed to finish output: {:?}", e);
+        }
+    }
+}
+
+impl<T: Serialize + Message<Result = ()> + Send + 'static> OutputActor<T> {
+    fn schedule_flush(&self, ctx: &mut Context<Self>) {
+        if let Some(delay) = self.flush_schedule.delay_after(Utc::now()) {
+            ctx.send_later(ScheduledFlush, delay);
         }
     }
 }
 
 #[async_trait]
-impl<W: Write + Send + 'static, T: Serialize + Message<Result = ()> + Send + 'static> Actor
-    for OutputActor<W, T>
-{
+impl<T: Serialize + Message<Result = ()> + Send + 'static> Actor for OutputActor<T> {
     async fn started(&mut self, ctx: &mut Context<Self>) -> Result<()> {
-        ctx.send_interval(Flush, Duration::from_secs(15));
+        self.schedule_flush(ctx);
         ctx.subscribe::<T>().await?;
         Ok(())
     }
+
+    async fn stopped(&mut self, _ctx: &mut Context<Self>) {
+        self.finish();
+    }
 }
 
 #[async_trait]
-impl<W: Write + Send + 'static, T: Serialize + Message<Result = ()> + Send + 'static> Handler<Flush>
-    for OutputActor<W, T>
-{
+impl<T: Serialize + Message<Result = ()> + Send + 'static> Handler<Flush> for OutputActor<T> {
     async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: Flush) -> () {
-        if let Err(e) = self.csv_writer.flush() {
-            error!("Failed to flush writer: {:?}", e);
-        }
+        self.flush();
+    }
+}
+
+#[async_trait]
+impl<T: Serialize + Message<Result = ()> + Send + 'static> Handler<Drain> for OutputActor<T> {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: Drain) -> () {
+        self.finish();
     }
 }
 
 #[async_trait]
-impl<W: Write + Send + 'static, T: Serialize + Send + Message<Result = ()> + 'static> Handler<T>
-    for OutputActor<W, T>
+impl<T: Serialize + Message<Result = ()> + Send + 'static> Handler<ScheduledFlush>
+    for OutputActor<T>
 {
+    async fn handle(&mut self, ctx: &mut Context<Self>, _msg: ScheduledFlush) -> () {
+        self.flush();
+        self.schedule_flush(ctx);
+    }
+}
+
+#[async_trait]
+impl<T: Serialize + Send + Message<Result = ()> + 'static> Handler<T> for OutputActor<T> {
     async fn handle(&mut self, ctx: &mut Context<Self>, msg: T) -> () {
-        if let Err(e) = self.csv_writer.serialize(&msg) {
-            error!(
-                "Failed to serialize data for msg: {:?}. Retrying in 5 seconds",
-                e
-            );
-            ctx.send_later(msg, Duration::from_secs(5));
-        };
+        let sink = &mut self.sink;
+        match with_number_format(self.number_format, || sink.write(&msg)) {
+            Ok(()) => {
+                if let Some((failures, ticker_of)) = &self.failures {
+                    failures.record_success(&ticker_of(&msg), FailureSource::Output);
+                }
+            }
+            Err(e) => {
+                error!(
+                    "Failed to serialize data for msg: {:?}. Retrying in 5 seconds",
+                    e
+                );
+                if let Some((failures, ticker_of)) = &self.failures {
+                    failures.record_failure(&ticker_of(&msg), FailureSource::Output, e, Utc::now());
+                }
+                ctx.send_later(msg, Duration::from_secs(5));
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Flush, OutputActor};
+    use super::{Drain, Flush, OutputActor};
+    use crate::sinks::{CsvSink, JsonSink};
+    use lib::formatter::{NumberFormat, Percentage, Price};
     use serde::Serialize;
     use std::io::{Result, Write};
     use std::sync::{Arc, Mutex};
@@ -107,7 +187,7 @@ mod tests {
         let buffer = Arc::new(Mutex::new(vec![]));
         let flush = Arc::new(Mutex::new(0));
         let mock_writer: MockWriter = MockWriter::new(buffer.clone(), flush);
-        let output_actor = OutputActor::new(mock_writer);
+        let output_actor = OutputActor::new(Box::new(CsvSink::new(mock_writer)));
 
         let mock_serializable = MockSerializable {
             test: "test".to_owned(),
@@ -129,7 +209,8 @@ mod tests {
         let buffer = Arc::new(Mutex::new(vec![]));
         let flush = Arc::new(Mutex::new(0));
         let mock_writer: MockWriter = MockWriter::new(buffer.clone(), flush.clone());
-        let output_actor: OutputActor<_, MockSerializable> = OutputActor::new(mock_writer);
+        let output_actor: OutputActor<MockSerializable> =
+            OutputActor::new(Box::new(CsvSink::new(mock_writer)));
 
         let mut addr = output_actor.start().await.unwrap();
         addr.call(Flush).await.unwrap();
@@ -144,7 +225,7 @@ mod tests {
         let buffer = Arc::new(Mutex::new(vec![]));
         let flush = Arc::new(Mutex::new(0));
         let mock_writer: MockWriter = MockWriter::new(buffer.clone(), flush);
-        let output_actor = OutputActor::new(mock_writer);
+        let output_actor = OutputActor::new(Box::new(CsvSink::new(mock_writer)));
 
         let mock_serializable_one = MockSerializable {
             test: "test".to_owned(),
@@ -166,4 +247,56 @@ mod tests {
             .unwrap()
             .starts_with(b"test,other_test\ntest,12\ntest2,13"));
     }
+
+    #[message]
+    #[derive(Serialize)]
+    struct MockPriced {
+        price: Price,
+        change: Percentage,
+    }
+
+    #[async_std::test]
+    async fn output_actor_writes_plain_numbers_in_raw_number_format() {
+        let buffer = Arc::new(Mutex::new(vec![]));
+        let flush = Arc::new(Mutex::new(0));
+        let mock_writer: MockWriter = MockWriter::new(buffer.clone(), flush);
+        let output_actor = OutputActor::new(Box::new(CsvSink::new(mock_writer)))
+            .with_number_format(NumberFormat::Raw);
+
+        let mut addr = output_actor.start().await.unwrap();
+        addr.call(MockPriced {
+            price: Price::usd(405.27),
+            change: Percentage(172.41),
+        })
+        .await
+        .unwrap();
+        addr.stop(None).unwrap();
+        addr.wait_for_stop().await;
+        assert!(buffer
+            .lock()
+            .unwrap()
+            .starts_with(b"price,change\n405.27,172.41\n"));
+    }
+
+    #[async_std::test]
+    async fn output_actor_closes_json_array_on_drain() {
+        let buffer = Arc::new(Mutex::new(vec![]));
+        let flush = Arc::new(Mutex::new(0));
+        let mock_writer: MockWriter = MockWriter::new(buffer.clone(), flush);
+        let output_actor = OutputActor::new(Box::new(JsonSink::new(mock_writer)));
+
+        let addr = output_actor.start().await.unwrap();
+        addr.call(MockSerializable {
+            test: "test".to_owned(),
+            other_test: 12,
+        })
+        .await
+        .unwrap();
+        addr.call(Drain).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buffer.lock().unwrap()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([{"test": "test", "other_test": 12}])
+        );
+    }
 }
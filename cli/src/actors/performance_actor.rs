@@ -1,20 +1,36 @@
 // use crate::actors::output_actor::Output;
-use crate::actors::messages::{PerformanceData, PerformanceIndicators};
+use crate::actors::messages::{Drain, PerformanceData, PerformanceIndicators};
+use crate::metadata::SharedMetadata;
 use async_trait::async_trait;
 use log::error;
 use xactor::{Actor, Addr, Broker, Context, Handler};
 
 pub struct PerformanceActor {
     addr: Addr<Broker<PerformanceIndicators>>,
+    metadata: SharedMetadata,
 }
 
 impl PerformanceActor {
     pub fn new(addr: Addr<Broker<PerformanceIndicators>>) -> Self {
-        PerformanceActor { addr }
+        PerformanceActor {
+            addr,
+            metadata: SharedMetadata::default(),
+        }
+    }
+
+    /// Joins reference data about each ticker into its indicators
+    pub fn with_metadata(mut self, metadata: SharedMetadata) -> Self {
+        self.metadata = metadata;
+        self
     }
 }
 impl Actor for PerformanceActor {}
 
+#[async_trait]
+impl Handler<Drain> for PerformanceActor {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: Drain) -> () {}
+}
+
 #[async_trait]
 impl Handler<PerformanceData> for PerformanceActor {
     async fn handle(&mut self, _ctx: &mut Context<Self>, msg: PerformanceData) -> () {
@@ -23,7 +39,13 @@ impl Handler<PerformanceData> for PerformanceActor {
             msg.performance_data(),
             msg.ticker().clone(),
             msg.to(),
-        );
+        )
+        .with_market_status(msg.market_status())
+        .with_currency(msg.currency())
+        .with_corporate_actions(msg.closes(), msg.dividends(), msg.corporate_actions())
+        .with_totals(msg.totals())
+        .with_quality(msg.quality().clone())
+        .with_metadata(self.metadata.get(msg.ticker()));
         if let Err(e) = self.addr.publish(performance_indicators) {
             error!("Failed to send performance indicators: {:?}", e);
         }
@@ -33,7 +55,6 @@ impl Handler<PerformanceData> for PerformanceActor {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use async_std;
     use async_trait::async_trait;
     use chrono::Utc;
     use lib::ticker::Ticker;
@@ -89,10 +110,18 @@ mod tests {
 
         addr.stop(None).unwrap();
         addr.wait_for_stop().await;
+        // The broker forwards published messages from its own mailbox, so the subscriber can still
+        // be waiting for them after the call has returned
+        for _ in 0..100 {
+            if !buffer.lock().unwrap().is_empty() {
+                break;
+            }
+            async_std::task::sleep(std::time::Duration::from_millis(10)).await;
+        }
         mock_actor_addr.stop(None).unwrap();
 
         mock_actor_addr.wait_for_stop().await;
         let received_messages = buffer.lock().unwrap().clone();
-        assert_eq!(received_messages.into_iter().nth(0).unwrap(), expected);
+        assert_eq!(received_messages.into_iter().next().unwrap(), expected);
     }
 }
@@ -0,0 +1,376 @@
+use crate::actors::messages::{Drain, PerformanceData};
+use crate::metrics::SharedMetrics;
+use async_trait::async_trait;
+use chrono::prelude::*;
+use chrono::Duration;
+use lib::calendar::MarketStatus;
+use lib::quality::{
+    in_observed_session, is_gapped, is_outlier, missed_bars, QualityFlag, QualityFlags,
+};
+use lib::ticker::Ticker;
+use log::{error, warn};
+use std::collections::HashMap;
+use xactor::{Actor, Addr, Context, Handler};
+
+/// Fewest earlier returns needed before a return can be judged an outlier
+const MIN_RETURNS_FOR_OUTLIERS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualityThresholds {
+    /// Intervals of market hours without the latest bar changing before a series is stale
+    pub stale_after: usize,
+    /// Standard deviations from the mean return before the latest return is an outlier
+    pub outlier_threshold: f64,
+    /// Intervals between consecutive bars before a series is gapped
+    pub max_gap_bars: i32,
+}
+
+impl Default for QualityThresholds {
+    fn default() -> Self {
+        QualityThresholds {
+            stale_after: 3,
+            outlier_threshold: 4f64,
+            max_gap_bars: 5,
+        }
+    }
+}
+
+/// The latest bar of a series, and how long the market has been open since it last changed
+struct LatestBar {
+    timestamp: DateTime<Utc>,
+    close: f64,
+    volume: Option<u64>,
+    seen: DateTime<Utc>,
+    open: bool,
+    unchanged_for: Duration,
+}
+
+/// Sits between `FetchActor` and `PerformanceActor`, annotating each series with quality flags
+pub struct QualityActor<H: Handler<PerformanceData>> {
+    sender: Addr<H>,
+    thresholds: QualityThresholds,
+    metrics: SharedMetrics,
+    latest_bars: HashMap<Ticker, LatestBar>,
+}
+
+impl<H: Handler<PerformanceData>> QualityActor<H> {
+    pub fn new(sender: Addr<H>, thresholds: QualityThresholds, metrics: SharedMetrics) -> Self {
+        QualityActor {
+            sender,
+            thresholds,
+            metrics,
+            latest_bars: HashMap::new(),
+        }
+    }
+
+    /// Whether the latest bar, its close and its volume, haven't changed for `stale_after`
+    /// intervals. Intraday series only count time the market was open, taken from the series'
+    /// own trading days and hours when there's no calendar. Daily and weekly series count the
+    /// bars missed since the latest one instead.
+    fn is_stale(&mut self, msg: &PerformanceData) -> bool {
+        let (timestamp, close) = match (msg.timestamps().last(), msg.performance_data().last()) {
+            (Some(timestamp), Some(close)) => (*timestamp, *close),
+            _ => return false,
+        };
+        let volume = msg.volumes().last().copied();
+        let at = msg.to();
+        let open = match msg.market_status() {
+            Some(status) => status == MarketStatus::Open,
+            None => in_observed_session(msg.timestamps(), msg.interval(), at),
+        };
+        let latest = match self.latest_bars.get_mut(msg.ticker()) {
+            Some(latest)
+                if latest.timestamp == timestamp
+                    && latest.close == close
+                    && latest.volume == volume =>
+            {
+                latest
+            }
+            _ => {
+                self.latest_bars.insert(
+                    msg.ticker().clone(),
+                    LatestBar {
+                        timestamp,
+                        close,
+                        volume,
+                        seen: at,
+                        open,
+                        unchanged_for: Duration::zero(),
+                    },
+                );
+                return false;
+            }
+        };
+        if latest.open && open && at > latest.seen {
+            latest.unchanged_for += at - latest.seen;
+        }
+        latest.seen = at;
+        latest.open = open;
+        let stale_after = self.thresholds.stale_after as i32;
+        if msg.interval().duration() >= Duration::days(1) {
+            missed_bars(timestamp, at, msg.interval()) >= i64::from(stale_after)
+        } else {
+            latest.unchanged_for >= msg.interval().duration() * stale_after
+        }
+    }
+
+    fn check(&mut self, msg: &PerformanceData) -> Vec<QualityFlag> {
+        let mut flags = vec![];
+        if self.is_stale(msg) {
+            flags.push(QualityFlag::Stale);
+        }
+        // Gaps older than the window don't affect its indicators
+        let timestamps = msg.timestamps();
+        let recent = &timestamps[timestamps.len().saturating_sub(msg.window() + 1)..];
+        if is_gapped(recent, msg.interval(), self.thresholds.max_gap_bars) {
+            flags.push(QualityFlag::Gapped);
+        }
+        if is_outlier(
+            msg.performance_data(),
+            self.thresholds.outlier_threshold,
+            MIN_RETURNS_FOR_OUTLIERS,
+        ) {
+            flags.push(QualityFlag::Outlier);
+        }
+        flags
+    }
+}
+
+impl<H: Handler<PerformanceData>> Actor for QualityActor<H> {}
+
+#[async_trait]
+impl<H: Handler<PerformanceData>> Handler<Drain> for QualityActor<H> {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: Drain) -> () {}
+}
+
+#[async_trait]
+impl<H: Handler<PerformanceData>> Handler<PerformanceData> for QualityActor<H> {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, msg: PerformanceData) -> () {
+        let flags = self.check(&msg);
+        self.metrics.record_quality(&flags);
+        if !flags.is_empty() {
+            warn!("Quality issues for {:?}: {:?}", msg.ticker(), flags);
+        }
+        if let Err(e) = self.sender.send(msg.with_quality(QualityFlags(flags))) {
+            error!("Failed to send quotes to actor: {:?}", e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::Metrics;
+    use lib::quote_provider::Interval;
+    use std::sync::{Arc, Mutex};
+
+    struct MockPerformanceDataActor {
+        buf: Arc<Mutex<Vec<PerformanceData>>>,
+    }
+
+    impl Actor for MockPerformanceDataActor {}
+
+    #[async_trait]
+    impl Handler<PerformanceData> for MockPerformanceDataActor {
+        async fn handle(&mut self, _ctx: &mut Context<Self>, msg: PerformanceData) -> () {
+            self.buf.lock().unwrap().push(msg);
+        }
+    }
+
+    fn performance_data(last_bar: DateTime<Utc>, series: Vec<f64>) -> PerformanceData {
+        bars(last_bar, Interval::OneDay, series, last_bar)
+    }
+
+    /// A series of `interval` bars ending at `last_bar`, as fetched at `to`
+    fn bars(
+        last_bar: DateTime<Utc>,
+        interval: Interval,
+        series: Vec<f64>,
+        to: DateTime<Utc>,
+    ) -> PerformanceData {
+        let timestamps = (0..series.len())
+            .rev()
+            .map(|i| last_bar - interval.duration() * i as i32)
+            .collect();
+        PerformanceData::new(Ticker::from("TEST"), 30, series, to)
+            .with_timestamps(timestamps, interval)
+    }
+
+    async fn flags_for(
+        messages: Vec<PerformanceData>,
+        metrics: SharedMetrics,
+    ) -> Vec<QualityFlags> {
+        let buf = Arc::new(Mutex::new(vec![]));
+        let mut mock_addr = MockPerformanceDataActor { buf: buf.clone() }
+            .start()
+            .await
+            .unwrap();
+        let mut addr = QualityActor::new(mock_addr.clone(), QualityThresholds::default(), metrics)
+            .start()
+            .await
+            .unwrap();
+        for msg in messages {
+            addr.call(msg).await.unwrap();
+        }
+        addr.stop(None).unwrap();
+        addr.wait_for_stop().await;
+        mock_addr.stop(None).unwrap();
+        mock_addr.wait_for_stop().await;
+        let received = buf.lock().unwrap().clone();
+        received.iter().map(|msg| msg.quality().clone()).collect()
+    }
+
+    fn stale(flags: &[QualityFlags]) -> Vec<bool> {
+        flags
+            .iter()
+            .map(|flags| flags.0.contains(&QualityFlag::Stale))
+            .collect()
+    }
+
+    #[async_std::test]
+    async fn quality_actor_flags_series_that_stop_advancing() {
+        let last_bar = Utc.with_ymd_and_hms(2021, 8, 10, 14, 0, 0).unwrap();
+        let metrics = Arc::new(Metrics::default());
+        let flags = flags_for(
+            (0..5)
+                .map(|i| {
+                    bars(
+                        last_bar,
+                        Interval::OneMinute,
+                        vec![1f64, 2f64],
+                        last_bar + Duration::minutes(i),
+                    )
+                    .with_market_status(Some(MarketStatus::Open))
+                })
+                .collect(),
+            metrics.clone(),
+        )
+        .await;
+        assert_eq!(
+            flags,
+            vec![
+                QualityFlags::default(),
+                QualityFlags::default(),
+                QualityFlags::default(),
+                QualityFlags(vec![QualityFlag::Stale]),
+                QualityFlags(vec![QualityFlag::Stale])
+            ]
+        );
+        assert_eq!(metrics.snapshot(), {
+            let expected = Metrics::default();
+            expected.record_quality(&[]);
+            expected.record_quality(&[]);
+            expected.record_quality(&[]);
+            expected.record_quality(&[QualityFlag::Stale]);
+            expected.record_quality(&[QualityFlag::Stale]);
+            expected.snapshot()
+        });
+    }
+
+    #[async_std::test]
+    async fn quality_actor_does_not_flag_a_bar_that_is_still_updating() {
+        let last_bar = Utc.with_ymd_and_hms(2021, 8, 10, 14, 0, 0).unwrap();
+        let flags = flags_for(
+            (0..5)
+                .map(|i| {
+                    bars(
+                        last_bar,
+                        Interval::OneMinute,
+                        vec![1f64, 2f64],
+                        last_bar + Duration::minutes(i),
+                    )
+                    .with_market_status(Some(MarketStatus::Open))
+                    .with_volumes(vec![100, 100 + i as u64])
+                })
+                .collect(),
+            Arc::new(Metrics::default()),
+        )
+        .await;
+        assert_eq!(stale(&flags), vec![false; 5]);
+    }
+
+    #[async_std::test]
+    async fn quality_actor_does_not_flag_series_outside_their_trading_hours() {
+        // A day of minute bars up to 20:00 on a Tuesday, fetched through the night without a
+        // calendar and then on the Wednesday morning before any new bar
+        let last_bar = Utc.with_ymd_and_hms(2021, 8, 10, 19, 59, 0).unwrap();
+        let series: Vec<f64> = (0..390).map(|i| 100f64 + (i % 2) as f64).collect();
+        let night = (1..10).map(|i| last_bar + Duration::hours(i));
+        let flags = flags_for(
+            night
+                .map(|to| bars(last_bar, Interval::OneMinute, series.clone(), to))
+                .collect(),
+            Arc::new(Metrics::default()),
+        )
+        .await;
+        assert_eq!(stale(&flags), vec![false; 9]);
+
+        let morning = Utc.with_ymd_and_hms(2021, 8, 11, 14, 0, 0).unwrap();
+        let flags = flags_for(
+            (0..5)
+                .map(|i| {
+                    let to = morning + Duration::minutes(i);
+                    bars(last_bar, Interval::OneMinute, series.clone(), to)
+                })
+                .collect(),
+            Arc::new(Metrics::default()),
+        )
+        .await;
+        assert_eq!(stale(&flags), vec![false, false, false, true, true]);
+    }
+
+    #[async_std::test]
+    async fn quality_actor_flags_daily_series_by_missed_bars() {
+        // Friday's bar, fetched over the weekend and then through the next week
+        let last_bar = Utc.with_ymd_and_hms(2021, 8, 13, 0, 0, 0).unwrap();
+        let flags = flags_for(
+            (1..=6)
+                .map(|day| {
+                    let to = last_bar + Duration::days(day) + Duration::hours(15);
+                    bars(last_bar, Interval::OneDay, vec![1f64, 2f64], to)
+                })
+                .collect(),
+            Arc::new(Metrics::default()),
+        )
+        .await;
+        assert_eq!(stale(&flags), vec![false, false, false, false, false, true]);
+    }
+
+    #[async_std::test]
+    async fn quality_actor_only_flags_gaps_within_the_window() {
+        let last_bar = Utc.with_ymd_and_hms(2021, 8, 10, 0, 0, 0).unwrap();
+        let gapped = |window_bars: usize| {
+            let mut timestamps = vec![last_bar - Duration::days(100)];
+            timestamps.extend(
+                (0..window_bars as i64)
+                    .rev()
+                    .map(|i| last_bar - Duration::days(i)),
+            );
+            let series = vec![1f64; timestamps.len()];
+            PerformanceData::new(Ticker::from("TEST"), 30, series, last_bar)
+                .with_timestamps(timestamps, Interval::OneDay)
+        };
+        let flags = flags_for(vec![gapped(30), gapped(31)], Arc::new(Metrics::default())).await;
+        assert_eq!(
+            flags,
+            vec![
+                QualityFlags(vec![QualityFlag::Gapped]),
+                QualityFlags::default()
+            ]
+        );
+    }
+
+    #[async_std::test]
+    async fn quality_actor_passes_healthy_series_through_unflagged() {
+        let last_bar = Utc.with_ymd_and_hms(2021, 8, 10, 0, 0, 0).unwrap();
+        let flags = flags_for(
+            vec![
+                performance_data(last_bar, vec![1f64, 2f64]),
+                performance_data(last_bar + chrono::Duration::days(1), vec![1f64, 2f64]),
+            ],
+            Arc::new(Metrics::default()),
+        )
+        .await;
+        assert!(flags.iter().all(QualityFlags::is_healthy));
+    }
+}
@@ -0,0 +1,155 @@
+use crate::actors::messages::{Bars, Drain, PerformanceIndicators};
+use crate::store::{unblock, SharedStore};
+use async_trait::async_trait;
+use log::error;
+use std::time::Duration;
+use xactor::{message, Actor, Context, Handler};
+
+/// Indicator rows held back before they're written in one transaction
+const BATCH_ROWS: usize = 500;
+
+/// How often held back indicator rows are written, however few there are
+const BATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[message]
+#[derive(Clone)]
+struct WriteBatch;
+
+/// Stores every indicator row published on the broker in SQLite, and the raw bars of each fetch
+/// if `with_bars` is set. Indicator rows are written in batches, and every write runs off the
+/// executor's threads.
+pub struct SqliteWriter {
+    store: SharedStore,
+    bars: bool,
+    batch: Vec<PerformanceIndicators>,
+}
+
+impl SqliteWriter {
+    pub fn new(store: SharedStore) -> Self {
+        SqliteWriter {
+            store,
+            bars: false,
+            batch: vec![],
+        }
+    }
+
+    pub fn with_bars(mut self, bars: bool) -> Self {
+        self.bars = bars;
+        self
+    }
+
+    async fn write_batch(&mut self) {
+        if self.batch.is_empty() {
+            return;
+        }
+        let rows = std::mem::take(&mut self.batch);
+        let store = self.store.clone();
+        let count = rows.len();
+        if let Err(e) = unblock(move || store.insert_indicators(&rows)).await {
+            error!("Failed to store {} indicator rows: {:?}", count, e);
+        }
+    }
+}
+
+#[async_trait]
+impl Actor for SqliteWriter {
+    async fn started(&mut self, ctx: &mut Context<Self>) -> anyhow::Result<()> {
+        ctx.subscribe::<PerformanceIndicators>().await?;
+        if self.bars {
+            ctx.subscribe::<Bars>().await?;
+        }
+        ctx.send_interval(WriteBatch, BATCH_INTERVAL);
+        Ok(())
+    }
+
+    async fn stopped(&mut self, _ctx: &mut Context<Self>) {
+        self.write_batch().await;
+    }
+}
+
+#[async_trait]
+impl Handler<PerformanceIndicators> for SqliteWriter {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, msg: PerformanceIndicators) -> () {
+        self.batch.push(msg);
+        if self.batch.len() >= BATCH_ROWS {
+            self.write_batch().await;
+        }
+    }
+}
+
+#[async_trait]
+impl Handler<WriteBatch> for SqliteWriter {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: WriteBatch) -> () {
+        self.write_batch().await;
+    }
+}
+
+#[async_trait]
+impl Handler<Bars> for SqliteWriter {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, msg: Bars) -> () {
+        let store = self.store.clone();
+        let ticker = msg.ticker().clone();
+        if let Err(e) = unblock(move || store.insert_bars(&msg)).await {
+            error!("Failed to store bars for {:?}: {:?}", ticker, e);
+        }
+    }
+}
+
+/// Writes the indicator rows held back so far
+#[async_trait]
+impl Handler<Drain> for SqliteWriter {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: Drain) -> () {
+        self.write_batch().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{HistoryRange, Store};
+    use chrono::Utc;
+    use lib::quote_provider::{Bar, Interval};
+    use lib::ticker::Ticker;
+    use std::sync::Arc;
+
+    #[async_std::test]
+    async fn sqlite_writer_stores_indicators_and_bars() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Arc::new(Store::open(dir.path().join("tracker.db")).unwrap());
+        let addr = SqliteWriter::new(store.clone())
+            .with_bars(true)
+            .start()
+            .await
+            .unwrap();
+
+        let ticker = Ticker::from("SQLT");
+        let now = Utc::now();
+        addr.call(PerformanceIndicators::new(30, &[1f64], ticker.clone(), now))
+            .await
+            .unwrap();
+        let bar = Bar {
+            timestamp: now,
+            open: 1f64,
+            high: 1f64,
+            low: 1f64,
+            close: 1f64,
+            adjclose: 1f64,
+            volume: 10,
+        };
+        addr.call(Bars::new(ticker.clone(), Interval::OneDay, vec![bar]))
+            .await
+            .unwrap();
+        addr.call(Drain).await.unwrap();
+
+        let range = HistoryRange {
+            from: None,
+            until: None,
+            limit: 10,
+        };
+        assert_eq!(store.indicators(&ticker, range).unwrap().len(), 1);
+        assert_eq!(
+            store.bars(&ticker, Interval::OneDay, range).unwrap().len(),
+            1
+        );
+    }
+}
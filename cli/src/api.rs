@@ -1,8 +1,17 @@
-pub use filters::get_n_indicators;
+pub use filters::{
+    get_history, get_metrics, get_n_indicators, get_sector_performance, get_ticker_status,
+    watchlist,
+};
+pub use models::Watchlist;
 mod filters {
     use super::handlers;
+    use super::models::{FormatQuery, HistoryQuery, SharedWatchlist};
     use crate::actors::messages::PerformanceIndicators;
+    use crate::failures::SharedFailures;
+    use crate::metrics::SharedMetrics;
     use crate::read_optimised_circular_buffer::ReadOptimisedCircularBuffer;
+    use crate::store::SharedStore;
+    use lib::quote_provider::Interval;
     use std::convert::Infallible;
     use warp::Filter;
 
@@ -11,10 +20,92 @@ mod filters {
     ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
         warp::path("tail")
             .and(warp::path::param())
+            .and(warp::query::<FormatQuery>())
             .and(with_buf(buf))
             .map(handlers::get_n_indicators)
     }
 
+    pub fn get_sector_performance(
+        buf: ReadOptimisedCircularBuffer<PerformanceIndicators>,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path("sectors")
+            .and(warp::path::end())
+            .and(warp::query::<FormatQuery>())
+            .and(with_buf(buf))
+            .map(handlers::get_sector_performance)
+    }
+
+    pub fn get_metrics(
+        metrics: SharedMetrics,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path("metrics")
+            .and(warp::path::end())
+            .map(move || metrics.clone())
+            .map(handlers::get_metrics)
+    }
+
+    pub fn get_ticker_status(
+        failures: SharedFailures,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("status" / "tickers")
+            .map(move || failures.clone())
+            .map(handlers::get_ticker_status)
+    }
+
+    /// `GET /history/{symbol}` and `GET /history/{symbol}/bars`, each with optional `from`,
+    /// `until` and `limit` query parameters, e.g. `?from=30d&limit=100`. Bars are of `interval`
+    /// unless an `interval` parameter picks another, e.g. `?interval=1d`.
+    pub fn get_history(
+        store: Option<SharedStore>,
+        interval: Interval,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        let indicators = warp::path!("history" / String)
+            .and(warp::query::<HistoryQuery>())
+            .and(with_store(store.clone()))
+            .and_then(handlers::get_indicator_history);
+        let bars = warp::path!("history" / String / "bars")
+            .and(warp::query::<HistoryQuery>())
+            .and(with_store(store))
+            .and(warp::any().map(move || interval))
+            .and_then(handlers::get_bar_history);
+        indicators.or(bars)
+    }
+
+    fn with_store(
+        store: Option<SharedStore>,
+    ) -> impl Filter<Extract = (Option<SharedStore>,), Error = Infallible> + Clone {
+        warp::any().map(move || store.clone())
+    }
+
+    /// `GET /tickers`, `POST /tickers` with `{"symbol": "MSFT", "group": "tech"}` and
+    /// `DELETE /tickers/{symbol}`
+    pub fn watchlist(
+        watchlist: SharedWatchlist,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        let list = warp::path("tickers")
+            .and(warp::path::end())
+            .and(warp::get())
+            .and(with_watchlist(watchlist.clone()))
+            .and_then(handlers::list_tickers);
+        let add = warp::path("tickers")
+            .and(warp::path::end())
+            .and(warp::post())
+            .and(warp::body::json())
+            .and(with_watchlist(watchlist.clone()))
+            .and_then(handlers::add_ticker);
+        let remove = warp::path!("tickers" / String)
+            .and(warp::delete())
+            .and(with_watchlist(watchlist))
+            .and_then(handlers::remove_ticker);
+        list.or(add).or(remove)
+    }
+
+    fn with_watchlist(
+        watchlist: SharedWatchlist,
+    ) -> impl Filter<Extract = (SharedWatchlist,), Error = Infallible> + Clone {
+        warp::any().map(move || watchlist.clone())
+    }
+
     fn with_buf(
         buf: ReadOptimisedCircularBuffer<PerformanceIndicators>,
     ) -> impl Filter<
@@ -26,22 +117,199 @@ mod filters {
 }
 
 mod handlers {
-    use super::models::Indicators;
-    use crate::actors::messages::PerformanceIndicators;
+    use super::models::{
+        AddTickerRequest, ErrorMessage, FormatQuery, HistoryQuery, Indicators, SectorPerformance,
+        SharedWatchlist,
+    };
+    use crate::actors::messages::{
+        AddTicker, ListTickers, PerformanceIndicators, RemoveTicker, WatchlistEntry,
+    };
+    use crate::config::DEFAULT_GROUP;
+    use crate::failures::SharedFailures;
+    use crate::metrics::SharedMetrics;
 
     use crate::read_optimised_circular_buffer::ReadOptimisedCircularBuffer;
+    use crate::store::{unblock, HistoryRange, SharedStore};
+    use chrono::Utc;
+    use lib::formatter::with_number_format;
+    use lib::quote_provider::Interval;
+    use lib::ticker::{Ticker, TickerParseError};
+    use std::convert::Infallible;
+    use std::fmt::Display;
+    use warp::http::StatusCode;
+    use warp::reply::Response;
+    use warp::Reply;
+
     pub fn get_n_indicators(
         n: usize,
+        query: FormatQuery,
         buf: ReadOptimisedCircularBuffer<PerformanceIndicators>,
     ) -> impl warp::Reply {
         let reader = buf.read().unwrap();
-        warp::reply::json(&Indicators::new(reader.iter().take(n).cloned().collect()))
+        let indicators = Indicators::new(reader.iter().take(n).cloned().collect());
+        with_number_format(query.format, || warp::reply::json(&indicators))
+    }
+
+    pub fn get_sector_performance(
+        query: FormatQuery,
+        buf: ReadOptimisedCircularBuffer<PerformanceIndicators>,
+    ) -> impl warp::Reply {
+        let reader = buf.read().unwrap();
+        let sectors = SectorPerformance::from_indicators(reader.iter());
+        with_number_format(query.format, || warp::reply::json(&sectors))
+    }
+
+    pub fn get_metrics(metrics: SharedMetrics) -> impl warp::Reply {
+        warp::reply::json(&metrics.snapshot())
+    }
+
+    pub fn get_ticker_status(failures: SharedFailures) -> impl warp::Reply {
+        warp::reply::json(&failures.records())
+    }
+
+    pub async fn list_tickers(watchlist: SharedWatchlist) -> Result<Response, Infallible> {
+        Ok(match watchlist.list.call(ListTickers).await {
+            Ok(entries) => warp::reply::json(&entries).into_response(),
+            Err(e) => error_reply(StatusCode::SERVICE_UNAVAILABLE, e),
+        })
+    }
+
+    pub async fn add_ticker(
+        request: AddTickerRequest,
+        watchlist: SharedWatchlist,
+    ) -> Result<Response, Infallible> {
+        let ticker: Ticker = match request.symbol.parse() {
+            Ok(ticker) => ticker,
+            Err(e) => return Ok(error_reply(StatusCode::BAD_REQUEST, e)),
+        };
+        let entry = WatchlistEntry {
+            ticker: ticker.clone(),
+            group: request
+                .group
+                .clone()
+                .unwrap_or_else(|| DEFAULT_GROUP.to_owned()),
+        };
+        let added = watchlist.add.call(AddTicker {
+            ticker,
+            group: request.group,
+        });
+        Ok(match added.await {
+            Ok(true) => warp::reply::with_status(warp::reply::json(&entry), StatusCode::CREATED)
+                .into_response(),
+            Ok(false) => error_reply(
+                StatusCode::CONFLICT,
+                format!("{} is already watched", entry.ticker),
+            ),
+            Err(e) => error_reply(StatusCode::SERVICE_UNAVAILABLE, e),
+        })
+    }
+
+    pub async fn remove_ticker(
+        symbol: String,
+        watchlist: SharedWatchlist,
+    ) -> Result<Response, Infallible> {
+        let ticker: Ticker = match symbol.parse() {
+            Ok(ticker) => ticker,
+            Err(e) => return Ok(error_reply(StatusCode::BAD_REQUEST, e)),
+        };
+        let removed = watchlist.remove.call(RemoveTicker {
+            ticker: ticker.clone(),
+        });
+        Ok(match removed.await {
+            Ok(true) => StatusCode::NO_CONTENT.into_response(),
+            Ok(false) => error_reply(StatusCode::NOT_FOUND, format!("{} is not watched", ticker)),
+            Err(e) => error_reply(StatusCode::SERVICE_UNAVAILABLE, e),
+        })
+    }
+
+    pub async fn get_indicator_history(
+        symbol: String,
+        query: HistoryQuery,
+        store: Option<SharedStore>,
+    ) -> Result<Response, Infallible> {
+        let (store, ticker, range) = match history_request(&symbol, &query, store) {
+            Ok(request) => request,
+            Err((status, error)) => return Ok(error_reply(status, error)),
+        };
+        Ok(
+            match unblock(move || store.indicators(&ticker, range)).await {
+                Ok(rows) => warp::reply::json(&rows).into_response(),
+                Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, e),
+            },
+        )
+    }
+
+    pub async fn get_bar_history(
+        symbol: String,
+        query: HistoryQuery,
+        store: Option<SharedStore>,
+        interval: Interval,
+    ) -> Result<Response, Infallible> {
+        let (store, ticker, range) = match history_request(&symbol, &query, store) {
+            Ok(request) => request,
+            Err((status, error)) => return Ok(error_reply(status, error)),
+        };
+        let interval = query.interval.unwrap_or(interval);
+        Ok(
+            match unblock(move || store.bars(&ticker, interval, range)).await {
+                Ok(bars) => warp::reply::json(&bars).into_response(),
+                Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, e),
+            },
+        )
+    }
+
+    fn history_request(
+        symbol: &str,
+        query: &HistoryQuery,
+        store: Option<SharedStore>,
+    ) -> Result<(SharedStore, Ticker, HistoryRange), (StatusCode, String)> {
+        let store = store.ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                "History needs an --sqlite database".to_owned(),
+            )
+        })?;
+        let ticker = symbol
+            .parse()
+            .map_err(|e: TickerParseError| (StatusCode::BAD_REQUEST, e.to_string()))?;
+        let range = query
+            .range(Utc::now())
+            .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+        Ok((store, ticker, range))
+    }
+
+    fn error_reply(status: StatusCode, error: impl Display) -> Response {
+        warp::reply::with_status(
+            warp::reply::json(&ErrorMessage {
+                error: error.to_string(),
+            }),
+            status,
+        )
+        .into_response()
     }
 }
 
 mod models {
-    use crate::actors::messages::PerformanceIndicators;
-    use serde::Serialize;
+    use crate::actors::messages::{AddTicker, ListTickers, PerformanceIndicators, RemoveTicker};
+    use crate::dates::{parse_date, DateParseError};
+    use crate::store::HistoryRange;
+    use chrono::{DateTime, Utc};
+    use lib::formatter::{NumberFormat, Percentage};
+    use lib::quote_provider::Interval;
+    use lib::ticker::Ticker;
+    use serde::{Deserialize, Deserializer, Serialize};
+    use std::collections::{BTreeMap, HashMap};
+    use std::sync::Arc;
+    use xactor::{Actor, Addr, Caller, Handler};
+
+    /// Sector for tickers without metadata
+    const UNKNOWN_SECTOR: &str = "Unknown";
+
+    /// Rows returned by history queries without a `limit`
+    const DEFAULT_HISTORY_LIMIT: usize = 1000;
+
+    /// Most rows a history query returns, whatever its `limit`
+    const MAX_HISTORY_LIMIT: usize = 10_000;
 
     #[derive(Serialize)]
     pub struct Indicators(Vec<PerformanceIndicators>);
@@ -51,4 +319,175 @@ mod models {
             Indicators(performance_indicators)
         }
     }
+
+    pub type SharedWatchlist = Arc<Watchlist>;
+
+    /// Callers for the watchlist messages handled by `FetchActor`
+    pub struct Watchlist {
+        pub add: Caller<AddTicker>,
+        pub remove: Caller<RemoveTicker>,
+        pub list: Caller<ListTickers>,
+    }
+
+    impl Watchlist {
+        pub fn new<A>(addr: &Addr<A>) -> Self
+        where
+            A: Actor + Handler<AddTicker> + Handler<RemoveTicker> + Handler<ListTickers>,
+        {
+            Watchlist {
+                add: addr.caller(),
+                remove: addr.caller(),
+                list: addr.caller(),
+            }
+        }
+    }
+
+    #[derive(Deserialize)]
+    pub struct AddTickerRequest {
+        pub symbol: String,
+        pub group: Option<String>,
+    }
+
+    /// `?format=raw` serializes prices and percentages as plain numbers
+    #[derive(Deserialize)]
+    pub struct FormatQuery {
+        #[serde(default, deserialize_with = "number_format")]
+        pub format: NumberFormat,
+    }
+
+    fn number_format<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NumberFormat, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+
+    /// Rows to return from the store, with dates in any form `--from` takes
+    #[derive(Deserialize)]
+    pub struct HistoryQuery {
+        pub from: Option<String>,
+        pub until: Option<String>,
+        pub limit: Option<usize>,
+        /// Only used for bars
+        pub interval: Option<Interval>,
+    }
+
+    impl HistoryQuery {
+        pub fn range(&self, now: DateTime<Utc>) -> Result<HistoryRange, DateParseError> {
+            let date =
+                |date: &Option<String>| date.as_deref().map(|d| parse_date(d, now)).transpose();
+            Ok(HistoryRange {
+                from: date(&self.from)?,
+                until: date(&self.until)?,
+                limit: self
+                    .limit
+                    .unwrap_or(DEFAULT_HISTORY_LIMIT)
+                    .min(MAX_HISTORY_LIMIT),
+            })
+        }
+    }
+
+    #[derive(Serialize)]
+    pub struct ErrorMessage {
+        pub error: String,
+    }
+
+    #[derive(Debug, PartialEq, Serialize)]
+    pub struct SectorPerformance {
+        sector: String,
+        tickers: usize,
+        average_percentage_change: Option<Percentage>,
+    }
+
+    impl SectorPerformance {
+        /// Averages the percentage change of each ticker's latest indicators by sector
+        pub fn from_indicators<'a>(
+            indicators: impl Iterator<Item = &'a PerformanceIndicators>,
+        ) -> Vec<SectorPerformance> {
+            let mut latest: HashMap<&Ticker, &PerformanceIndicators> = HashMap::new();
+            for indicator in indicators {
+                latest.insert(indicator.ticker(), indicator);
+            }
+            let mut sectors: BTreeMap<&str, (usize, Vec<f64>)> = BTreeMap::new();
+            for indicator in latest.values() {
+                let (tickers, changes) = sectors
+                    .entry(indicator.sector().unwrap_or(UNKNOWN_SECTOR))
+                    .or_default();
+                *tickers += 1;
+                changes.extend(indicator.percentage_change().map(|change| change.0));
+            }
+            sectors
+                .into_iter()
+                .map(|(sector, (tickers, changes))| SectorPerformance {
+                    sector: sector.to_owned(),
+                    tickers,
+                    average_percentage_change: (!changes.is_empty())
+                        .then(|| Percentage(changes.iter().sum::<f64>() / changes.len() as f64)),
+                })
+                .collect()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use chrono::Utc;
+        use lib::ticker::TickerMetadata;
+
+        fn indicators(ticker: &str, series: &[f64], sector: Option<&str>) -> PerformanceIndicators {
+            let metadata = TickerMetadata {
+                sector: sector.map(str::to_owned),
+                ..TickerMetadata::default()
+            };
+            PerformanceIndicators::new(30, series, Ticker::from(ticker), Utc::now())
+                .with_metadata(Some(&metadata))
+        }
+
+        #[test]
+        fn history_query_caps_the_limit() {
+            let query = |limit| HistoryQuery {
+                from: None,
+                until: None,
+                limit,
+                interval: None,
+            };
+            let now = Utc::now();
+            assert_eq!(query(None).range(now).unwrap().limit, DEFAULT_HISTORY_LIMIT);
+            assert_eq!(query(Some(10)).range(now).unwrap().limit, 10);
+            assert_eq!(
+                query(Some(usize::MAX)).range(now).unwrap().limit,
+                MAX_HISTORY_LIMIT
+            );
+        }
+
+        #[test]
+        fn sector_performance_averages_latest_indicators_per_ticker() {
+            let buf = [
+                indicators("MSFT", &[10f64, 20f64], Some("Tech")),
+                indicators("MSFT", &[10f64, 11f64], Some("Tech")),
+                indicators("AAPL", &[10f64, 13f64], Some("Tech")),
+                indicators("XOM", &[10f64, 9f64], Some("Energy")),
+                indicators("NEW", &[10f64], None),
+            ];
+            assert_eq!(
+                SectorPerformance::from_indicators(buf.iter()),
+                vec![
+                    SectorPerformance {
+                        sector: "Energy".to_owned(),
+                        tickers: 1,
+                        average_percentage_change: Some(Percentage(90f64)),
+                    },
+                    SectorPerformance {
+                        sector: "Tech".to_owned(),
+                        tickers: 2,
+                        average_percentage_change: Some(Percentage(120f64)),
+                    },
+                    SectorPerformance {
+                        sector: UNKNOWN_SECTOR.to_owned(),
+                        tickers: 1,
+                        average_percentage_change: None,
+                    },
+                ]
+            );
+        }
+    }
 }
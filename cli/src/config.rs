@@ -1,8 +1,23 @@
+use crate::actors::QualityThresholds;
+use crate::dates::parse_date;
+use crate::schedule::{Schedule, ScheduleParseError, TickerGroup};
+use crate::settings::{parse, Settings, CONFIG};
+use crate::sinks::{FileOptions, OutputFormat, ParquetOptions};
+use crate::ticker_file::{parse_ticker_file, TickerFileError};
 use anyhow::{anyhow, Result};
 use chrono::{DateTime, Utc};
-use clap::{App, Arg};
-use lib::ticker::Ticker;
+use clap::{App, Arg, SubCommand};
+use lib::calendar::ExchangeCalendar;
+use lib::currency::Currency;
+use lib::formatter::{FormatOptions, NumberFormat};
+use lib::quote_provider::Interval;
+use lib::ticker::{Ticker, TickerParseError};
+use log::warn;
+use std::ffi::OsString;
 use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use strum::EnumString;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -15,88 +30,1169 @@ pub enum ArgumentParsingError {
 
     #[error("Only one of {0} {1} required")]
     TooManyParameters(&'static str, &'static str),
+
+    #[error("Invalid value for {0}: {1}")]
+    InvalidValue(String, String),
+
+    #[error("Unknown setting {0}")]
+    UnknownKey(String),
+
+    #[error("Invalid config file {0}: {1}")]
+    InvalidConfigFile(String, toml::de::Error),
+
+    #[error(transparent)]
+    InvalidTicker(#[from] TickerParseError),
+
+    #[error("Invalid ticker file {0}: {1}")]
+    InvalidTickerFile(String, TickerFileError),
+}
+
+pub const DEFAULT_GROUP: &str = "default";
+
+#[derive(Debug, Clone, Copy, PartialEq, EnumString)]
+#[strum(serialize_all = "lowercase")]
+enum ProviderKind {
+    Yahoo,
+    File,
+    Synthetic,
+}
+
+#[derive(Debug, Clone)]
+pub enum ProviderConfig {
+    Yahoo,
+    File { data_dir: PathBuf },
+    Synthetic(SyntheticConfig),
+    Replay { dir: PathBuf, speed: f64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, EnumString)]
+#[strum(serialize_all = "kebab-case")]
+pub enum SyntheticModel {
+    Gbm,
+    RandomWalk,
+}
+
+/// Parameters of the synthetic provider. Drift and volatility are annualised.
+#[derive(Debug, Clone)]
+pub struct SyntheticConfig {
+    pub model: SyntheticModel,
+    pub seed: u64,
+    pub drift: f64,
+    pub volatility: f64,
+    pub initial_price: f64,
+}
+
+/// What the binary does, chosen by subcommand
+#[derive(Debug, Clone, Copy, PartialEq, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum Command {
+    /// Polls on schedule and serves the HTTP API until stopped; the default
+    Serve,
+    /// Fetches once, writes the indicators and exits
+    Report,
+    /// Writes the indicators as of every bar from `from` to `until`, then exits
+    Backfill,
+    /// Checks the config, tickers and reference files without fetching
+    Validate,
 }
 
 #[derive(Debug, Clone)]
 pub struct Config {
-    pub tickers: Vec<Ticker>,
+    pub command: Command,
+    pub groups: Vec<TickerGroup>,
+    pub flush_schedule: Schedule,
     pub from: DateTime<Utc>,
+    pub until: Option<DateTime<Utc>>,
     pub file: Option<String>,
+    pub output_format: OutputFormat,
+    pub file_options: FileOptions,
+    pub parquet: ParquetOptions,
+    pub sqlite: Option<PathBuf>,
+    pub sqlite_bars: bool,
+    pub provider: ProviderConfig,
+    pub record: Option<PathBuf>,
+    pub interval: Interval,
+    pub calendar: Option<ExchangeCalendar>,
+    pub off_hours_interval: Option<chrono::Duration>,
+    pub quality: QualityThresholds,
+    pub quarantine_after: u32,
+    pub quarantine_retry: chrono::Duration,
+    pub dead_letter: Option<PathBuf>,
+    pub metadata: Option<PathBuf>,
+    pub watchlist: Option<PathBuf>,
+    pub report_currency: Option<Currency>,
+    pub number_format: NumberFormat,
+    pub format_options: FormatOptions,
+    pub window: usize,
+    pub port: u16,
+    pub api_buffer_size: usize,
+    pub shutdown_timeout: std::time::Duration,
 }
 
 impl Config {
     pub fn new() -> Result<Config> {
-        let arg_matcher = App::new("SimpleTracker")
-            .version("0.0.1")
-            .arg(
-                Arg::with_name("ticker")
-                    .short("t")
-                    .long("ticker")
-                    .value_name("TICKER")
-                    .help("Loads the stock data for the provided ticker")
-                    // .required(true)
-                    .multiple(true),
-            )
-            .arg(
-                Arg::with_name("ticker_file")
-                    .short("i")
-                    .long("ticker-file")
-                    .value_name("TICKER FILE")
-                    .help("Loads a comma delimited file of tickers"),
-            )
-            .arg(
-                Arg::with_name("from")
-                    .short("f")
-                    .long("from")
-                    .value_name("FROM")
-                    .help("Start date to load data from"),
-            )
-            .arg(
-                Arg::with_name("file")
-                    .short("o")
-                    .long("output")
-                    .value_name("FILE")
-                    .help("File to output CSV data to"),
-            )
-            .get_matches();
+        Config::from_args(std::env::args_os(), std::env::vars())
+    }
 
-        let tickers: Vec<Ticker> = match (
-            arg_matcher.values_of("ticker"),
-            arg_matcher.value_of("ticker_file"),
+    /// Resolves the config from command line `args`, `SIMPLETRACKER_*` variables of `env`, and the
+    /// TOML file named by `--config`, in that order of precedence
+    pub fn from_args<I, A>(
+        args: I,
+        env: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Config>
+    where
+        I: IntoIterator<Item = A>,
+        A: Into<OsString> + Clone,
+    {
+        let mut names = vec![];
+        let matches = app(&mut names).get_matches_from(args);
+        let (command, matches) = match matches.subcommand() {
+            (name, Some(subcommand_matches)) => (
+                Command::from_str(name).map_err(|_| {
+                    ArgumentParsingError::InvalidValue("command".to_owned(), name.to_owned())
+                })?,
+                subcommand_matches.clone(),
+            ),
+            _ => (Command::Serve, matches),
+        };
+        let settings = Settings::new(matches, &names, env)?;
+
+        let watchlist = settings.value("watchlist").map(|s| PathBuf::from(s.value));
+        let given = match (settings.values("ticker"), settings.value("ticker_file")) {
+            (None, None) => None,
+            (Some(tickers), None) => Some((
+                parse_tickers(tickers.value.iter().map(String::as_str))?,
+                vec![],
+            )),
+            (None, Some(ticker_file)) => Some(read_ticker_file(Path::new(&ticker_file.value))?),
+            (Some(_), Some(_)) => {
+                return Err(anyhow!(ArgumentParsingError::TooManyParameters(
+                    "Ticker",
+                    "Ticker-File"
+                )))
+            }
+        };
+        let (tickers, file_groups) = match (
+            given,
+            watchlist.as_deref().filter(|watchlist| watchlist.exists()),
         ) {
-            (None, None) => Err(anyhow!(ArgumentParsingError::OneRequired(
-                "Ticker",
-                "Ticker-file"
-            ))),
-            (Some(tickers), None) => Ok(tickers
-                .into_iter()
-                .map(String::from)
-                .map(Ticker::new)
-                .collect()),
-            (None, Some(tickerfile)) => {
-                let tickers = read_to_string(tickerfile)?;
-                Ok(tickers
-                    .split(',')
-                    .map(String::from)
-                    .map(Ticker::new)
-                    .collect())
+            (given, Some(watchlist)) => {
+                let saved = read_ticker_file(watchlist)?;
+                let ignored = given
+                    .map(|given| not_in(&given, &saved))
+                    .unwrap_or_default();
+                if !ignored.is_empty() {
+                    warn!(
+                            "Loading tickers from {} instead of --ticker/--ticker-file, which also list {}. Add them through the API or remove the watchlist to fetch them",
+                            watchlist.display(),
+                            ignored.iter().map(Ticker::as_str).collect::<Vec<_>>().join(", ")
+                        );
+                }
+                saved
+            }
+            (Some(given), None) => given,
+            (None, None) => {
+                return Err(anyhow!(ArgumentParsingError::OneRequired(
+                    "Ticker",
+                    "Ticker-file"
+                )))
             }
-            (Some(_), Some(_)) => Err(anyhow!(ArgumentParsingError::TooManyParameters(
-                "Ticker",
-                "Ticker-File"
-            ))),
-        }?;
-        let from_value = arg_matcher
-            .value_of("from")
-            .ok_or(ArgumentParsingError::MissingParameter("From"))?;
-        let from = DateTime::parse_from_rfc3339(from_value)?.with_timezone(&Utc);
-        let file = arg_matcher.value_of("file").map(|x| x.to_owned());
+        };
+        let now = Utc::now();
+        let from_value = settings
+            .value("from")
+            .ok_or(ArgumentParsingError::MissingParameter("from"))?;
+        let from = parse_date(&from_value.value, now).map_err(|e| from_value.invalid(e))?;
+        let until = match settings.value("until") {
+            Some(until_value) => match parse_date(&until_value.value, now) {
+                Ok(until) if until <= from => {
+                    return Err(until_value.invalid("must be after --from").into())
+                }
+                Ok(until) => Some(until),
+                Err(e) => return Err(until_value.invalid(e).into()),
+            },
+            None => None,
+        };
+        let file = settings.value("output").map(|s| s.value);
+        let output_format = settings.require("output_format")?;
+        if output_format == OutputFormat::Parquet && file.is_none() {
+            return Err(settings
+                .value("output_format")
+                .unwrap()
+                .invalid("parquet needs --output")
+                .into());
+        }
+        let positive = |name: &'static str| -> Result<Option<usize>> {
+            match settings.parse(name)? {
+                Some(0) => Err(settings
+                    .value(name)
+                    .unwrap()
+                    .invalid("must be positive")
+                    .into()),
+                value => Ok(value),
+            }
+        };
+        let file_options = FileOptions {
+            append: settings.parse("append")?.unwrap_or(false),
+            rotate_size: positive("rotate_size")?.map(|megabytes| megabytes as u64 * 1024 * 1024),
+            rotate_every: settings.parse("rotate_every")?,
+            gzip: settings.parse("gzip_rotated")?.unwrap_or(false),
+        };
+        let rotates = file_options.rotate_size.is_some() || file_options.rotate_every.is_some();
+        for (name, set) in [
+            ("append", file_options.append),
+            ("rotate_size", file_options.rotate_size.is_some()),
+            ("rotate_every", file_options.rotate_every.is_some()),
+            ("gzip_rotated", file_options.gzip),
+        ] {
+            let reason = match output_format {
+                _ if !set => continue,
+                _ if file.is_none() => "needs --output",
+                OutputFormat::Parquet => {
+                    "isn't supported by parquet, see --rollover-rows and --rollover-interval"
+                }
+                OutputFormat::Json if name == "append" => "can't continue a closed JSON array",
+                _ if name == "gzip_rotated" && !rotates => "needs --rotate-size or --rotate-every",
+                _ => continue,
+            };
+            return Err(settings.value(name).unwrap().invalid(reason).into());
+        }
+        let parquet = ParquetOptions {
+            batch_rows: positive("batch_rows")?.unwrap_or_default(),
+            batch_timeout: chrono::Duration::seconds(settings.require("batch_timeout")?),
+            rollover_rows: positive("rollover_rows")?,
+            rollover_interval: settings
+                .parse("rollover_interval")?
+                .map(chrono::Duration::seconds),
+        };
+        let sqlite = settings.value("sqlite").map(|s| PathBuf::from(s.value));
+        let sqlite_bars = settings.parse("sqlite_bars")?.unwrap_or(false);
+        if sqlite_bars && sqlite.is_none() {
+            return Err(settings
+                .value("sqlite_bars")
+                .unwrap()
+                .invalid("needs --sqlite")
+                .into());
+        }
 
-        let config = Config {
+        let provider = match settings.value("replay") {
+            Some(dir) => ProviderConfig::Replay {
+                dir: PathBuf::from(dir.value),
+                speed: match settings.require("replay_speed")? {
+                    speed if speed > 0f64 => speed,
+                    _ => {
+                        return Err(settings
+                            .value("replay_speed")
+                            .unwrap()
+                            .invalid("must be positive")
+                            .into())
+                    }
+                },
+            },
+            None => match settings.require("provider")? {
+                ProviderKind::Yahoo => ProviderConfig::Yahoo,
+                ProviderKind::File => ProviderConfig::File {
+                    data_dir: settings
+                        .value("data_dir")
+                        .map(|s| PathBuf::from(s.value))
+                        .ok_or(ArgumentParsingError::MissingParameter("data-dir"))?,
+                },
+                ProviderKind::Synthetic => ProviderConfig::Synthetic(SyntheticConfig {
+                    model: settings.require("model")?,
+                    seed: settings.require("seed")?,
+                    drift: settings.require("drift")?,
+                    volatility: settings.require("volatility")?,
+                    initial_price: settings.require("initial_price")?,
+                }),
+            },
+        };
+        let record = settings.value("record").map(|s| PathBuf::from(s.value));
+        let interval = settings.require("interval")?;
+        let calendar = match (settings.value("calendar"), settings.value("exchange")) {
+            (Some(calendar_file), _) => Some(
+                toml::from_str(&read_to_string(&calendar_file.value)?).map_err(|e| {
+                    calendar_file.invalid(format!("{}: {}", calendar_file.value, e))
+                })?,
+            ),
+            (None, Some(exchange)) => match exchange.value.as_str() {
+                "nyse" => Some(ExchangeCalendar::nyse()),
+                "nasdaq" => Some(ExchangeCalendar::nasdaq()),
+                other => {
+                    return Err(exchange
+                        .invalid(format!("unknown exchange {}", other))
+                        .into())
+                }
+            },
+            (None, None) => None,
+        };
+        let off_hours_interval = positive("off_hours_interval")?
+            .map(|seconds| chrono::Duration::seconds(seconds as i64));
+
+        let group_values = settings
+            .values("group")
+            .map(|s| s.value)
+            .unwrap_or_default();
+        let schedule_values = settings
+            .values("schedule")
+            .map(|s| s.value)
+            .unwrap_or_default();
+        let groups = ticker_groups(
             tickers,
+            file_groups,
+            group_values.iter().map(String::as_str),
+            schedule_values.iter().map(String::as_str),
+        )?;
+        let flush_schedule = settings.require("flush_schedule")?;
+        let number_format = settings.require("number_format")?;
+        let decimal_mark = settings.require("decimal_mark")?;
+        let thousands_separator = match settings.value("thousands_separator") {
+            Some(separator) => match parse(&separator)? {
+                separator_char if separator_char == decimal_mark => {
+                    return Err(separator.invalid("it is also the decimal mark").into())
+                }
+                separator_char => Some(separator_char),
+            },
+            None => None,
+        };
+        let format_options = FormatOptions::default()
+            .with_decimals(settings.require("decimals")?)
+            .with_decimal_mark(decimal_mark)
+            .with_thousands_separator(thousands_separator)
+            .with_negative_style(settings.require("negative_style")?)
+            .with_currency_placement(settings.require("currency_placement")?);
+        let quality = QualityThresholds {
+            stale_after: settings.require("stale_after")?,
+            outlier_threshold: settings.require("outlier_threshold")?,
+            max_gap_bars: settings.require("max_gap")?,
+        };
+        let quarantine_after = settings.require("quarantine_after")?;
+        let quarantine_retry = chrono::Duration::seconds(settings.require("quarantine_retry")?);
+        let dead_letter = settings
+            .value("dead_letter")
+            .map(|s| PathBuf::from(s.value));
+        let metadata = settings.value("metadata").map(|s| PathBuf::from(s.value));
+        let report_currency = settings.parse("report_currency")?;
+        let window = match settings.require("window")? {
+            0 => {
+                return Err(settings
+                    .value("window")
+                    .unwrap()
+                    .invalid("must be positive")
+                    .into())
+            }
+            window => window,
+        };
+        let port = settings.require("port")?;
+        let api_buffer_size = settings.require("api_buffer_size")?;
+        let shutdown_timeout =
+            std::time::Duration::from_secs(settings.require("shutdown_timeout")?);
+
+        let config = Config {
+            command,
+            groups,
+            flush_schedule,
             from,
+            until,
             file,
+            output_format,
+            file_options,
+            parquet,
+            sqlite,
+            sqlite_bars,
+            provider,
+            record,
+            interval,
+            calendar,
+            off_hours_interval,
+            quality,
+            quarantine_after,
+            quarantine_retry,
+            dead_letter,
+            metadata,
+            watchlist,
+            report_currency,
+            number_format,
+            format_options,
+            window,
+            port,
+            api_buffer_size,
+            shutdown_timeout,
         };
         Ok(config)
     }
 }
+
+/// Every setting as a command line flag, recording each setting's name in `names`
+fn app(names: &mut Vec<&'static str>) -> App<'static, 'static> {
+    let mut arg = |name: &'static str| {
+        names.push(name);
+        Arg::with_name(name).global(true)
+    };
+    App::new("SimpleTracker")
+        .version("0.0.1")
+        .subcommand(
+            SubCommand::with_name("serve")
+                .about("Polls on schedule and serves the HTTP API until stopped (the default)"),
+        )
+        .subcommand(
+            SubCommand::with_name("report").about("Fetches once, writes the output and exits"),
+        )
+        .subcommand(SubCommand::with_name("backfill").about(
+            "Writes the indicators as of every bar from --from to --until, or now, and exits",
+        ))
+        .subcommand(
+            SubCommand::with_name("validate")
+                .about("Checks the config, tickers and reference files without fetching"),
+        )
+        .arg(
+            arg("ticker")
+                .short("t")
+                .long("ticker")
+                .value_name("TICKER")
+                .help("Loads the stock data for the provided ticker")
+                // .required(true)
+                .multiple(true),
+        )
+        .arg(
+            arg("ticker_file")
+                .short("i")
+                .long("ticker-file")
+                .value_name("TICKER FILE")
+                .help("Loads tickers from a file listing them by line or comma, with [name] sections, or from a CSV file with a symbol column"),
+        )
+        .arg(
+            arg("from")
+                .short("f")
+                .long("from")
+                .value_name("FROM")
+                .help("Start date to load data from, e.g. 2020-01-01, 2020-01-01T00:00:00Z, 30d, 6mo, 1y or ytd"),
+        )
+        .arg(
+            arg("until")
+                .long("until")
+                .value_name("UNTIL")
+                .help("End date to load data until, in the same forms as --from; fetches once and exits instead of polling"),
+        )
+        .arg(
+            arg("output")
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .help("File to write output to, in --output-format [default: stdout]"),
+        )
+        .arg(
+            arg("output_format")
+                .long("output-format")
+                .value_name("FORMAT")
+                .possible_values(&["csv", "jsonl", "json", "parquet"])
+                .default_value("csv")
+                .help("Writes CSV, JSON Lines, a JSON array closed on exit, or Parquet to --output; all but CSV keep multi-valued fields such as quality as lists"),
+        )
+        .arg(
+            arg("append")
+                .long("append")
+                .help("Continues an existing --output file instead of truncating it, without writing the CSV header again"),
+        )
+        .arg(
+            arg("rotate_size")
+                .long("rotate-size")
+                .value_name("MB")
+                .help("Starts a new numbered --output file, e.g. indicators-1.csv, once it reaches MB megabytes"),
+        )
+        .arg(
+            arg("rotate_every")
+                .long("rotate-every")
+                .value_name("PERIOD")
+                .possible_values(&["hourly", "daily"])
+                .help("Writes to an --output file named by the current hour or day, e.g. indicators-2021-08-13.csv"),
+        )
+        .arg(
+            arg("gzip_rotated")
+                .long("gzip-rotated")
+                .help("Compresses --output files to .gz once they have been rotated"),
+        )
+        .arg(
+            arg("batch_rows")
+                .long("batch-rows")
+                .value_name("ROWS")
+                .default_value("1024")
+                .help("Rows buffered before Parquet output writes a record batch"),
+        )
+        .arg(
+            arg("batch_timeout")
+                .long("batch-timeout")
+                .value_name("SECONDS")
+                .default_value("60")
+                .help("Writes a Parquet record batch once its first row has waited SECONDS; --flush-schedule closes row groups"),
+        )
+        .arg(
+            arg("rollover_rows")
+                .long("rollover-rows")
+                .value_name("ROWS")
+                .help("Starts a new Parquet file, named by when it was opened, after ROWS rows"),
+        )
+        .arg(
+            arg("rollover_interval")
+                .long("rollover-interval")
+                .value_name("SECONDS")
+                .help("Starts a new Parquet file, named by when it was opened, every SECONDS"),
+        )
+        .arg(
+            arg("sqlite")
+                .long("sqlite")
+                .value_name("FILE")
+                .help("Also stores every indicator row in an SQLite database, served by /history/{ticker}"),
+        )
+        .arg(
+            arg("sqlite_bars")
+                .long("sqlite-bars")
+                .help("Also stores the raw bars of every fetch in the --sqlite database, served by /history/{ticker}/bars"),
+        )
+        .arg(
+            arg("provider")
+                .long("provider")
+                .value_name("PROVIDER")
+                .possible_values(&["yahoo", "file", "synthetic"])
+                .default_value("yahoo")
+                .help("Source of quote data"),
+        )
+        .arg(
+            arg("data_dir")
+                .long("data-dir")
+                .value_name("DIR")
+                .help("Directory of per-ticker OHLCV CSV files for the file provider"),
+        )
+        .arg(
+            arg("model")
+                .long("model")
+                .value_name("MODEL")
+                .possible_values(&["gbm", "random-walk"])
+                .default_value("gbm")
+                .help("Price model for the synthetic provider"),
+        )
+        .arg(
+            arg("seed")
+                .long("seed")
+                .value_name("SEED")
+                .default_value("0")
+                .help("Seed for the synthetic provider"),
+        )
+        .arg(
+            arg("drift")
+                .long("drift")
+                .value_name("DRIFT")
+                .default_value("0.05")
+                .help("Annualised drift for the synthetic provider"),
+        )
+        .arg(
+            arg("volatility")
+                .long("volatility")
+                .value_name("VOLATILITY")
+                .default_value("0.2")
+                .help("Annualised volatility for the synthetic provider"),
+        )
+        .arg(
+            arg("initial_price")
+                .long("initial-price")
+                .value_name("PRICE")
+                .default_value("100")
+                .help("Starting price for the synthetic provider"),
+        )
+        .arg(
+            arg("interval")
+                .long("interval")
+                .value_name("INTERVAL")
+                .possible_values(&["1m", "5m", "15m", "1h", "1d", "1wk"])
+                .default_value("1d")
+                .help("Bar interval to fetch; window-based indicators count bars of this interval"),
+        )
+        .arg(
+            arg("exchange")
+                .long("exchange")
+                .value_name("EXCHANGE")
+                .possible_values(&["nyse", "nasdaq"])
+                .help("Only polls during the regular sessions of EXCHANGE"),
+        )
+        .arg(
+            arg("calendar")
+                .long("calendar")
+                .value_name("CALENDAR FILE")
+                .help("Only polls during the sessions in a TOML exchange calendar file"),
+        )
+        .arg(
+            arg("off_hours_interval")
+                .long("off-hours-interval")
+                .value_name("SECONDS")
+                .help("Polls every SECONDS outside trading hours instead of suspending polling"),
+        )
+        .arg(
+            arg("group")
+                .long("group")
+                .value_name("NAME=TICKER,...")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Names a group of tickers that can be given its own schedule"),
+        )
+        .arg(
+            arg("schedule")
+                .long("schedule")
+                .value_name("[GROUP=]SCHEDULE")
+                .multiple(true)
+                .number_of_values(1)
+                .help("When to fetch, e.g. \"every 5m\" or \"tech=cron 0 5 16 * * Mon-Fri in America/New_York\" [default: every 30s]"),
+        )
+        .arg(
+            arg("number_format")
+                .long("number-format")
+                .value_name("FORMAT")
+                .possible_values(&["formatted", "raw"])
+                .default_value("formatted")
+                .help("Writes prices and percentages to the output file as display strings or plain numbers"),
+        )
+        .arg(
+            arg("decimals")
+                .long("decimals")
+                .value_name("PLACES")
+                .default_value("2")
+                .help("Decimal places of formatted prices and percentages"),
+        )
+        .arg(
+            arg("decimal_mark")
+                .long("decimal-mark")
+                .value_name("CHAR")
+                .default_value(".")
+                .help("Separates whole and fractional parts of formatted numbers, e.g. ,"),
+        )
+        .arg(
+            arg("thousands_separator")
+                .long("thousands-separator")
+                .value_name("CHAR")
+                .help("Groups thousands of formatted numbers, e.g. , or ."),
+        )
+        .arg(
+            arg("negative_style")
+                .long("negative-style")
+                .value_name("STYLE")
+                .possible_values(&["after-symbol", "before-symbol", "parentheses"])
+                .default_value("after-symbol")
+                .help("Writes negative prices as $-1.49, -$1.49 or ($1.49)"),
+        )
+        .arg(
+            arg("currency_placement")
+                .long("currency-placement")
+                .value_name("PLACEMENT")
+                .possible_values(&["native", "prefix", "suffix"])
+                .default_value("native")
+                .help("Writes currencies as $1.49 and 1.49 CHF, before the number, or after it"),
+        )
+        .arg(
+            arg("flush_schedule")
+                .long("flush-schedule")
+                .value_name("SCHEDULE")
+                .default_value("every 15s")
+                .help("When to flush output to disk"),
+        )
+        .arg(
+            arg("stale_after")
+                .long("stale-after")
+                .value_name("BARS")
+                .default_value("3")
+                .help("Flags a series as stale when its latest bar hasn't changed for BARS intervals while the market is open"),
+        )
+        .arg(
+            arg("outlier_threshold")
+                .long("outlier-threshold")
+                .value_name("STDDEVS")
+                .default_value("4")
+                .help("Flags the latest return as an outlier when it is more than STDDEVS standard deviations from the mean"),
+        )
+        .arg(
+            arg("max_gap")
+                .long("max-gap")
+                .value_name("BARS")
+                .default_value("5")
+                .help("Flags a series as gapped when consecutive bars are more than BARS intervals apart"),
+        )
+        .arg(
+            arg("quarantine_after")
+                .long("quarantine-after")
+                .value_name("FAILURES")
+                .default_value("5")
+                .help("Quarantines a ticker after FAILURES consecutive fetch failures"),
+        )
+        .arg(
+            arg("quarantine_retry")
+                .long("quarantine-retry")
+                .value_name("SECONDS")
+                .default_value("900")
+                .help("Retries quarantined tickers every SECONDS"),
+        )
+        .arg(
+            arg("watchlist")
+                .long("watchlist")
+                .value_name("FILE")
+                .help("Saves tickers changed through the API to FILE, and loads them from it instead of --ticker or --ticker-file on startup when it exists"),
+        )
+        .arg(
+            arg("report_currency")
+                .long("report-currency")
+                .value_name("CURRENCY")
+                .help("Converts prices to CURRENCY, e.g. USD, using FX rates from the provider"),
+        )
+        .arg(
+            arg("metadata")
+                .long("metadata")
+                .value_name("FILE")
+                .help("Joins name, exchange, currency, sector and industry from a reference CSV into the output"),
+        )
+        .arg(
+            arg("dead_letter")
+                .long("dead-letter")
+                .value_name("FILE")
+                .help("Appends a JSON line to FILE for every failure"),
+        )
+        .arg(
+            arg("record")
+                .long("record")
+                .value_name("DIR")
+                .help("Records every provider request and response to DIR"),
+        )
+        .arg(
+            arg("replay")
+                .long("replay")
+                .value_name("DIR")
+                .help("Replays provider responses recorded to DIR instead of fetching"),
+        )
+        .arg(
+            arg("replay_speed")
+                .long("replay-speed")
+                .value_name("FACTOR")
+                .default_value("1")
+                .help("Speeds up replayed responses by FACTOR"),
+        )
+        .arg(
+            arg("window")
+                .long("window")
+                .value_name("BARS")
+                .default_value("30")
+                .help("Number of bars window-based indicators are calculated over"),
+        )
+        .arg(
+            arg("port")
+                .long("port")
+                .value_name("PORT")
+                .default_value("3030")
+                .help("Port the HTTP API listens on"),
+        )
+        .arg(
+            arg("api_buffer_size")
+                .long("api-buffer-size")
+                .value_name("INDICATORS")
+                .default_value("1023")
+                .help("Number of recent indicators the HTTP API keeps"),
+        )
+        .arg(
+            arg("shutdown_timeout")
+                .long("shutdown-timeout")
+                .value_name("SECONDS")
+                .default_value("30")
+                .help("On SIGINT or SIGTERM, waits up to SECONDS for in-flight data to be written out before exiting"),
+        )
+        .arg(
+            arg(CONFIG)
+                .short("c")
+                .long("config")
+                .value_name("FILE")
+                .help("Reads any setting from a TOML file, e.g. flush-schedule = \"every 1m\", overridden by SIMPLETRACKER_* variables and flags"),
+        )
+}
+
+/// Tickers of a named group, before schedules are applied
+type NamedGroup = (String, Vec<Ticker>);
+
+/// Reads ungrouped tickers and named groups from a ticker file
+fn read_ticker_file(path: &Path) -> Result<(Vec<Ticker>, Vec<NamedGroup>)> {
+    let entries = parse_ticker_file(&read_to_string(path)?)
+        .map_err(|e| ArgumentParsingError::InvalidTickerFile(path.display().to_string(), e))?;
+    let mut tickers = vec![];
+    let mut groups: Vec<NamedGroup> = vec![];
+    for entry in entries {
+        match entry.group {
+            None => tickers.push(entry.ticker),
+            Some(name) => match groups.iter_mut().find(|(n, _)| *n == name) {
+                Some((_, members)) => members.push(entry.ticker),
+                None => groups.push((name, vec![entry.ticker])),
+            },
+        }
+    }
+    Ok((tickers, groups))
+}
+
+/// Tickers listed in `tickers`, whether ungrouped or in a group, but not in `other`
+fn not_in(
+    tickers: &(Vec<Ticker>, Vec<NamedGroup>),
+    other: &(Vec<Ticker>, Vec<NamedGroup>),
+) -> Vec<Ticker> {
+    let all = |(tickers, groups): &(Vec<Ticker>, Vec<NamedGroup>)| {
+        tickers
+            .iter()
+            .chain(groups.iter().flat_map(|(_, members)| members))
+            .cloned()
+            .collect::<Vec<Ticker>>()
+    };
+    let other = all(other);
+    all(tickers)
+        .into_iter()
+        .filter(|ticker| !other.contains(ticker))
+        .collect()
+}
+
+/// Parses each non-blank value as a ticker, so trailing commas and newlines are ignored
+fn parse_tickers<'a>(
+    values: impl Iterator<Item = &'a str>,
+) -> Result<Vec<Ticker>, TickerParseError> {
+    values
+        .filter(|value| !value.trim().is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+/// Splits `tickers` into the default group and any named groups, whether from the ticker file or
+/// `--group`, applying each group's schedule
+fn ticker_groups<'a>(
+    tickers: Vec<Ticker>,
+    file_groups: Vec<NamedGroup>,
+    group_values: impl Iterator<Item = &'a str>,
+    schedule_values: impl Iterator<Item = &'a str>,
+) -> Result<Vec<TickerGroup>, ArgumentParsingError> {
+    let mut groups = vec![TickerGroup::new(
+        DEFAULT_GROUP.to_owned(),
+        tickers,
+        Schedule::default(),
+    )];
+    let mut named_groups = file_groups;
+    for group_value in group_values {
+        let (name, members) = group_value.split_once('=').ok_or_else(|| {
+            ArgumentParsingError::InvalidValue("group".to_owned(), group_value.to_owned())
+        })?;
+        named_groups.push((name.trim().to_owned(), parse_tickers(members.split(','))?));
+    }
+    for (name, members) in named_groups {
+        groups[0].tickers.retain(|ticker| !members.contains(ticker));
+        match groups.iter_mut().find(|group| group.name == name) {
+            Some(group) => group.tickers.extend(members),
+            None => groups.push(TickerGroup::new(name, members, Schedule::default())),
+        }
+    }
+    for schedule_value in schedule_values {
+        let (name, schedule) = match schedule_value.split_once('=') {
+            Some((name, schedule)) => (name.trim(), schedule),
+            None => (DEFAULT_GROUP, schedule_value),
+        };
+        let group = groups
+            .iter_mut()
+            .find(|group| group.name == name)
+            .ok_or_else(|| {
+                ArgumentParsingError::InvalidValue(
+                    "schedule".to_owned(),
+                    format!("unknown group {}", name),
+                )
+            })?;
+        group.schedule = schedule.parse().map_err(|e: ScheduleParseError| {
+            ArgumentParsingError::InvalidValue("schedule".to_owned(), e.to_string())
+        })?;
+    }
+    Ok(groups)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn config_reads_subcommand_with_flags_on_either_side() {
+        let config = |args: &[&str]| {
+            Config::from_args(
+                std::iter::once("simpletracker").chain(args.iter().copied()),
+                vec![],
+            )
+            .unwrap()
+        };
+        let serve = config(&["--ticker", "MSFT", "--from", "2021-01-01"]);
+        assert_eq!(serve.command, Command::Serve);
+
+        let report = config(&["--from", "2021-01-01", "report", "--ticker", "MSFT"]);
+        assert_eq!(report.command, Command::Report);
+        assert_eq!(report.groups[0].tickers, vec![Ticker::from("MSFT")]);
+        assert_eq!(
+            report.from,
+            Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap()
+        );
+
+        let backfill = config(&[
+            "backfill",
+            "--ticker",
+            "MSFT",
+            "--from",
+            "2021-01-01",
+            "--until",
+            "2021-02-01",
+        ]);
+        assert_eq!(backfill.command, Command::Backfill);
+        assert_eq!(
+            backfill.until,
+            Some(Utc.with_ymd_and_hms(2021, 2, 1, 0, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn config_loads_an_existing_watchlist_instead_of_tickers() {
+        let dir = tempfile::tempdir().unwrap();
+        let watchlist = dir.path().join("watchlist.txt");
+        let config = |args: &[&str]| {
+            Config::from_args(
+                ["simpletracker", "--from", "2021-01-01", "--watchlist"]
+                    .iter()
+                    .copied()
+                    .chain(std::iter::once(watchlist.to_str().unwrap()))
+                    .chain(args.iter().copied()),
+                vec![],
+            )
+        };
+        assert!(config(&[]).is_err());
+        assert_eq!(
+            config(&["--ticker", "MSFT"]).unwrap().groups[0].tickers,
+            vec![Ticker::from("MSFT")]
+        );
+
+        std::fs::write(&watchlist, "IBM\n[tech]\nMSFT\n").unwrap();
+        let config = config(&["--ticker", "MSFT", "--ticker", "AAPL"]).unwrap();
+        assert_eq!(config.groups[0].tickers, vec![Ticker::from("IBM")]);
+        assert_eq!(config.groups[1].tickers, vec![Ticker::from("MSFT")]);
+    }
+
+    #[test]
+    fn not_in_lists_tickers_missing_from_any_group() {
+        let given = (
+            vec![Ticker::from("MSFT"), Ticker::from("AAPL")],
+            vec![("energy".to_owned(), vec![Ticker::from("XOM")])],
+        );
+        let saved = (
+            vec![Ticker::from("IBM")],
+            vec![("tech".to_owned(), vec![Ticker::from("MSFT")])],
+        );
+        assert_eq!(
+            not_in(&given, &saved),
+            vec![Ticker::from("AAPL"), Ticker::from("XOM")]
+        );
+    }
+
+    #[test]
+    fn config_layers_file_env_and_flags() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tracker.toml");
+        std::fs::write(
+            &path,
+            "ticker = [\"MSFT\", \"AAPL\"]\nfrom = 2021-01-01T00:00:00Z\nwindow = 20\nport = 8080\n",
+        )
+        .unwrap();
+        let args = vec![
+            "simpletracker",
+            "--config",
+            path.to_str().unwrap(),
+            "--port",
+            "9090",
+        ];
+        let env = vec![("SIMPLETRACKER_WINDOW".to_owned(), "10".to_owned())];
+
+        let config = Config::from_args(args.clone(), env).unwrap();
+        assert_eq!(
+            config.groups[0].tickers,
+            vec![Ticker::from("MSFT"), Ticker::from("AAPL")]
+        );
+        assert_eq!(
+            config.from,
+            Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap()
+        );
+        assert_eq!(config.window, 10);
+        assert_eq!(config.port, 9090);
+        assert_eq!(config.api_buffer_size, 1023);
+
+        let env = vec![("SIMPLETRACKER_PORT".to_owned(), "http".to_owned())];
+        let args = vec!["simpletracker", "--config", path.to_str().unwrap()];
+        assert_eq!(
+            Config::from_args(args, env).unwrap_err().to_string(),
+            "Invalid value for SIMPLETRACKER_PORT: invalid digit found in string"
+        );
+    }
+
+    #[test]
+    fn config_reads_intervals_and_rejects_invalid_ones() {
+        let config = |args: &[&str]| {
+            Config::from_args(
+                ["simpletracker", "--ticker", "MSFT", "--from", "2021-01-01"]
+                    .iter()
+                    .chain(args.iter())
+                    .copied(),
+                vec![],
+            )
+        };
+        assert_eq!(config(&[]).unwrap().interval, Interval::OneDay);
+        assert_eq!(
+            config(&["--interval", "1h"]).unwrap().interval,
+            Interval::OneHour
+        );
+        assert_eq!(
+            config(&["--off-hours-interval", "0"])
+                .unwrap_err()
+                .to_string(),
+            "Invalid value for --off-hours-interval: must be positive"
+        );
+    }
+
+    #[test]
+    fn config_reads_parquet_options_and_requires_an_output_file() {
+        let config = |args: &[&str]| {
+            Config::from_args(
+                ["simpletracker", "--ticker", "MSFT", "--from", "2021-01-01"]
+                    .iter()
+                    .chain(args.iter())
+                    .copied(),
+                vec![],
+            )
+        };
+        let parquet = config(&[
+            "--output-format",
+            "parquet",
+            "-o",
+            "out.parquet",
+            "--rollover-interval",
+            "3600",
+        ])
+        .unwrap();
+        assert_eq!(parquet.output_format, OutputFormat::Parquet);
+        assert_eq!(
+            parquet.parquet,
+            ParquetOptions {
+                rollover_interval: Some(chrono::Duration::hours(1)),
+                ..ParquetOptions::default()
+            }
+        );
+
+        assert_eq!(
+            config(&["--output-format", "parquet"])
+                .unwrap_err()
+                .to_string(),
+            "Invalid value for --output-format: parquet needs --output"
+        );
+        assert_eq!(
+            config(&["--batch-rows", "0"]).unwrap_err().to_string(),
+            "Invalid value for --batch-rows: must be positive"
+        );
+    }
+
+    #[test]
+    fn config_reads_file_options_for_text_output_files_only() {
+        let config = |args: &[&str]| {
+            Config::from_args(
+                ["simpletracker", "--ticker", "MSFT", "--from", "2021-01-01"]
+                    .iter()
+                    .chain(args.iter())
+                    .copied(),
+                vec![],
+            )
+        };
+        let rotated = config(&[
+            "-o",
+            "out.csv",
+            "--append",
+            "--rotate-size",
+            "100",
+            "--rotate-every",
+            "daily",
+            "--gzip-rotated",
+        ])
+        .unwrap();
+        assert_eq!(
+            rotated.file_options,
+            FileOptions {
+                append: true,
+                rotate_size: Some(100 * 1024 * 1024),
+                rotate_every: "daily".parse().ok(),
+                gzip: true,
+            }
+        );
+
+        let invalid = |args: &[&str]| config(args).unwrap_err().to_string();
+        assert_eq!(
+            invalid(&["--append"]),
+            "Invalid value for --append: needs --output"
+        );
+        assert_eq!(
+            invalid(&["-o", "out.json", "--output-format", "json", "--append"]),
+            "Invalid value for --append: can't continue a closed JSON array"
+        );
+        assert_eq!(
+            invalid(&["-o", "out.csv", "--gzip-rotated"]),
+            "Invalid value for --gzip-rotated: needs --rotate-size or --rotate-every"
+        );
+        assert_eq!(
+            invalid(&["-o", "out.parquet", "--output-format", "parquet", "--rotate-every", "hourly"]),
+            "Invalid value for --rotate-every: isn't supported by parquet, see --rollover-rows and --rollover-interval"
+        );
+    }
+
+    #[test]
+    fn ticker_groups_moves_grouped_tickers_out_of_default_group() {
+        let groups = ticker_groups(
+            vec![Ticker::from("MSFT"), Ticker::from("IBM")],
+            vec![("energy".to_owned(), vec![Ticker::from("XOM")])],
+            vec!["tech=MSFT,AAPL"].into_iter(),
+            vec!["every 1m", "tech=every 5m"].into_iter(),
+        )
+        .unwrap();
+        let members: Vec<(&str, Vec<Ticker>)> = groups
+            .iter()
+            .map(|group| (group.name.as_str(), group.tickers.clone()))
+            .collect();
+        assert_eq!(
+            members,
+            vec![
+                (DEFAULT_GROUP, vec![Ticker::from("IBM")]),
+                ("energy", vec![Ticker::from("XOM")]),
+                ("tech", vec![Ticker::from("MSFT"), Ticker::from("AAPL")])
+            ]
+        );
+        let now = Utc::now();
+        assert_eq!(
+            groups[2].schedule.next_after(now),
+            Some(now + chrono::Duration::minutes(5))
+        );
+    }
+
+    #[test]
+    fn ticker_groups_rejects_schedule_for_unknown_group() {
+        assert!(ticker_groups(
+            vec![Ticker::from("MSFT")],
+            vec![],
+            vec![].into_iter(),
+            vec!["tech=every 5m"].into_iter(),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn parse_tickers_normalises_values_and_skips_blanks() {
+        let tickers = parse_tickers("msft, brk-b,\nVOD.L,\n".split(',')).unwrap();
+        assert_eq!(
+            tickers,
+            vec![
+                Ticker::from("MSFT"),
+                Ticker::from("BRK.B"),
+                Ticker::from("VOD.L")
+            ]
+        );
+        assert!(parse_tickers("MSFT,MS FT".split(',')).is_err());
+    }
+}
@@ -0,0 +1,130 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use std::convert::TryFrom;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("Invalid date {0}, expected e.g. 30d, 6mo, 1y, ytd, 2020-01-01 or 2020-01-01T00:00:00Z")]
+pub struct DateParseError(String);
+
+/// Parses an RFC 3339 timestamp, a plain date as midnight UTC, `now`, `ytd` as the start of the
+/// year, or a number of hours, days, weeks, months or years before `now`, e.g. `12h`, `30d`,
+/// `2w`, `6mo` or `1y`
+pub fn parse_date(s: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, DateParseError> {
+    let s = s.trim();
+    let invalid = || DateParseError(s.to_owned());
+    if let Ok(date) = DateTime::parse_from_rfc3339(s) {
+        return Ok(date.with_timezone(&Utc));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Ok(Utc.from_utc_datetime(&date.and_time(NaiveTime::MIN)));
+    }
+    match s.to_lowercase().as_str() {
+        "now" => return Ok(now),
+        "ytd" => return Ok(Utc.with_ymd_and_hms(now.year(), 1, 1, 0, 0, 0).unwrap()),
+        _ => {}
+    }
+
+    let split = s.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+    let count: i64 = s[..split].parse().map_err(|_| invalid())?;
+    let before =
+        |duration: Option<Duration>| duration.and_then(|duration| now.checked_sub_signed(duration));
+    match s[split..].to_lowercase().as_str() {
+        "h" => before(Duration::try_hours(count)),
+        "d" => before(Duration::try_days(count)),
+        "w" => before(Duration::try_weeks(count)),
+        "mo" => months_before(now, count),
+        "y" => count
+            .checked_mul(12)
+            .and_then(|months| months_before(now, months)),
+        _ => None,
+    }
+    .ok_or_else(invalid)
+}
+
+/// The same time `months` calendar months before `at`, on the last day of the month if the day
+/// doesn't exist in it, e.g. one month before 31 March is 28 or 29 February
+fn months_before(at: DateTime<Utc>, months: i64) -> Option<DateTime<Utc>> {
+    let total = (i64::from(at.year()) * 12 + i64::from(at.month0())).checked_sub(months)?;
+    let year = i32::try_from(total.div_euclid(12)).ok()?;
+    let month = total.rem_euclid(12) as u32 + 1;
+    let first_of_next = match month {
+        12 => NaiveDate::from_ymd_opt(year + 1, 1, 1)?,
+        _ => NaiveDate::from_ymd_opt(year, month + 1, 1)?,
+    };
+    let day = at.day().min(first_of_next.pred_opt()?.day());
+    let date = NaiveDate::from_ymd_opt(year, month, day)?;
+    Some(Utc.from_utc_datetime(&date.and_time(at.time())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_date_accepts_absolute_dates() {
+        let now = Utc::now();
+        assert_eq!(
+            parse_date("2020-01-01", now),
+            Ok(Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap())
+        );
+        assert_eq!(
+            parse_date("2020-01-01T14:30:00+01:00", now),
+            Ok(Utc.with_ymd_and_hms(2020, 1, 1, 13, 30, 0).unwrap())
+        );
+        assert_eq!(parse_date("now", now), Ok(now));
+    }
+
+    #[test]
+    fn parse_date_counts_relative_dates_back_from_now() {
+        let now = Utc.with_ymd_and_hms(2021, 3, 31, 16, 0, 0).unwrap();
+        assert_eq!(
+            parse_date("30d", now),
+            Ok(Utc.with_ymd_and_hms(2021, 3, 1, 16, 0, 0).unwrap())
+        );
+        assert_eq!(
+            parse_date("2w", now),
+            Ok(Utc.with_ymd_and_hms(2021, 3, 17, 16, 0, 0).unwrap())
+        );
+        assert_eq!(
+            parse_date("1mo", now),
+            Ok(Utc.with_ymd_and_hms(2021, 2, 28, 16, 0, 0).unwrap())
+        );
+        assert_eq!(
+            parse_date("6MO", now),
+            Ok(Utc.with_ymd_and_hms(2020, 9, 30, 16, 0, 0).unwrap())
+        );
+        assert_eq!(
+            parse_date("1y", now),
+            Ok(Utc.with_ymd_and_hms(2020, 3, 31, 16, 0, 0).unwrap())
+        );
+        assert_eq!(
+            parse_date("ytd", now),
+            Ok(Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn parse_date_rejects_unknown_expressions() {
+        let now = Utc::now();
+        for s in ["", "d", "30", "30m", "-3d", "2020-13-01", "yesterday"].iter() {
+            assert_eq!(parse_date(s, now), Err(DateParseError(s.to_string())));
+        }
+    }
+
+    #[test]
+    fn parse_date_rejects_dates_out_of_range() {
+        let now = Utc::now();
+        for s in [
+            "9223372036854775807h",
+            "999999999999d",
+            "999999999999w",
+            "999999999999mo",
+            "9223372036854775807mo",
+            "999999999999999999y",
+        ]
+        .iter()
+        {
+            assert_eq!(parse_date(s, now), Err(DateParseError(s.to_string())));
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use lib::ticker::Ticker;
+use log::{error, warn};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+
+pub type SharedFailures = Arc<FailureTracker>;
+
+/// Where in the pipeline a failure happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FailureSource {
+    Fetch,
+    Output,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FailureRecord {
+    ticker: Ticker,
+    source: FailureSource,
+    last_error: String,
+    consecutive_failures: u32,
+    first_failure: DateTime<Utc>,
+    last_failure: DateTime<Utc>,
+    quarantined: bool,
+}
+
+/// A line of the dead-letter file
+#[derive(Serialize)]
+struct DeadLetter<'a> {
+    time: DateTime<Utc>,
+    ticker: &'a Ticker,
+    source: FailureSource,
+    error: &'a str,
+    consecutive_failures: u32,
+}
+
+/// Tracks consecutive failures per ticker and source, shared between actors and the API. Tickers
+/// that fail to fetch `quarantine_after` times in a row are quarantined and only retried every
+/// `quarantine_retry`. Output failures are tracked but never quarantine a ticker, as skipping
+/// its fetches wouldn't fix the output.
+#[derive(Debug)]
+pub struct FailureTracker {
+    records: RwLock<HashMap<(Ticker, FailureSource), FailureRecord>>,
+    dead_letter: Option<Mutex<File>>,
+    quarantine_after: u32,
+    quarantine_retry: Duration,
+}
+
+impl Default for FailureTracker {
+    fn default() -> Self {
+        FailureTracker::new(5, Duration::minutes(15))
+    }
+}
+
+impl FailureTracker {
+    pub fn new(quarantine_after: u32, quarantine_retry: Duration) -> Self {
+        FailureTracker {
+            records: RwLock::new(HashMap::new()),
+            dead_letter: None,
+            quarantine_after,
+            quarantine_retry,
+        }
+    }
+
+    /// Appends every failure as a line of JSON to the file at `path`
+    pub fn with_dead_letter(mut self, path: &Path) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        self.dead_letter = Some(Mutex::new(file));
+        Ok(self)
+    }
+
+    pub fn record_failure(
+        &self,
+        ticker: &Ticker,
+        source: FailureSource,
+        err: impl Display,
+        at: DateTime<Utc>,
+    ) {
+        let mut records = self.records.write().unwrap();
+        let record = records
+            .entry((ticker.clone(), source))
+            .or_insert_with(|| FailureRecord {
+                ticker: ticker.clone(),
+                source,
+                last_error: String::new(),
+                consecutive_failures: 0,
+                first_failure: at,
+                last_failure: at,
+                quarantined: false,
+            });
+        record.last_error = err.to_string();
+        record.consecutive_failures += 1;
+        record.last_failure = at;
+        if source == FailureSource::Fetch
+            && !record.quarantined
+            && record.consecutive_failures >= self.quarantine_after
+        {
+            warn!(
+                "Quarantining {} after {} consecutive failures",
+                ticker, record.consecutive_failures
+            );
+            record.quarantined = true;
+        }
+        self.write_dead_letter(record);
+    }
+
+    /// Clears the failure record of a ticker from `source`, lifting any quarantine
+    pub fn record_success(&self, ticker: &Ticker, source: FailureSource) {
+        let key = (ticker.clone(), source);
+        if !self.records.read().unwrap().contains_key(&key) {
+            return;
+        }
+        let mut records = self.records.write().unwrap();
+        if records
+            .remove(&key)
+            .is_some_and(|record| record.quarantined)
+        {
+            warn!("Lifting quarantine of {}", ticker);
+        }
+    }
+
+    /// Whether `ticker` should be fetched at `at`, i.e. it isn't quarantined or is due a retry
+    pub fn should_attempt(&self, ticker: &Ticker, at: DateTime<Utc>) -> bool {
+        self.records
+            .read()
+            .unwrap()
+            .get(&(ticker.clone(), FailureSource::Fetch))
+            .is_none_or(|record| {
+                !record.quarantined || at - record.last_failure >= self.quarantine_retry
+            })
+    }
+
+    /// Failure records ordered by ticker, then source
+    pub fn records(&self) -> Vec<FailureRecord> {
+        let mut records: Vec<FailureRecord> =
+            self.records.read().unwrap().values().cloned().collect();
+        records.sort_by(|a, b| (a.ticker.as_str(), a.source).cmp(&(b.ticker.as_str(), b.source)));
+        records
+    }
+
+    fn write_dead_letter(&self, record: &FailureRecord) {
+        let file = match &self.dead_letter {
+            Some(file) => file,
+            None => return,
+        };
+        let line = DeadLetter {
+            time: record.last_failure,
+            ticker: &record.ticker,
+            source: record.source,
+            error: &record.last_error,
+            consecutive_failures: record.consecutive_failures,
+        };
+        let mut file = file.lock().unwrap();
+        let written = serde_json::to_string(&line)
+            .map_err(std::io::Error::from)
+            .and_then(|json| writeln!(file, "{}", json))
+            .and_then(|_| file.flush());
+        if let Err(e) = written {
+            error!("Failed to write dead letter: {:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::fs::read_to_string;
+
+    #[test]
+    fn failure_tracker_quarantines_after_consecutive_failures_and_retries_later() {
+        let tracker = FailureTracker::new(2, Duration::minutes(15));
+        let ticker = Ticker::from("TEST");
+        let at = Utc.with_ymd_and_hms(2021, 8, 10, 12, 0, 0).unwrap();
+
+        tracker.record_failure(&ticker, FailureSource::Fetch, "timeout", at);
+        assert!(tracker.should_attempt(&ticker, at));
+        tracker.record_failure(&ticker, FailureSource::Fetch, "timeout", at);
+        assert!(!tracker.should_attempt(&ticker, at + Duration::minutes(14)));
+        assert!(tracker.should_attempt(&ticker, at + Duration::minutes(15)));
+
+        let records = tracker.records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].consecutive_failures, 2);
+        assert!(records[0].quarantined);
+
+        tracker.record_success(&ticker, FailureSource::Fetch);
+        assert!(tracker.records().is_empty());
+        assert!(tracker.should_attempt(&ticker, at));
+    }
+
+    #[test]
+    fn failure_tracker_only_quarantines_on_fetch_failures() {
+        let tracker = FailureTracker::new(2, Duration::minutes(15));
+        let ticker = Ticker::from("TEST");
+        let at = Utc.with_ymd_and_hms(2021, 8, 10, 12, 0, 0).unwrap();
+
+        for _ in 0..3 {
+            tracker.record_failure(&ticker, FailureSource::Output, "io", at);
+        }
+        assert!(tracker.should_attempt(&ticker, at));
+        tracker.record_failure(&ticker, FailureSource::Fetch, "timeout", at);
+
+        let records = tracker.records();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].source, FailureSource::Fetch);
+        assert_eq!(records[0].consecutive_failures, 1);
+        assert_eq!(records[1].source, FailureSource::Output);
+        assert_eq!(records[1].consecutive_failures, 3);
+        assert!(!records[1].quarantined);
+
+        tracker.record_success(&ticker, FailureSource::Fetch);
+        assert_eq!(tracker.records().len(), 1);
+        tracker.record_success(&ticker, FailureSource::Output);
+        assert!(tracker.records().is_empty());
+    }
+
+    #[test]
+    fn failure_tracker_appends_failures_to_dead_letter_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dead-letter.jsonl");
+        let tracker = FailureTracker::default().with_dead_letter(&path).unwrap();
+        let at = Utc.with_ymd_and_hms(2021, 8, 10, 12, 0, 0).unwrap();
+
+        tracker.record_failure(&Ticker::from("A"), FailureSource::Fetch, "timeout", at);
+        tracker.record_failure(&Ticker::from("B"), FailureSource::Output, "io", at);
+
+        let lines: Vec<serde_json::Value> = read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["ticker"], "A");
+        assert_eq!(lines[0]["error"], "timeout");
+        assert_eq!(lines[1]["source"], "output");
+        assert_eq!(lines[1]["consecutive_failures"], 1);
+    }
+}
@@ -1,41 +1,91 @@
 use crate::actors::{
-    messages::{Fetch, PerformanceIndicators},
-    FetchActor, InMemoryQuoteWriter, OutputActor, PerformanceActor,
+    messages::{Backfill, Bars, Drain, Fetch, PerformanceIndicators},
+    FetchActor, InMemoryQuoteWriter, OutputActor, PerformanceActor, QualityActor, SqliteWriter,
 };
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use bounded_vec_deque::BoundedVecDeque;
-use std::{
-    fs::File,
-    io::{BufWriter, Write},
-    sync::{Arc, RwLock},
-};
+use chrono::Utc;
+use config::{Command, Config};
+use failures::FailureTracker;
+use futures::channel::oneshot;
+use lib::quote_provider::QuoteProvider;
+use log::info;
+use metrics::Metrics;
+use std::sync::{Arc, RwLock};
+use store::Store;
+use warp::Filter;
 use xactor::{Broker, Service, Supervisor};
 mod actors;
 mod api;
 mod config;
+mod dates;
+mod failures;
+mod metadata;
+mod metrics;
+mod providers;
 mod read_optimised_circular_buffer;
+mod schedule;
+mod settings;
+mod shutdown;
+mod sinks;
+mod store;
+mod ticker_file;
 
-static MAX_API_BUFFER_SIZE: usize = 1023;
 #[async_std::main]
 async fn main() -> Result<()> {
     env_logger::init();
-    let config = config::Config::new()?;
+    let config = Config::new()?;
+    lib::formatter::set_format_options(config.format_options);
+    if config.command == Command::Validate {
+        return validate(&config);
+    }
+
+    let mut failures = FailureTracker::new(config.quarantine_after, config.quarantine_retry);
+    if let Some(dead_letter) = &config.dead_letter {
+        failures = failures.with_dead_letter(dead_letter)?;
+    }
+    let failures = Arc::new(failures);
 
-    let pth = config.file.clone();
+    let output_sink = sinks::SharedSink::new(sinks::from_config(&config)?);
+    let output_failures = failures.clone();
+    let flush_schedule = config.flush_schedule.clone();
+    let number_format = config.number_format;
     let output_actor_addr = Supervisor::start(move || {
-        let writer: Box<dyn Write + Send> = match &pth {
-            Some(pth) => File::create(pth).map(BufWriter::new).map(Box::new).unwrap(),
-            None => Box::new(std::io::stdout()),
-        };
-        let output_actor: OutputActor<_, PerformanceIndicators> = OutputActor::new(writer);
+        let output_actor: OutputActor<PerformanceIndicators> =
+            OutputActor::new(Box::new(output_sink.clone()))
+                .with_flush_schedule(flush_schedule.clone())
+                .with_number_format(number_format)
+                .with_failures(output_failures.clone(), |indicators| {
+                    indicators.ticker().clone()
+                });
         output_actor
     })
     .await?;
 
     let read_optimised_in_memory_store =
-        Arc::new(RwLock::new(BoundedVecDeque::new(MAX_API_BUFFER_SIZE)));
+        Arc::new(RwLock::new(BoundedVecDeque::new(config.api_buffer_size)));
+
+    let metrics = Arc::new(Metrics::default());
+    let route = api::get_n_indicators(read_optimised_in_memory_store.clone())
+        .or(api::get_sector_performance(
+            read_optimised_in_memory_store.clone(),
+        ))
+        .or(api::get_metrics(metrics.clone()))
+        .or(api::get_ticker_status(failures.clone()));
 
-    let route = api::get_n_indicators(read_optimised_in_memory_store.clone());
+    let store = match &config.sqlite {
+        Some(path) => Some(Arc::new(Store::open(path)?)),
+        None => None,
+    };
+    let route = route.or(api::get_history(store.clone(), config.interval));
+    let sqlite_bars = config.sqlite_bars;
+    let sqlite_writer_addr = match store {
+        Some(store) => Some(
+            Supervisor::start(move || SqliteWriter::new(store.clone()).with_bars(sqlite_bars))
+                .await?,
+        ),
+        None => None,
+    };
 
     let _deque_actor_addr =
         Supervisor::start(move || InMemoryQuoteWriter::new(read_optimised_in_memory_store.clone()))
@@ -43,23 +93,116 @@ async fn main() -> Result<()> {
 
     let broker = Broker::from_registry().await?;
 
-    let performance_actor_addr =
-        Supervisor::start(move || PerformanceActor::new(broker.clone())).await?;
+    let metadata = Arc::new(match &config.metadata {
+        Some(path) => metadata::load_metadata(path)?,
+        None => Default::default(),
+    });
+    let performance_metadata = metadata.clone();
+    let performance_actor_addr = Supervisor::start(move || {
+        PerformanceActor::new(broker.clone()).with_metadata(performance_metadata.clone())
+    })
+    .await?;
+
+    let quality = config.quality;
+    let quality_performance_actor_addr = performance_actor_addr.clone();
+    let quality_actor_addr = Supervisor::start(move || {
+        QualityActor::new(
+            quality_performance_actor_addr.clone(),
+            quality,
+            metrics.clone(),
+        )
+    })
+    .await?;
 
+    let port = config.port;
+    let command = config.command;
+    let config_until = config.until;
+    let shutdown_timeout = config.shutdown_timeout;
+    let bounded = command != Command::Serve || config.until.is_some();
+    let fetch_quality_actor_addr = quality_actor_addr.clone();
+    let provider: Arc<dyn QuoteProvider + Send + Sync> = providers::from_config(&config)?.into();
+    let bar_broker = if config.sqlite_bars {
+        Some(Broker::<Bars>::from_registry().await?)
+    } else {
+        None
+    };
     let fetch_actor_addr = Supervisor::start(move || {
-        let provider = yahoo_finance_api::YahooConnector::new();
         FetchActor::new(
-            performance_actor_addr.clone(),
-            provider,
-            config.tickers.clone(),
+            fetch_quality_actor_addr.clone(),
+            provider.clone(),
+            vec![],
             config.from,
         )
+        .with_groups(config.groups.clone())
+        .with_until(config.until)
+        .with_interval(config.interval)
+        .with_window(config.window)
+        .with_calendar(config.calendar.clone(), config.off_hours_interval)
+        .with_failures(failures.clone())
+        .with_watchlist(config.watchlist.clone())
+        .with_report_currency(config.report_currency, metadata.clone())
+        .with_bars(bar_broker.clone())
     })
     .await?;
+    let route = route.or(api::watchlist(Arc::new(api::Watchlist::new(
+        &fetch_actor_addr,
+    ))));
 
-    fetch_actor_addr.call(Fetch::new()).await?;
+    match command {
+        Command::Backfill => {
+            fetch_actor_addr
+                .call(Backfill::new(config_until.unwrap_or_else(Utc::now)))
+                .await?
+        }
+        _ => fetch_actor_addr.call(Fetch::new()).await?,
+    }
+
+    let stages = [
+        fetch_actor_addr.caller::<Drain>(),
+        quality_actor_addr.caller::<Drain>(),
+        performance_actor_addr.caller::<Drain>(),
+    ];
+    let mut sinks = vec![output_actor_addr.caller::<Drain>()];
+    sinks.extend(sqlite_writer_addr.iter().map(|addr| addr.caller::<Drain>()));
+    if bounded {
+        return shutdown::drain(&stages, &sinks).await;
+    }
+
+    let (shutdown_started, shutdown_requested) = oneshot::channel::<()>();
+    let (_, server) =
+        warp::serve(route).bind_with_graceful_shutdown(([127, 0, 0, 1], port), async {
+            shutdown_requested.await.ok();
+        });
+    let server = async_std::task::spawn(server);
+    shutdown::signal().await;
+    info!("Shutting down, waiting up to {:?}", shutdown_timeout);
+    shutdown_started.send(()).ok();
+    let shutdown = async {
+        server.await;
+        shutdown::drain(&stages, &sinks).await
+    };
+    match async_std::future::timeout(shutdown_timeout, shutdown).await {
+        Ok(drained) => drained,
+        Err(_) => Err(anyhow!(
+            "Timed out after {:?} before all output was written",
+            shutdown_timeout
+        )),
+    }
+}
 
-    warp::serve(route).run(([127, 0, 0, 1], 3030)).await;
-    output_actor_addr.wait_for_stop().await;
+/// Loads everything a run would without fetching, reporting what was found
+fn validate(config: &Config) -> Result<()> {
+    let metadata = match &config.metadata {
+        Some(path) => metadata::load_metadata(path)?,
+        None => Default::default(),
+    };
+    providers::from_config(config)?;
+    let tickers: usize = config.groups.iter().map(|group| group.tickers.len()).sum();
+    println!(
+        "{} tickers in {} groups, {} with metadata",
+        tickers,
+        config.groups.len(),
+        metadata.len()
+    );
     Ok(())
 }
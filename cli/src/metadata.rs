@@ -0,0 +1,111 @@
+use anyhow::{anyhow, Result};
+use lib::currency::Currency;
+use lib::ticker::{Ticker, TickerMetadata};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+pub type SharedMetadata = Arc<HashMap<Ticker, TickerMetadata>>;
+
+/// A row of the reference CSV: `symbol,name,exchange,currency,sector,industry`
+#[derive(Deserialize)]
+struct MetadataRow {
+    symbol: String,
+    name: Option<String>,
+    exchange: Option<String>,
+    currency: Option<String>,
+    sector: Option<String>,
+    industry: Option<String>,
+}
+
+/// Loads ticker metadata from a reference CSV. Only the `symbol` column is required.
+pub fn load_metadata(path: &Path) -> Result<HashMap<Ticker, TickerMetadata>> {
+    let mut reader = csv::Reader::from_path(path)?;
+    let mut metadata = HashMap::new();
+    for (row, record) in reader.deserialize::<MetadataRow>().enumerate() {
+        // Line 1 is the header
+        let line = row + 2;
+        let record = record.map_err(|e| anyhow!("{}:{}: {}", path.display(), line, e))?;
+        let ticker: Ticker = record
+            .symbol
+            .parse()
+            .map_err(|e| anyhow!("{}:{}: {}", path.display(), line, e))?;
+        metadata.insert(
+            ticker,
+            TickerMetadata {
+                name: record.name,
+                exchange: record.exchange,
+                currency: record.currency,
+                sector: record.sector,
+                industry: record.industry,
+            },
+        );
+    }
+    Ok(metadata)
+}
+
+/// The currency a ticker trades in, from its metadata, else its exchange suffix, else USD
+pub fn currency_of(ticker: &Ticker, metadata: Option<&TickerMetadata>) -> Currency {
+    metadata
+        .and_then(|metadata| metadata.currency.as_deref())
+        .and_then(|currency| currency.parse().ok())
+        .or_else(|| {
+            ticker
+                .exchange_suffix()
+                .and_then(Currency::for_exchange_suffix)
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn load_metadata_reads_reference_csv_with_optional_columns() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            file,
+            "symbol,name,exchange,currency,sector,industry\n\
+             msft,Microsoft Corp,NASDAQ,USD,Information Technology,Systems Software\n\
+             BRK-B,Berkshire Hathaway,NYSE,USD,,\n"
+        )
+        .unwrap();
+
+        let metadata = load_metadata(file.path()).unwrap();
+        assert_eq!(
+            metadata[&Ticker::from("MSFT")].sector.as_deref(),
+            Some("Information Technology")
+        );
+        assert_eq!(metadata[&Ticker::from("BRK.B")].sector, None);
+        assert_eq!(
+            metadata[&Ticker::from("BRK.B")].exchange.as_deref(),
+            Some("NYSE")
+        );
+    }
+
+    #[test]
+    fn load_metadata_reports_line_of_invalid_symbol() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(file, "symbol,name\nMSFT,Microsoft\nMS FT,Typo\n").unwrap();
+
+        let err = load_metadata(file.path()).unwrap_err().to_string();
+        assert!(err.contains(":3:"), "{}", err);
+    }
+
+    #[test]
+    fn currency_of_prefers_metadata_over_exchange_suffix() {
+        let metadata = TickerMetadata {
+            currency: Some("USD".to_owned()),
+            ..TickerMetadata::default()
+        };
+        assert_eq!(
+            currency_of(&Ticker::from("VOD.L"), Some(&metadata)),
+            Currency::USD
+        );
+        assert_eq!(currency_of(&Ticker::from("VOD.L"), None), Currency::GBX);
+        assert_eq!(currency_of(&Ticker::from("MSFT"), None), Currency::USD);
+    }
+}
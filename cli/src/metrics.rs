@@ -0,0 +1,46 @@
+use lib::quality::QualityFlag;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+pub type SharedMetrics = Arc<Metrics>;
+
+/// Counters shared between actors and exposed through the API
+#[derive(Debug, Default)]
+pub struct Metrics {
+    series_checked: AtomicU64,
+    stale_series: AtomicU64,
+    gapped_series: AtomicU64,
+    outlier_series: AtomicU64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MetricsSnapshot {
+    series_checked: u64,
+    stale_series: u64,
+    gapped_series: u64,
+    outlier_series: u64,
+}
+
+impl Metrics {
+    pub fn record_quality(&self, flags: &[QualityFlag]) {
+        self.series_checked.fetch_add(1, Ordering::Relaxed);
+        for flag in flags {
+            let counter = match flag {
+                QualityFlag::Stale => &self.stale_series,
+                QualityFlag::Gapped => &self.gapped_series,
+                QualityFlag::Outlier => &self.outlier_series,
+            };
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            series_checked: self.series_checked.load(Ordering::Relaxed),
+            stale_series: self.stale_series.load(Ordering::Relaxed),
+            gapped_series: self.gapped_series.load(Ordering::Relaxed),
+            outlier_series: self.outlier_series.load(Ordering::Relaxed),
+        }
+    }
+}
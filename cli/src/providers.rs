@@ -0,0 +1,28 @@
+mod file;
+mod replay;
+mod synthetic;
+mod yahoo;
+
+pub use file::FileProvider;
+pub use replay::{RecordingProvider, ReplayProvider};
+pub use synthetic::SyntheticProvider;
+pub use yahoo::YahooProvider;
+
+use crate::config::{Config, ProviderConfig};
+use anyhow::Result;
+use lib::quote_provider::QuoteProvider;
+
+pub fn from_config(config: &Config) -> Result<Box<dyn QuoteProvider + Send + Sync>> {
+    let provider: Box<dyn QuoteProvider + Send + Sync> = match &config.provider {
+        ProviderConfig::Yahoo => Box::new(YahooProvider::new()),
+        ProviderConfig::File { data_dir } => Box::new(FileProvider::new(data_dir.clone())),
+        ProviderConfig::Synthetic(synthetic) => Box::new(SyntheticProvider::new(synthetic.clone())),
+        ProviderConfig::Replay { dir, speed } => {
+            Box::new(ReplayProvider::new(dir.clone(), *speed)?)
+        }
+    };
+    Ok(match &config.record {
+        Some(dir) => Box::new(RecordingProvider::new(provider, dir.clone())?),
+        None => provider,
+    })
+}
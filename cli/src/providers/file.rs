@@ -0,0 +1,164 @@
+use async_trait::async_trait;
+use chrono::prelude::*;
+use lib::quote_provider::{Bar, Interval, ProviderError, QuoteProvider};
+use lib::ticker::Ticker;
+use serde::Deserialize;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+/// Serves bars from per-ticker OHLCV files. Bars for an interval are read from
+/// `<data_dir>/<interval>/<TICKER>.csv` when present, otherwise from `<data_dir>/<TICKER>.csv`.
+/// Both lower-case headers and Yahoo's CSV export headers are accepted.
+pub struct FileProvider {
+    data_dir: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct FileRow {
+    #[serde(alias = "Date")]
+    date: String,
+    #[serde(alias = "Open")]
+    open: f64,
+    #[serde(alias = "High")]
+    high: f64,
+    #[serde(alias = "Low")]
+    low: f64,
+    #[serde(alias = "Close")]
+    close: f64,
+    #[serde(alias = "Adj Close", default)]
+    adjclose: Option<f64>,
+    #[serde(alias = "Volume", default)]
+    volume: Option<u64>,
+}
+
+impl FileProvider {
+    pub fn new(data_dir: PathBuf) -> Self {
+        FileProvider { data_dir }
+    }
+
+    fn read_bars(&self, ticker: &Ticker, interval: Interval) -> Result<Vec<Bar>, ProviderError> {
+        let file_name = format!("{}.csv", ticker);
+        let interval_path = self.data_dir.join(interval.as_str()).join(&file_name);
+        let path = if interval_path.exists() {
+            interval_path
+        } else {
+            self.data_dir.join(&file_name)
+        };
+        let mut reader = csv::Reader::from_path(&path).map_err(|e| match e.kind() {
+            csv::ErrorKind::Io(io) if io.kind() == ErrorKind::NotFound => {
+                ProviderError::NoData(format!("{} ({})", ticker, path.display()))
+            }
+            _ => ProviderError::InvalidResponse(format!("{}: {}", path.display(), e)),
+        })?;
+        reader
+            .deserialize::<FileRow>()
+            .map(|row| {
+                let row = row.map_err(|e| {
+                    ProviderError::InvalidResponse(format!("{}: {}", path.display(), e))
+                })?;
+                Ok(Bar {
+                    timestamp: parse_timestamp(&row.date).ok_or_else(|| {
+                        ProviderError::InvalidResponse(format!(
+                            "{}: invalid date {:?}",
+                            path.display(),
+                            row.date
+                        ))
+                    })?,
+                    open: row.open,
+                    high: row.high,
+                    low: row.low,
+                    close: row.close,
+                    adjclose: row.adjclose.unwrap_or(row.close),
+                    volume: row.volume.unwrap_or(0),
+                })
+            })
+            .collect()
+    }
+}
+
+fn parse_timestamp(date: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(date)
+        .map(|d| d.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .ok()
+                .map(|d| Utc.from_utc_datetime(&d.and_time(NaiveTime::MIN)))
+        })
+}
+
+#[async_trait]
+impl QuoteProvider for FileProvider {
+    async fn get_quote_history(
+        &self,
+        ticker: &Ticker,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        interval: Interval,
+    ) -> Result<Vec<Bar>, ProviderError> {
+        let bars = self.read_bars(ticker, interval)?;
+        Ok(bars
+            .into_iter()
+            .filter(|bar| bar.timestamp >= start && bar.timestamp <= end)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider() -> FileProvider {
+        FileProvider::new(PathBuf::from(format!(
+            "{}/src/providers/testdata",
+            env!("CARGO_MANIFEST_DIR")
+        )))
+    }
+
+    #[async_std::test]
+    async fn file_provider_returns_bars_within_range() {
+        let bars = provider()
+            .get_quote_history(
+                &Ticker::from("TEST"),
+                Utc.with_ymd_and_hms(2021, 1, 5, 0, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2021, 1, 6, 0, 0, 0).unwrap(),
+                Interval::OneDay,
+            )
+            .await
+            .unwrap();
+        let adjclose: Vec<f64> = bars.iter().map(|b| b.adjclose).collect();
+        assert_eq!(adjclose, vec![11f64, 12f64]);
+    }
+
+    #[async_std::test]
+    async fn file_provider_reads_yahoo_export_headers() {
+        let bars = provider()
+            .get_quote_history(
+                &Ticker::from("YAHOO"),
+                Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap(),
+                Utc::now(),
+                Interval::OneDay,
+            )
+            .await
+            .unwrap();
+        assert_eq!(bars.len(), 2);
+        assert_eq!(bars[1].adjclose, 21.25f64);
+        assert_eq!(
+            bars[1].timestamp,
+            Utc.with_ymd_and_hms(2021, 1, 5, 14, 30, 0).unwrap()
+        );
+    }
+
+    #[async_std::test]
+    async fn file_provider_returns_no_data_for_missing_ticker() {
+        let result = provider()
+            .get_quote_history(
+                &Ticker::from("MISSING"),
+                Utc::now(),
+                Utc::now(),
+                Interval::OneDay,
+            )
+            .await;
+        assert!(matches!(result, Err(ProviderError::NoData(_))));
+    }
+}
@@ -0,0 +1,350 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::prelude::*;
+use lib::corporate_actions::CorporateAction;
+use lib::quote_provider::{Bar, History, Interval, ProviderError, QuoteProvider};
+use lib::ticker::Ticker;
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A single `get_quote_history` or `get_history` call as captured by `RecordingProvider`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Recording {
+    ticker: Ticker,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    interval: Interval,
+    requested_at: DateTime<Utc>,
+    latency_ms: u64,
+    response: Result<Vec<Bar>, ProviderError>,
+    /// Only recorded by `get_history`, and missing from recordings made before it was
+    #[serde(default)]
+    corporate_actions: Option<Result<Vec<CorporateAction>, ProviderError>>,
+}
+
+/// Wraps another provider and writes every request and response to `dir`, one JSON file per call.
+pub struct RecordingProvider<P: QuoteProvider> {
+    inner: P,
+    dir: PathBuf,
+    sequence: AtomicUsize,
+}
+
+impl<P: QuoteProvider> RecordingProvider<P> {
+    pub fn new(inner: P, dir: PathBuf) -> Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(RecordingProvider {
+            inner,
+            dir,
+            sequence: AtomicUsize::new(0),
+        })
+    }
+
+    fn write(&self, recording: &Recording) -> Result<()> {
+        let sequence = self.sequence.fetch_add(1, Ordering::SeqCst);
+        let path = self.dir.join(format!(
+            "{}-{:06}-{}.json",
+            recording.requested_at.timestamp_millis(),
+            sequence,
+            recording.ticker
+        ));
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), recording)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<P: QuoteProvider + Send + Sync> QuoteProvider for RecordingProvider<P> {
+    async fn get_quote_history(
+        &self,
+        ticker: &Ticker,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        interval: Interval,
+    ) -> Result<Vec<Bar>, ProviderError> {
+        let requested_at = Utc::now();
+        let timer = Instant::now();
+        let response = self
+            .inner
+            .get_quote_history(ticker, start, end, interval)
+            .await;
+        let recording = Recording {
+            ticker: ticker.clone(),
+            start,
+            end,
+            interval,
+            requested_at,
+            latency_ms: timer.elapsed().as_millis() as u64,
+            response,
+            corporate_actions: None,
+        };
+        if let Err(e) = self.write(&recording) {
+            error!("Failed to record response for {:?}: {:?}", ticker, e);
+        }
+        recording.response
+    }
+
+    async fn get_corporate_actions(
+        &self,
+        ticker: &Ticker,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<CorporateAction>, ProviderError> {
+        self.inner.get_corporate_actions(ticker, start, end).await
+    }
+
+    async fn get_history(
+        &self,
+        ticker: &Ticker,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        interval: Interval,
+    ) -> Result<History, ProviderError> {
+        let requested_at = Utc::now();
+        let timer = Instant::now();
+        let (response, corporate_actions) =
+            match self.inner.get_history(ticker, start, end, interval).await {
+                Ok(history) => (Ok(history.bars), Some(history.corporate_actions)),
+                Err(e) => (Err(e), None),
+            };
+        let recording = Recording {
+            ticker: ticker.clone(),
+            start,
+            end,
+            interval,
+            requested_at,
+            latency_ms: timer.elapsed().as_millis() as u64,
+            response,
+            corporate_actions,
+        };
+        if let Err(e) = self.write(&recording) {
+            error!("Failed to record response for {:?}: {:?}", ticker, e);
+        }
+        recording.into_history()
+    }
+}
+
+impl Recording {
+    fn into_history(self) -> Result<History, ProviderError> {
+        Ok(History {
+            bars: self.response?,
+            corporate_actions: self.corporate_actions.unwrap_or_else(|| Ok(vec![])),
+        })
+    }
+}
+
+/// Serves responses captured by `RecordingProvider`. Each ticker's responses are returned in the
+/// order they were recorded, delayed to match their original timing divided by `speed`.
+pub struct ReplayProvider {
+    recordings: Mutex<HashMap<Ticker, VecDeque<(Duration, Recording)>>>,
+    speed: f64,
+    started: Instant,
+}
+
+impl ReplayProvider {
+    pub fn new(dir: PathBuf, speed: f64) -> Result<Self> {
+        let mut recordings = vec![];
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                let recording: Recording =
+                    serde_json::from_reader(BufReader::new(File::open(&path)?))?;
+                recordings.push((path, recording));
+            }
+        }
+        recordings.sort_by(|(a_path, a), (b_path, b)| {
+            (a.requested_at, a_path).cmp(&(b.requested_at, b_path))
+        });
+
+        let first_request = recordings.first().map(|(_, r)| r.requested_at);
+        let mut by_ticker: HashMap<Ticker, VecDeque<(Duration, Recording)>> = HashMap::new();
+        for (_, recording) in recordings {
+            let offset = (recording.requested_at - first_request.unwrap_or(recording.requested_at))
+                .to_std()
+                .unwrap_or_default()
+                + Duration::from_millis(recording.latency_ms);
+            by_ticker
+                .entry(recording.ticker.clone())
+                .or_default()
+                .push_back((offset, recording));
+        }
+        Ok(ReplayProvider {
+            recordings: Mutex::new(by_ticker),
+            speed,
+            started: Instant::now(),
+        })
+    }
+}
+
+impl ReplayProvider {
+    /// The ticker's next recording, once it is due
+    async fn next(&self, ticker: &Ticker) -> Result<Recording, ProviderError> {
+        let next = self
+            .recordings
+            .lock()
+            .unwrap()
+            .get_mut(ticker)
+            .and_then(VecDeque::pop_front);
+        let (offset, recording) = next.ok_or_else(|| {
+            ProviderError::NoData(format!("{} (no recorded responses left)", ticker))
+        })?;
+        let due = offset.div_f64(self.speed);
+        let elapsed = self.started.elapsed();
+        if due > elapsed {
+            async_std::task::sleep(due - elapsed).await;
+        }
+        Ok(recording)
+    }
+}
+
+#[async_trait]
+impl QuoteProvider for ReplayProvider {
+    async fn get_quote_history(
+        &self,
+        ticker: &Ticker,
+        _start: DateTime<Utc>,
+        _end: DateTime<Utc>,
+        _interval: Interval,
+    ) -> Result<Vec<Bar>, ProviderError> {
+        self.next(ticker).await?.response
+    }
+
+    async fn get_history(
+        &self,
+        ticker: &Ticker,
+        _start: DateTime<Utc>,
+        _end: DateTime<Utc>,
+        _interval: Interval,
+    ) -> Result<History, ProviderError> {
+        self.next(ticker).await?.into_history()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct CountingProvider {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl QuoteProvider for CountingProvider {
+        async fn get_quote_history(
+            &self,
+            ticker: &Ticker,
+            start: DateTime<Utc>,
+            _end: DateTime<Utc>,
+            _interval: Interval,
+        ) -> Result<Vec<Bar>, ProviderError> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst) as f64;
+            if ticker.as_str() == "BAD" {
+                return Err(ProviderError::InvalidResponse("bad response".to_owned()));
+            }
+            Ok(vec![Bar {
+                timestamp: start,
+                open: call,
+                high: call,
+                low: call,
+                close: call,
+                adjclose: call,
+                volume: 0,
+            }])
+        }
+
+        async fn get_corporate_actions(
+            &self,
+            _ticker: &Ticker,
+            start: DateTime<Utc>,
+            _end: DateTime<Utc>,
+        ) -> Result<Vec<CorporateAction>, ProviderError> {
+            Ok(vec![CorporateAction::Dividend {
+                date: start,
+                amount: 0.5,
+            }])
+        }
+    }
+
+    #[async_std::test]
+    async fn replay_provider_serves_recorded_corporate_actions() {
+        let dir = tempfile::tempdir().unwrap();
+        let recorder = RecordingProvider::new(
+            CountingProvider {
+                calls: AtomicUsize::new(0),
+            },
+            dir.path().to_path_buf(),
+        )
+        .unwrap();
+        let ticker = Ticker::from("TEST");
+        let now = Utc::now();
+        let recorded = recorder
+            .get_history(&ticker, now, now, Interval::OneDay)
+            .await
+            .unwrap();
+        assert_eq!(recorded.corporate_actions.as_ref().unwrap().len(), 1);
+
+        let replay = ReplayProvider::new(dir.path().to_path_buf(), 1000.0).unwrap();
+        assert_eq!(
+            replay
+                .get_history(&ticker, now, now, Interval::OneDay)
+                .await
+                .unwrap(),
+            recorded
+        );
+    }
+
+    #[async_std::test]
+    async fn replay_provider_serves_recorded_responses_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let recorder = RecordingProvider::new(
+            CountingProvider {
+                calls: AtomicUsize::new(0),
+            },
+            dir.path().to_path_buf(),
+        )
+        .unwrap();
+        let ticker = Ticker::from("TEST");
+        let now = Utc::now();
+        let first = recorder
+            .get_quote_history(&ticker, now, now, Interval::OneDay)
+            .await;
+        let second = recorder
+            .get_quote_history(&ticker, now, now, Interval::OneDay)
+            .await;
+        let bad = recorder
+            .get_quote_history(&Ticker::from("BAD"), now, now, Interval::OneDay)
+            .await;
+
+        let replay = ReplayProvider::new(dir.path().to_path_buf(), 1000.0).unwrap();
+        assert_eq!(
+            replay
+                .get_quote_history(&ticker, now, now, Interval::OneDay)
+                .await,
+            first
+        );
+        assert_eq!(
+            replay
+                .get_quote_history(&ticker, now, now, Interval::OneDay)
+                .await,
+            second
+        );
+        assert_eq!(
+            replay
+                .get_quote_history(&Ticker::from("BAD"), now, now, Interval::OneDay)
+                .await,
+            bad
+        );
+        assert!(matches!(
+            replay
+                .get_quote_history(&ticker, now, now, Interval::OneDay)
+                .await,
+            Err(ProviderError::NoData(_))
+        ));
+    }
+}
@@ -0,0 +1,189 @@
+use crate::config::{SyntheticConfig, SyntheticModel};
+use async_trait::async_trait;
+use chrono::prelude::*;
+use lib::quote_provider::{Bar, Interval, ProviderError, QuoteProvider};
+use lib::ticker::Ticker;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::f64::consts::PI;
+
+const SECONDS_PER_YEAR: f64 = 365.25 * 24.0 * 60.0 * 60.0;
+const MIN_PRICE: f64 = 0.01;
+
+/// Generates a price series per ticker without any external service.
+/// A series depends only on the seed, the ticker and the requested start, so repeated
+/// fetches over a growing range return the same history extended by the new bars.
+/// Intraday history is limited to the interval's lookback, as Yahoo limits it, so its start
+/// moves along with the end.
+pub struct SyntheticProvider {
+    config: SyntheticConfig,
+}
+
+impl SyntheticProvider {
+    pub fn new(config: SyntheticConfig) -> Self {
+        SyntheticProvider { config }
+    }
+
+    fn rng_for(&self, ticker: &Ticker) -> ChaCha8Rng {
+        // FNV-1a, so the per-ticker seed is stable across builds and platforms
+        let ticker_hash = ticker
+            .as_str()
+            .bytes()
+            .fold(0xcbf29ce484222325u64, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            });
+        ChaCha8Rng::seed_from_u64(self.config.seed ^ ticker_hash)
+    }
+
+    fn next_price(&self, price: f64, dt: f64, z: f64) -> f64 {
+        let SyntheticConfig {
+            drift, volatility, ..
+        } = self.config;
+        let next = match self.config.model {
+            SyntheticModel::Gbm => {
+                price
+                    * ((drift - volatility * volatility / 2.0) * dt + volatility * dt.sqrt() * z)
+                        .exp()
+            }
+            SyntheticModel::RandomWalk => {
+                price + self.config.initial_price * (drift * dt + volatility * dt.sqrt() * z)
+            }
+        };
+        next.max(MIN_PRICE)
+    }
+
+    fn generate(
+        &self,
+        ticker: &Ticker,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        interval: Interval,
+    ) -> Vec<Bar> {
+        let mut rng = self.rng_for(ticker);
+        let interval = interval.duration();
+        let dt = interval.num_seconds() as f64 / SECONDS_PER_YEAR;
+        let mut price = self.config.initial_price;
+        let mut timestamp = start;
+        let mut bars = vec![];
+        while timestamp <= end {
+            let open = price;
+            price = self.next_price(price, dt, standard_normal(&mut rng));
+            let wick = 1.0 + rng.gen::<f64>() * 0.01;
+            bars.push(Bar {
+                timestamp,
+                open,
+                high: open.max(price) * wick,
+                low: open.min(price) / wick,
+                close: price,
+                adjclose: price,
+                volume: rng.gen_range(100_000..1_000_000),
+            });
+            timestamp += interval;
+        }
+        bars
+    }
+}
+
+/// Box-Muller transform
+fn standard_normal<R: Rng>(rng: &mut R) -> f64 {
+    let u1: f64 = 1.0 - rng.gen::<f64>();
+    let u2: f64 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+}
+
+#[async_trait]
+impl QuoteProvider for SyntheticProvider {
+    async fn get_quote_history(
+        &self,
+        ticker: &Ticker,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        interval: Interval,
+    ) -> Result<Vec<Bar>, ProviderError> {
+        let start = interval.clamp_start(start, end);
+        Ok(self.generate(ticker, start, end, interval))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider(model: SyntheticModel, seed: u64) -> SyntheticProvider {
+        SyntheticProvider::new(SyntheticConfig {
+            model,
+            seed,
+            drift: 0.05,
+            volatility: 0.2,
+            initial_price: 100.0,
+        })
+    }
+
+    fn closes(provider: &SyntheticProvider, ticker: &str, days: i64) -> Vec<f64> {
+        let start = Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap();
+        provider
+            .generate(
+                &Ticker::from(ticker),
+                start,
+                start + chrono::Duration::days(days),
+                Interval::OneDay,
+            )
+            .iter()
+            .map(|b| b.close)
+            .collect()
+    }
+
+    #[async_std::test]
+    async fn synthetic_provider_limits_intraday_history_to_the_lookback() {
+        let end = Utc.with_ymd_and_hms(2021, 8, 13, 0, 0, 0).unwrap();
+        let bars = provider(SyntheticModel::Gbm, 7)
+            .get_quote_history(
+                &Ticker::from("TEST"),
+                end - chrono::Duration::days(365),
+                end,
+                Interval::OneMinute,
+            )
+            .await
+            .unwrap();
+        assert_eq!(bars[0].timestamp, end - chrono::Duration::days(7));
+        assert_eq!(bars.len(), 7 * 24 * 60 + 1);
+    }
+
+    #[test]
+    fn synthetic_provider_is_reproducible_for_the_same_seed() {
+        let first = closes(&provider(SyntheticModel::Gbm, 7), "TEST", 30);
+        let second = closes(&provider(SyntheticModel::Gbm, 7), "TEST", 30);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn synthetic_provider_extends_history_when_range_grows() {
+        let short = closes(&provider(SyntheticModel::RandomWalk, 1), "TEST", 10);
+        let long = closes(&provider(SyntheticModel::RandomWalk, 1), "TEST", 20);
+        assert_eq!(short[..], long[..short.len()]);
+    }
+
+    #[test]
+    fn synthetic_provider_generates_different_series_per_ticker_and_seed() {
+        let p = provider(SyntheticModel::Gbm, 7);
+        assert_ne!(closes(&p, "TEST", 30), closes(&p, "OTHER", 30));
+        assert_ne!(
+            closes(&p, "TEST", 30),
+            closes(&provider(SyntheticModel::Gbm, 8), "TEST", 30)
+        );
+    }
+
+    #[test]
+    fn synthetic_provider_emits_one_bar_per_interval_in_range() {
+        let start = Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap();
+        let bars = provider(SyntheticModel::Gbm, 0).generate(
+            &Ticker::from("TEST"),
+            start,
+            start + chrono::Duration::hours(9),
+            Interval::OneHour,
+        );
+        assert_eq!(bars.len(), 10);
+        assert_eq!(bars[9].timestamp, start + chrono::Duration::hours(9));
+        assert!(bars.iter().all(|b| b.low <= b.close && b.close <= b.high));
+    }
+}
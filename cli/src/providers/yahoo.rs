@@ -0,0 +1,220 @@
+use async_trait::async_trait;
+use chrono::prelude::*;
+use lib::corporate_actions::CorporateAction;
+use lib::quote_provider::{Bar, History, Interval, ProviderError, QuoteProvider};
+use lib::ticker::Ticker;
+use std::convert::TryFrom;
+use yahoo_finance_api::{YResponse, YahooConnector, YahooError};
+
+pub struct YahooProvider {
+    connector: YahooConnector,
+}
+
+impl YahooProvider {
+    pub fn new() -> Self {
+        YahooProvider {
+            connector: YahooConnector::new(),
+        }
+    }
+}
+
+impl Default for YahooProvider {
+    fn default() -> Self {
+        YahooProvider::new()
+    }
+}
+
+#[async_trait]
+impl QuoteProvider for YahooProvider {
+    async fn get_quote_history(
+        &self,
+        ticker: &Ticker,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        interval: Interval,
+    ) -> Result<Vec<Bar>, ProviderError> {
+        let start = interval.clamp_start(start, end);
+        self.connector
+            .get_quote_history_interval(&yahoo_symbol(ticker), start, end, interval.as_str())
+            .await
+            .map_err(provider_error)
+            .and_then(|response| bars_from_response(&response))
+    }
+
+    /// Yahoo sends splits and dividends along with every chart response
+    async fn get_corporate_actions(
+        &self,
+        ticker: &Ticker,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<CorporateAction>, ProviderError> {
+        self.get_history(ticker, start, end, Interval::OneDay)
+            .await?
+            .corporate_actions
+    }
+
+    async fn get_history(
+        &self,
+        ticker: &Ticker,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        interval: Interval,
+    ) -> Result<History, ProviderError> {
+        let start = interval.clamp_start(start, end);
+        let response = self
+            .connector
+            .get_quote_history_interval(&yahoo_symbol(ticker), start, end, interval.as_str())
+            .await
+            .map_err(provider_error)?;
+        Ok(History {
+            bars: bars_from_response(&response)?,
+            corporate_actions: corporate_actions_from_response(&response),
+        })
+    }
+}
+
+/// Yahoo separates share classes with `-`, e.g. `BRK-B`
+fn yahoo_symbol(ticker: &Ticker) -> String {
+    ticker.to_symbol('-')
+}
+
+fn bars_from_response(response: &YResponse) -> Result<Vec<Bar>, ProviderError> {
+    response
+        .quotes()
+        .map_err(provider_error)?
+        .into_iter()
+        .map(|q| {
+            Ok(Bar {
+                timestamp: timestamp(q.timestamp)?,
+                open: q.open,
+                high: q.high,
+                low: q.low,
+                close: q.close,
+                adjclose: q.adjclose,
+                volume: q.volume,
+            })
+        })
+        .collect()
+}
+
+fn corporate_actions_from_response(
+    response: &YResponse,
+) -> Result<Vec<CorporateAction>, ProviderError> {
+    let splits = response
+        .splits()
+        .map_err(provider_error)?
+        .into_iter()
+        .map(|split| {
+            Ok(CorporateAction::Split {
+                date: timestamp(split.date)?,
+                numerator: split.numerator,
+                denominator: split.denominator,
+            })
+        });
+    let dividends = response
+        .dividends()
+        .map_err(provider_error)?
+        .into_iter()
+        .map(|dividend| {
+            Ok(CorporateAction::Dividend {
+                date: timestamp(dividend.date)?,
+                amount: dividend.amount,
+            })
+        });
+    let mut actions = splits
+        .chain(dividends)
+        .collect::<Result<Vec<CorporateAction>, ProviderError>>()?;
+    actions.sort_by_key(CorporateAction::date);
+    Ok(actions)
+}
+
+/// Seconds since the epoch, as Yahoo sends them
+fn timestamp(seconds: u64) -> Result<DateTime<Utc>, ProviderError> {
+    i64::try_from(seconds)
+        .ok()
+        .and_then(|seconds| Utc.timestamp_opt(seconds, 0).single())
+        .ok_or_else(|| {
+            ProviderError::InvalidResponse(format!("Timestamp {} out of range", seconds))
+        })
+}
+
+fn provider_error(e: YahooError) -> ProviderError {
+    match e {
+        YahooError::ConnectionFailed | YahooError::FetchFailed(_) => {
+            ProviderError::ConnectionFailed(e.to_string())
+        }
+        YahooError::EmptyDataSet => ProviderError::NoData(e.to_string()),
+        YahooError::DeserializeFailed(_)
+        | YahooError::InvalidJson
+        | YahooError::DataInconsistency => ProviderError::InvalidResponse(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::BufReader;
+
+    fn mock_response() -> YResponse {
+        let file = File::open(format!(
+            "{}/src/providers/mockYahooData.json",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let reader = BufReader::new(file);
+        YResponse::from_json(serde_json::from_reader(reader).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn bars_from_response_converts_every_quote() {
+        let bars = bars_from_response(&mock_response()).unwrap();
+        let adjclose: Vec<f64> = bars.iter().map(|b| b.adjclose).collect();
+        assert_eq!(adjclose, vec![1f64, 2f64, 3f64]);
+        assert_eq!(bars[0].timestamp, Utc.timestamp_opt(1628515800, 0).unwrap());
+        assert_eq!(bars[0].volume, 35611100);
+    }
+
+    #[test]
+    fn corporate_actions_from_response_converts_splits_and_dividends() {
+        let actions = corporate_actions_from_response(&mock_response()).unwrap();
+        assert_eq!(
+            actions,
+            vec![
+                CorporateAction::Dividend {
+                    date: Utc.timestamp_opt(1628602200, 0).unwrap(),
+                    amount: 0.5
+                },
+                CorporateAction::Split {
+                    date: Utc.timestamp_opt(1628712003, 0).unwrap(),
+                    numerator: 2,
+                    denominator: 1
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn timestamp_rejects_seconds_out_of_range() {
+        assert_eq!(
+            timestamp(1628515800),
+            Ok(Utc.with_ymd_and_hms(2021, 8, 9, 13, 30, 0).unwrap())
+        );
+        assert!(matches!(
+            timestamp(u64::MAX),
+            Err(ProviderError::InvalidResponse(_))
+        ));
+        assert!(matches!(
+            timestamp(i64::MAX as u64),
+            Err(ProviderError::InvalidResponse(_))
+        ));
+    }
+
+    #[test]
+    fn provider_error_maps_empty_data_set_to_no_data() {
+        assert!(matches!(
+            provider_error(YahooError::EmptyDataSet),
+            ProviderError::NoData(_)
+        ));
+    }
+}
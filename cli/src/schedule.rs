@@ -0,0 +1,203 @@
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use lib::ticker::Ticker;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ScheduleParseError {
+    #[error("Empty schedule")]
+    Empty,
+
+    #[error("Invalid interval {0}, expected e.g. 30s, 5m or 1h")]
+    InvalidInterval(String),
+
+    #[error("Invalid cron expression {0}: {1}")]
+    InvalidCron(String, String),
+
+    #[error("Unknown timezone {0}")]
+    InvalidTimezone(String),
+
+    #[error("Invalid schedule rule {0}, expected `every <interval>` or `cron <expression> [in <timezone>]`")]
+    InvalidRule(String),
+}
+
+#[derive(Debug, Clone)]
+enum Rule {
+    Every(Duration),
+    Cron(Box<cron::Schedule>, Tz),
+}
+
+impl Rule {
+    fn next_after(&self, time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Rule::Every(interval) => Some(time + *interval),
+            Rule::Cron(schedule, timezone) => schedule
+                .after(&time.with_timezone(timezone))
+                .next()
+                .map(|next| next.with_timezone(&Utc)),
+        }
+    }
+}
+
+impl FromStr for Rule {
+    type Err = ScheduleParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(interval) = s.strip_prefix("every ") {
+            return parse_interval(interval.trim()).map(Rule::Every);
+        }
+        if let Some(expression) = s.strip_prefix("cron ") {
+            let (expression, timezone) = match expression.rsplit_once(" in ") {
+                Some((expression, timezone)) => (
+                    expression,
+                    timezone.trim().parse::<Tz>().map_err(|_| {
+                        ScheduleParseError::InvalidTimezone(timezone.trim().to_owned())
+                    })?,
+                ),
+                None => (expression, Tz::UTC),
+            };
+            let schedule = cron::Schedule::from_str(expression.trim()).map_err(|e| {
+                ScheduleParseError::InvalidCron(expression.trim().to_owned(), e.to_string())
+            })?;
+            return Ok(Rule::Cron(Box::new(schedule), timezone));
+        }
+        Err(ScheduleParseError::InvalidRule(s.to_owned()))
+    }
+}
+
+fn parse_interval(s: &str) -> Result<Duration, ScheduleParseError> {
+    let invalid = || ScheduleParseError::InvalidInterval(s.to_owned());
+    let split = s.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+    let (value, unit) = s.split_at(split);
+    let value: i64 = value.parse().map_err(|_| invalid())?;
+    let interval = match unit.trim() {
+        "s" => Duration::seconds(value),
+        "m" => Duration::minutes(value),
+        "h" => Duration::hours(value),
+        "d" => Duration::days(value),
+        _ => return Err(invalid()),
+    };
+    if interval <= Duration::zero() {
+        return Err(invalid());
+    }
+    Ok(interval)
+}
+
+/// When to trigger a recurring task: the earliest of one or more rules separated by `;`, e.g.
+/// `cron 0 */5 9-15 * * Mon-Fri in America/New_York; cron 0 5 16 * * Mon-Fri in America/New_York`.
+/// Cron expressions have a leading seconds field and default to UTC.
+#[derive(Debug, Clone)]
+pub struct Schedule(Vec<Rule>);
+
+impl Schedule {
+    pub fn every(interval: Duration) -> Self {
+        Schedule(vec![Rule::Every(interval)])
+    }
+
+    pub fn next_after(&self, time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.0.iter().filter_map(|rule| rule.next_after(time)).min()
+    }
+
+    /// Time to wait from `time` until the next trigger
+    pub fn delay_after(&self, time: DateTime<Utc>) -> Option<std::time::Duration> {
+        self.next_after(time)
+            .map(|next| (next - time).to_std().unwrap_or_default())
+    }
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Schedule::every(Duration::seconds(30))
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = ScheduleParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rules = s
+            .split(';')
+            .filter(|rule| !rule.trim().is_empty())
+            .map(Rule::from_str)
+            .collect::<Result<Vec<_>, _>>()?;
+        if rules.is_empty() {
+            return Err(ScheduleParseError::Empty);
+        }
+        Ok(Schedule(rules))
+    }
+}
+
+/// A set of tickers fetched together on their own schedule
+#[derive(Debug, Clone)]
+pub struct TickerGroup {
+    pub name: String,
+    pub tickers: Vec<Ticker>,
+    pub schedule: Schedule,
+}
+
+impl TickerGroup {
+    pub fn new(name: String, tickers: Vec<Ticker>, schedule: Schedule) -> Self {
+        TickerGroup {
+            name,
+            tickers,
+            schedule,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn every_schedule_triggers_after_interval() {
+        let schedule: Schedule = "every 5m".parse().unwrap();
+        let time = Utc.with_ymd_and_hms(2021, 8, 10, 14, 0, 0).unwrap();
+        assert_eq!(schedule.next_after(time), Some(time + Duration::minutes(5)));
+    }
+
+    #[test]
+    fn cron_schedule_triggers_in_its_timezone() {
+        let schedule: Schedule = "cron 0 5 16 * * Mon-Fri in America/New_York"
+            .parse()
+            .unwrap();
+        // Tuesday 10:00 in New York, next trigger is 16:05 that day
+        let time = Utc.with_ymd_and_hms(2021, 8, 10, 14, 0, 0).unwrap();
+        assert_eq!(
+            schedule.next_after(time),
+            Some(Utc.with_ymd_and_hms(2021, 8, 10, 20, 5, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn schedule_with_several_rules_triggers_at_the_earliest() {
+        let schedule: Schedule = "cron 0 */5 9-15 * * Mon-Fri in America/New_York; cron 0 5 16 * * Mon-Fri in America/New_York"
+            .parse()
+            .unwrap();
+        // Tuesday 15:58 in New York: the five minute rule has finished for the day
+        let time = Utc.with_ymd_and_hms(2021, 8, 10, 19, 58, 0).unwrap();
+        assert_eq!(
+            schedule.next_after(time),
+            Some(Utc.with_ymd_and_hms(2021, 8, 10, 20, 5, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn schedule_rejects_invalid_rules() {
+        assert_eq!(
+            "every 0s".parse::<Schedule>().unwrap_err(),
+            ScheduleParseError::InvalidInterval("0s".to_owned())
+        );
+        assert!(matches!(
+            "cron 0 5 16 * * Mon-Fri in Mars/Olympus".parse::<Schedule>(),
+            Err(ScheduleParseError::InvalidTimezone(_))
+        ));
+        assert!(matches!(
+            "hourly".parse::<Schedule>(),
+            Err(ScheduleParseError::InvalidRule(_))
+        ));
+    }
+}
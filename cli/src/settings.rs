@@ -0,0 +1,301 @@
+use crate::config::ArgumentParsingError;
+use anyhow::Result;
+use clap::ArgMatches;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Prefix of environment variables overriding settings, e.g. `SIMPLETRACKER_FLUSH_SCHEDULE`
+pub const ENV_PREFIX: &str = "SIMPLETRACKER_";
+
+/// Separates the values of settings that take several, e.g. `--schedule`, in environment variables
+const ENV_SEPARATOR: char = ';';
+
+/// The setting naming the config file, which can't itself be set from the file
+pub const CONFIG: &str = "config";
+
+/// A setting's value, with its key named after where it came from for error messages
+#[derive(Debug, Clone, PartialEq)]
+pub struct Setting<T> {
+    pub value: T,
+    pub key: String,
+}
+
+/// Settings resolved from, in order of precedence, command line flags, `SIMPLETRACKER_*`
+/// environment variables, a TOML config file, and the flags' defaults. Every setting is known
+/// by its clap argument name, e.g. `flush_schedule`, which is spelt `flush-schedule` in the file.
+pub struct Settings<'a> {
+    matches: ArgMatches<'a>,
+    env: HashMap<String, String>,
+    file: Option<(PathBuf, toml::value::Table)>,
+}
+
+impl<'a> Settings<'a> {
+    /// Reads the config file named by `--config` or `SIMPLETRACKER_CONFIG`, rejecting any key of
+    /// it or `env` that isn't one of `names`
+    pub fn new(
+        matches: ArgMatches<'a>,
+        names: &[&str],
+        env: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self> {
+        let env: HashMap<String, String> = env
+            .into_iter()
+            .filter_map(|(key, value)| Some((key.strip_prefix(ENV_PREFIX)?.to_owned(), value)))
+            .collect();
+        if let Some(key) = env
+            .keys()
+            .find(|key| !names.contains(&key.to_lowercase().as_str()))
+        {
+            return Err(ArgumentParsingError::UnknownKey(format!("{}{}", ENV_PREFIX, key)).into());
+        }
+
+        let mut settings = Settings {
+            matches,
+            env,
+            file: None,
+        };
+        if let Some(path) = settings.value(CONFIG) {
+            let path = PathBuf::from(path.value);
+            let table: toml::value::Table =
+                toml::from_str(&read_to_string(&path)?).map_err(|e| {
+                    ArgumentParsingError::InvalidConfigFile(path.display().to_string(), e)
+                })?;
+            if let Some(key) = table.keys().find(|key| {
+                let name = key.replace('-', "_");
+                name == CONFIG || !names.contains(&name.as_str())
+            }) {
+                return Err(ArgumentParsingError::UnknownKey(file_key(key, &path)).into());
+            }
+            settings.file = Some((path, table));
+        }
+        Ok(settings)
+    }
+
+    /// The value of a single valued setting
+    pub fn value(&self, name: &str) -> Option<Setting<String>> {
+        self.values(name).map(|setting| Setting {
+            value: setting.value.into_iter().next().unwrap_or_default(),
+            key: setting.key,
+        })
+    }
+
+    /// The values of a setting. Environment variables separate values with `;`, and the config
+    /// file takes either an array or a single value.
+    pub fn values(&self, name: &str) -> Option<Setting<Vec<String>>> {
+        let flag = format!("--{}", name.replace('_', "-"));
+        if self.matches.occurrences_of(name) > 0 {
+            return Some(Setting {
+                value: match self.matches.values_of(name) {
+                    Some(values) if values.len() > 0 => values.map(str::to_owned).collect(),
+                    // Flags are set by being given
+                    _ => vec!["true".to_owned()],
+                },
+                key: flag,
+            });
+        }
+        if let Some(value) = self.env.get(&name.to_uppercase()) {
+            return Some(Setting {
+                value: value.split(ENV_SEPARATOR).map(str::to_owned).collect(),
+                key: format!("{}{}", ENV_PREFIX, name.to_uppercase()),
+            });
+        }
+        if let Some((path, table)) = &self.file {
+            let key = name.replace('_', "-");
+            if let Some(value) = table.get(&key) {
+                let value = match value {
+                    toml::Value::Array(values) => values.iter().map(toml_string).collect(),
+                    value => vec![toml_string(value)],
+                };
+                return Some(Setting {
+                    value,
+                    key: file_key(&key, path),
+                });
+            }
+        }
+        Some(Setting {
+            value: self.matches.values_of(name)?.map(str::to_owned).collect(),
+            key: flag,
+        })
+    }
+
+    /// Parses a setting, if it is set
+    pub fn parse<T>(&self, name: &str) -> Result<Option<T>, ArgumentParsingError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.value(name).map(|setting| parse(&setting)).transpose()
+    }
+
+    /// Parses a setting that must be set, usually because it has a default
+    pub fn require<T>(&self, name: &'static str) -> Result<T, ArgumentParsingError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.parse(name)?
+            .ok_or(ArgumentParsingError::MissingParameter(name))
+    }
+}
+
+/// Parses a setting's value, naming its key if it is invalid
+pub fn parse<T>(setting: &Setting<String>) -> Result<T, ArgumentParsingError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    setting
+        .value
+        .trim()
+        .parse()
+        .map_err(|e: T::Err| setting.invalid(e))
+}
+
+impl<T> Setting<T> {
+    pub fn invalid(&self, reason: impl Display) -> ArgumentParsingError {
+        ArgumentParsingError::InvalidValue(self.key.clone(), reason.to_string())
+    }
+}
+
+fn file_key(key: &str, path: &Path) -> String {
+    format!("{} in {}", key, path.display())
+}
+
+/// TOML strings are taken as they are, and other values as written, e.g. `2021-01-01T00:00:00Z`
+fn toml_string(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{App, Arg};
+    use std::fs::write;
+
+    const NAMES: [&str; 5] = ["config", "decimals", "schedule", "output", "verbose"];
+
+    fn settings(args: &[&str], env: &[(&str, &str)]) -> Result<Settings<'static>> {
+        let matches = App::new("test")
+            .arg(Arg::with_name("config").long("config").takes_value(true))
+            .arg(
+                Arg::with_name("decimals")
+                    .long("decimals")
+                    .default_value("2"),
+            )
+            .arg(
+                Arg::with_name("schedule")
+                    .long("schedule")
+                    .multiple(true)
+                    .number_of_values(1),
+            )
+            .arg(Arg::with_name("output").long("output").takes_value(true))
+            .arg(Arg::with_name("verbose").long("verbose"))
+            .get_matches_from(std::iter::once("test").chain(args.iter().copied()));
+        let env = env
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()));
+        Settings::new(matches, &NAMES, env)
+    }
+
+    #[test]
+    fn settings_prefer_flags_then_env_then_file_then_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tracker.toml");
+        write(
+            &path,
+            "decimals = 4\nschedule = [\"every 1m\", \"tech=every 5m\"]\noutput = \"out.csv\"\n",
+        )
+        .unwrap();
+        let config = path.to_str().unwrap();
+
+        let file = settings(&["--config", config], &[]).unwrap();
+        assert_eq!(file.require::<usize>("decimals").unwrap(), 4);
+        assert_eq!(
+            file.values("schedule").unwrap().value,
+            vec!["every 1m", "tech=every 5m"]
+        );
+        assert_eq!(file.value("output").unwrap().value, "out.csv");
+
+        let env = settings(
+            &[],
+            &[
+                ("SIMPLETRACKER_CONFIG", config),
+                ("SIMPLETRACKER_DECIMALS", "6"),
+                ("SIMPLETRACKER_SCHEDULE", "every 2m;tech=every 10m"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(env.require::<usize>("decimals").unwrap(), 6);
+        assert_eq!(
+            env.values("schedule").unwrap().value,
+            vec!["every 2m", "tech=every 10m"]
+        );
+        assert_eq!(env.value("output").unwrap().value, "out.csv");
+
+        let flags = settings(
+            &["--config", config, "--decimals", "8"],
+            &[("SIMPLETRACKER_DECIMALS", "6")],
+        )
+        .unwrap();
+        assert_eq!(flags.require::<usize>("decimals").unwrap(), 8);
+
+        let defaults = settings(&[], &[("HOME", "/root")]).unwrap();
+        assert_eq!(defaults.require::<usize>("decimals").unwrap(), 2);
+        assert_eq!(defaults.value("output"), None);
+    }
+
+    #[test]
+    fn settings_read_flags_as_booleans() {
+        let unset = settings(&[], &[]).unwrap();
+        assert_eq!(unset.parse::<bool>("verbose").unwrap(), None);
+        let flag = settings(&["--verbose"], &[]).unwrap();
+        assert_eq!(flag.parse::<bool>("verbose").unwrap(), Some(true));
+        let env = settings(&[], &[("SIMPLETRACKER_VERBOSE", "false")]).unwrap();
+        assert_eq!(env.parse::<bool>("verbose").unwrap(), Some(false));
+    }
+
+    #[test]
+    fn settings_errors_name_the_offending_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tracker.toml");
+        write(&path, "decimals = \"many\"\n").unwrap();
+        let config = path.to_str().unwrap();
+
+        let error = settings(&["--config", config], &[])
+            .unwrap()
+            .require::<usize>("decimals")
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Invalid value for decimals in {}: invalid digit found in string",
+                config
+            )
+        );
+
+        let error = settings(&[], &[("SIMPLETRACKER_DECIMALS", "-1")])
+            .unwrap()
+            .require::<usize>("decimals")
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Invalid value for SIMPLETRACKER_DECIMALS"));
+
+        write(&path, "decimal = 4\n").unwrap();
+        let error = settings(&["--config", config], &[]).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            format!("Unknown setting decimal in {}", config)
+        );
+
+        let error = settings(&[], &[("SIMPLETRACKER_DECIMAL", "4")])
+            .err()
+            .unwrap();
+        assert_eq!(error.to_string(), "Unknown setting SIMPLETRACKER_DECIMAL");
+    }
+}
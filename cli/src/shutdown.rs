@@ -0,0 +1,63 @@
+use crate::actors::messages::{Bars, Drain, PerformanceIndicators};
+use anyhow::Result;
+use futures::future::{self, Either};
+use log::warn;
+use xactor::{Broker, Caller, Service};
+
+/// Resolves on the first SIGINT or SIGTERM. After that, a second signal exits the process
+/// immediately, without draining.
+pub async fn signal() {
+    wait_for_signal().await;
+    async_std::task::spawn(async {
+        wait_for_signal().await;
+        warn!("Exiting without draining");
+        std::process::exit(130);
+    });
+}
+
+async fn wait_for_signal() {
+    let ctrl_c = Box::pin(tokio::signal::ctrl_c());
+    if let Either::Left((Err(e), _)) = future::select(ctrl_c, Box::pin(terminate())).await {
+        warn!("Failed to listen for Ctrl-C: {:?}", e);
+        future::pending::<()>().await
+    }
+}
+
+#[cfg(unix)]
+async fn terminate() {
+    use tokio::signal::unix::{signal, SignalKind};
+    match signal(SignalKind::terminate()) {
+        Ok(mut terminate) => {
+            terminate.recv().await;
+        }
+        Err(e) => {
+            warn!("Failed to listen for SIGTERM: {:?}", e);
+            future::pending::<()>().await
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn terminate() {
+    future::pending::<()>().await
+}
+
+/// Pushes every message already sent into the pipeline through to the sinks, and flushes them.
+/// Each actor handles a drain after the messages already sent to it, so draining the stages in
+/// order leaves nothing behind; sources also stop polling.
+pub async fn drain(stages: &[Caller<Drain>], sinks: &[Caller<Drain>]) -> Result<()> {
+    for stage in stages {
+        stage.call(Drain).await?;
+    }
+    // Stopping the brokers forwards everything they have been sent first
+    let mut broker = Broker::<PerformanceIndicators>::from_registry().await?;
+    broker.stop(None)?;
+    broker.wait_for_stop().await;
+    let mut broker = Broker::<Bars>::from_registry().await?;
+    broker.stop(None)?;
+    broker.wait_for_stop().await;
+    for sink in sinks {
+        sink.call(Drain).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,309 @@
+mod columnar;
+mod files;
+
+pub use columnar::{Columnar, ParquetOptions, ParquetSink};
+pub use files::{FileOptions, FileSink, OpenSink};
+
+use crate::config::{ArgumentParsingError, Config};
+use anyhow::{bail, Result};
+use lib::formatter::{with_field_layout, FieldLayout};
+use serde::Serialize;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use strum::EnumString;
+
+/// Where the output actor writes records to
+pub trait Sink<T>: Send {
+    fn write(&mut self, record: &T) -> Result<()>;
+
+    fn flush(&mut self) -> Result<()>;
+
+    /// Completes the output, e.g. closing a JSON array, and flushes it. Called when the pipeline
+    /// drains and when the output actor stops, so it may be called more than once.
+    fn finish(&mut self) -> Result<()> {
+        self.flush()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum OutputFormat {
+    /// One row per record under a header row. Fields with several values are `|` separated.
+    #[default]
+    Csv,
+    /// One JSON object per line, with fields with several values as lists
+    Jsonl,
+    /// A pretty printed JSON array of objects, closed when the output finishes
+    Json,
+    /// Typed columns written to Parquet files in batches
+    Parquet,
+}
+
+/// Opens the sink the config's output format and file name. Text formats are written to stdout
+/// without a file.
+pub fn from_config<T>(config: &Config) -> Result<Box<dyn Sink<T>>>
+where
+    T: Serialize + Columnar + 'static,
+{
+    let text = |open_sink: OpenSink<T>| -> Result<Box<dyn Sink<T>>> {
+        Ok(match &config.file {
+            Some(path) => Box::new(FileSink::open(path, config.file_options, open_sink)?),
+            None => open_sink(Box::new(std::io::stdout()), None),
+        })
+    };
+    Ok(match config.output_format {
+        OutputFormat::Csv => text(|writer, header| match header {
+            Some(header) => Box::new(CsvSink::after_header(writer, header)),
+            None => Box::new(CsvSink::new(writer)),
+        })?,
+        OutputFormat::Jsonl => text(|writer, _| Box::new(JsonLinesSink::new(writer)))?,
+        OutputFormat::Json => text(|writer, _| Box::new(JsonSink::new(writer)))?,
+        OutputFormat::Parquet => {
+            let path = config
+                .file
+                .as_ref()
+                .ok_or(ArgumentParsingError::MissingParameter("output"))?;
+            Box::new(ParquetSink::new(path, T::schema())?.with_options(config.parquet))
+        }
+    })
+}
+
+/// One sink written to by every restart of the output actor, so the output is only opened once
+pub struct SharedSink<T>(Arc<Mutex<Box<dyn Sink<T>>>>);
+
+impl<T> SharedSink<T> {
+    pub fn new(sink: Box<dyn Sink<T>>) -> Self {
+        SharedSink(Arc::new(Mutex::new(sink)))
+    }
+}
+
+impl<T> Clone for SharedSink<T> {
+    fn clone(&self) -> Self {
+        SharedSink(self.0.clone())
+    }
+}
+
+impl<T> Sink<T> for SharedSink<T> {
+    fn write(&mut self, record: &T) -> Result<()> {
+        self.0.lock().unwrap().write(record)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.0.lock().unwrap().flush()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.0.lock().unwrap().finish()
+    }
+}
+
+pub struct CsvSink<W: Write> {
+    writer: csv::Writer<W>,
+    /// The header already written to the output, until the first record is checked against it
+    existing_header: Option<String>,
+}
+
+impl<W: Write> CsvSink<W> {
+    pub fn new(writer: W) -> Self {
+        CsvSink {
+            writer: csv::Writer::from_writer(writer),
+            existing_header: None,
+        }
+    }
+
+    /// Continues output that already starts with `header`, without writing it again. Records
+    /// with other columns are rejected rather than written under it.
+    pub fn after_header(writer: W, header: String) -> Self {
+        CsvSink {
+            writer: csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(writer),
+            existing_header: Some(header),
+        }
+    }
+}
+
+/// The header row CSV output of `record` starts with
+fn csv_header<T: Serialize>(record: &T) -> Result<String> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.serialize(record)?;
+    let output = String::from_utf8(writer.into_inner()?)?;
+    Ok(output.lines().next().unwrap_or_default().to_owned())
+}
+
+impl<T: Serialize, W: Write + Send> Sink<T> for CsvSink<W> {
+    fn write(&mut self, record: &T) -> Result<()> {
+        with_field_layout(FieldLayout::Flat, || {
+            if let Some(existing) = &self.existing_header {
+                let header = csv_header(record)?;
+                if &header != existing {
+                    bail!(
+                        "Columns {} don't match the existing header {}",
+                        header,
+                        existing
+                    );
+                }
+                self.existing_header = None;
+            }
+            Ok(self.writer.serialize(record)?)
+        })
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+pub struct JsonLinesSink<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> JsonLinesSink<W> {
+    pub fn new(writer: W) -> Self {
+        JsonLinesSink { writer }
+    }
+}
+
+impl<T: Serialize, W: Write + Send> Sink<T> for JsonLinesSink<W> {
+    fn write(&mut self, record: &T) -> Result<()> {
+        // Serialize the whole line first, so a failed record doesn't leave half a line behind
+        let mut line = with_field_layout(FieldLayout::Nested, || serde_json::to_vec(record))?;
+        line.push(b'\n');
+        Ok(self.writer.write_all(&line)?)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+pub struct JsonSink<W: Write> {
+    writer: W,
+    records: usize,
+    finished: bool,
+}
+
+impl<W: Write> JsonSink<W> {
+    pub fn new(writer: W) -> Self {
+        JsonSink {
+            writer,
+            records: 0,
+            finished: false,
+        }
+    }
+}
+
+impl<T: Serialize, W: Write + Send> Sink<T> for JsonSink<W> {
+    fn write(&mut self, record: &T) -> Result<()> {
+        let json = with_field_layout(FieldLayout::Nested, || serde_json::to_string_pretty(record))?;
+        let separator = if self.records == 0 { "[\n" } else { ",\n" };
+        let indented: Vec<String> = json.lines().map(|line| format!("  {}", line)).collect();
+        write!(self.writer, "{}{}", separator, indented.join("\n"))?;
+        self.records += 1;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(self.writer.flush()?)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if !self.finished {
+            let close = if self.records == 0 { "[]\n" } else { "\n]\n" };
+            self.writer.write_all(close.as_bytes())?;
+            self.finished = true;
+        }
+        Sink::<T>::flush(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib::quality::{QualityFlag, QualityFlags};
+
+    #[derive(Serialize)]
+    struct Record {
+        ticker: String,
+        quality: QualityFlags,
+    }
+
+    fn records() -> Vec<Record> {
+        vec![
+            Record {
+                ticker: "AAPL".to_owned(),
+                quality: QualityFlags(vec![QualityFlag::Stale, QualityFlag::Gapped]),
+            },
+            Record {
+                ticker: "MSFT".to_owned(),
+                quality: QualityFlags::default(),
+            },
+        ]
+    }
+
+    fn write_all(format: OutputFormat, records: &[Record]) -> String {
+        let mut buffer = vec![];
+        {
+            let mut sink: Box<dyn Sink<Record>> = match format {
+                OutputFormat::Csv => Box::new(CsvSink::new(&mut buffer)),
+                OutputFormat::Jsonl => Box::new(JsonLinesSink::new(&mut buffer)),
+                OutputFormat::Json => Box::new(JsonSink::new(&mut buffer)),
+                OutputFormat::Parquet => unreachable!(),
+            };
+            for record in records {
+                sink.write(record).unwrap();
+            }
+            sink.finish().unwrap();
+            sink.finish().unwrap();
+        }
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn csv_sink_joins_several_values_into_one_column() {
+        assert_eq!(
+            write_all(OutputFormat::Csv, &records()),
+            "ticker,quality\nAAPL,stale|gapped\nMSFT,\n"
+        );
+    }
+
+    #[test]
+    fn json_lines_sink_writes_one_object_per_line_with_nested_fields() {
+        assert_eq!(
+            write_all(OutputFormat::Jsonl, &records()),
+            "{\"ticker\":\"AAPL\",\"quality\":[\"stale\",\"gapped\"]}\n\
+             {\"ticker\":\"MSFT\",\"quality\":[]}\n"
+        );
+    }
+
+    #[test]
+    fn json_sink_writes_a_closed_array_once() {
+        let json = write_all(OutputFormat::Json, &records());
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed[0]["quality"], serde_json::json!(["stale", "gapped"]));
+        assert_eq!(parsed[1]["ticker"], "MSFT");
+        assert!(json.starts_with("[\n  {\n    \"ticker\": \"AAPL\","));
+        assert_eq!(write_all(OutputFormat::Json, &[]), "[]\n");
+    }
+
+    #[test]
+    fn csv_sink_appends_under_a_matching_header_only() {
+        let mut buffer = vec![];
+        let mut sink = CsvSink::after_header(&mut buffer, "ticker,quality".to_owned());
+        for record in records() {
+            sink.write(&record).unwrap();
+        }
+        Sink::<Record>::finish(&mut sink).unwrap();
+        drop(sink);
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "AAPL,stale|gapped\nMSFT,\n"
+        );
+
+        let mut sink = CsvSink::after_header(vec![], "symbol,price".to_owned());
+        assert_eq!(
+            sink.write(&records()[0]).unwrap_err().to_string(),
+            "Columns ticker,quality don't match the existing header symbol,price"
+        );
+    }
+}
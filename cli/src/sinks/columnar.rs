@@ -0,0 +1,348 @@
+use super::Sink;
+use crate::actors::messages::PerformanceIndicators;
+use anyhow::Result;
+use arrow_json::reader::{Decoder, ReaderBuilder};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use chrono::{DateTime, Duration, Utc};
+use lib::formatter::{with_field_layout, with_number_format, FieldLayout, NumberFormat};
+use log::info;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use serde::Serialize;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Records with a fixed set of typed columns, for columnar sinks
+pub trait Columnar {
+    /// One field per serialized field of the record, in `FieldLayout::Nested` and
+    /// `NumberFormat::Raw`
+    fn schema() -> SchemaRef;
+}
+
+impl Columnar for PerformanceIndicators {
+    fn schema() -> SchemaRef {
+        let price = |name| Field::new(name, DataType::Float64, true);
+        let text = |name| Field::new(name, DataType::Utf8, true);
+        let flag = |name| Field::new(name, DataType::Boolean, false);
+        Arc::new(Schema::new(vec![
+            Field::new("ticker", DataType::Utf8, false),
+            Field::new(
+                "time",
+                DataType::Timestamp(TimeUnit::Microsecond, Some("+00:00".into())),
+                false,
+            ),
+            price("current_price"),
+            price("min"),
+            price("max"),
+            price("n_window_sma"),
+            price("percentage_change"),
+            price("abs_change"),
+            Field::new("price_currency", DataType::Utf8, false),
+            text("market_status"),
+            price("price_return"),
+            price("total_return"),
+            flag("split_in_window"),
+            flag("ex_dividend_in_window"),
+            Field::new(
+                "quality",
+                DataType::List(Arc::new(Field::new("item", DataType::Utf8, false))),
+                false,
+            ),
+            text("name"),
+            text("exchange"),
+            text("currency"),
+            text("sector"),
+            text("industry"),
+        ]))
+    }
+}
+
+/// When the Parquet sink writes a record batch, and when it starts a new file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParquetOptions {
+    /// Rows buffered before they are written as a record batch
+    pub batch_rows: usize,
+    /// How long the first buffered row waits before the batch is written anyway
+    pub batch_timeout: Duration,
+    /// Rows written to a file before the next row starts a new one
+    pub rollover_rows: Option<usize>,
+    /// How long a file is written to before the next batch starts a new one
+    pub rollover_interval: Option<Duration>,
+}
+
+impl Default for ParquetOptions {
+    fn default() -> Self {
+        ParquetOptions {
+            batch_rows: 1024,
+            batch_timeout: Duration::seconds(60),
+            rollover_rows: None,
+            rollover_interval: None,
+        }
+    }
+}
+
+struct OpenFile {
+    writer: ArrowWriter<File>,
+    opened: DateTime<Utc>,
+    rows: usize,
+}
+
+/// Writes Parquet files of typed columns. Buffered rows are written as a record batch once there
+/// are enough of them or the first has waited long enough, a flush closes the row group, and
+/// finishing closes the file. Files roll over to new ones named by when they were opened and
+/// numbered, e.g. `indicators-20210813T143000Z-1.parquet`, or are written to the path itself
+/// without rollover.
+pub struct ParquetSink {
+    path: PathBuf,
+    schema: SchemaRef,
+    options: ParquetOptions,
+    decoder: Decoder,
+    batch_started: Option<DateTime<Utc>>,
+    file: Option<OpenFile>,
+    files: usize,
+}
+
+impl ParquetSink {
+    pub fn new(path: impl Into<PathBuf>, schema: SchemaRef) -> Result<Self> {
+        Ok(ParquetSink {
+            path: path.into(),
+            decoder: ReaderBuilder::new(schema.clone())
+                .with_strict_mode(true)
+                .build_decoder()?,
+            schema,
+            options: ParquetOptions::default(),
+            batch_started: None,
+            file: None,
+            files: 0,
+        })
+    }
+
+    pub fn with_options(mut self, options: ParquetOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    fn write_batch(&mut self, now: DateTime<Utc>) -> Result<()> {
+        let batch = match self.decoder.flush()? {
+            Some(batch) => batch,
+            None => return Ok(()),
+        };
+        self.batch_started = None;
+        if self
+            .file
+            .as_ref()
+            .is_some_and(|file| self.rolls_over(file, now))
+        {
+            self.close()?;
+        }
+        if self.file.is_none() {
+            self.file = Some(self.open(now)?);
+        }
+        let file = self.file.as_mut().unwrap();
+        file.writer.write(&batch)?;
+        file.rows += batch.num_rows();
+        Ok(())
+    }
+
+    fn rolls_over(&self, file: &OpenFile, now: DateTime<Utc>) -> bool {
+        let full = matches!(self.options.rollover_rows, Some(rows) if file.rows >= rows);
+        let old = matches!(self.options.rollover_interval, Some(interval) if now - file.opened >= interval);
+        full || old
+    }
+
+    fn open(&mut self, now: DateTime<Utc>) -> Result<OpenFile> {
+        let rolls_over =
+            self.options.rollover_rows.is_some() || self.options.rollover_interval.is_some();
+        // Without rollover, files after the first only happen if writes follow a finish
+        let path = if rolls_over || self.files > 0 {
+            numbered(&self.path, now, self.files + 1)
+        } else {
+            self.path.clone()
+        };
+        info!("Writing Parquet to {}", path.display());
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let writer =
+            ArrowWriter::try_new(File::create(path)?, self.schema.clone(), Some(properties))?;
+        self.files += 1;
+        Ok(OpenFile {
+            writer,
+            opened: now,
+            rows: 0,
+        })
+    }
+
+    fn close(&mut self) -> Result<()> {
+        if let Some(file) = self.file.take() {
+            file.writer.close()?;
+        }
+        Ok(())
+    }
+}
+
+/// `dir/indicators.parquet` as `dir/indicators-20210813T143000Z-1.parquet` for the first file
+/// opened at `at`
+fn numbered(path: &Path, at: DateTime<Utc>, number: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().unwrap_or_else(|| "parquet".as_ref());
+    let name = format!(
+        "{}-{}-{}.{}",
+        stem,
+        at.format("%Y%m%dT%H%M%SZ"),
+        number,
+        extension.to_string_lossy()
+    );
+    path.with_file_name(name)
+}
+
+impl<T: Serialize> Sink<T> for ParquetSink {
+    fn write(&mut self, record: &T) -> Result<()> {
+        let decoder = &mut self.decoder;
+        with_number_format(NumberFormat::Raw, || {
+            with_field_layout(FieldLayout::Nested, || {
+                decoder.serialize(std::slice::from_ref(record))
+            })
+        })?;
+        let now = Utc::now();
+        let started = *self.batch_started.get_or_insert(now);
+        if self.decoder.len() >= self.options.batch_rows
+            || now - started >= self.options.batch_timeout
+        {
+            self.write_batch(now)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the buffered rows and closes the row group
+    fn flush(&mut self) -> Result<()> {
+        self.write_batch(Utc::now())?;
+        if let Some(file) = &mut self.file {
+            file.writer.flush()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.write_batch(Utc::now())?;
+        self.close()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::{Array, Float64Array, ListArray, RecordBatch, StringArray};
+    use lib::quality::{QualityFlag, QualityFlags};
+    use lib::ticker::Ticker;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use std::fs::read_dir;
+
+    fn indicators(ticker: &str) -> PerformanceIndicators {
+        PerformanceIndicators::new(
+            30,
+            &[100f64, 110f64, 105f64],
+            Ticker::new(ticker.to_owned()),
+            Utc::now(),
+        )
+        .with_quality(QualityFlags(vec![QualityFlag::Stale, QualityFlag::Gapped]))
+    }
+
+    fn read(path: &Path) -> Vec<RecordBatch> {
+        ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    #[test]
+    fn parquet_sink_writes_typed_columns() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("indicators.parquet");
+        let mut sink = ParquetSink::new(&path, PerformanceIndicators::schema()).unwrap();
+        Sink::write(&mut sink, &indicators("AAPL")).unwrap();
+        Sink::write(&mut sink, &indicators("MSFT")).unwrap();
+        Sink::<PerformanceIndicators>::finish(&mut sink).unwrap();
+
+        let batches = read(&path);
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        assert_eq!(batch.schema(), PerformanceIndicators::schema());
+        let tickers = batch
+            .column_by_name("ticker")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(tickers.value(1), "MSFT");
+        let prices = batch
+            .column_by_name("current_price")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(prices.value(0), 105f64);
+        let currencies = batch
+            .column_by_name("price_currency")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(currencies.value(0), "USD");
+        assert!(batch.column_by_name("n_window_sma").unwrap().is_null(0));
+        let quality = batch
+            .column_by_name("quality")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<ListArray>()
+            .unwrap();
+        assert_eq!(quality.value_length(0), 2);
+    }
+
+    #[test]
+    fn parquet_sink_writes_batches_of_rows_and_rolls_over() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("indicators.parquet");
+        let mut sink = ParquetSink::new(&path, PerformanceIndicators::schema())
+            .unwrap()
+            .with_options(ParquetOptions {
+                batch_rows: 2,
+                rollover_rows: Some(4),
+                ..ParquetOptions::default()
+            });
+        for _ in 0..3 {
+            Sink::write(&mut sink, &indicators("AAPL")).unwrap();
+        }
+        // The first two rows are a batch, the third waits for the next one
+        assert_eq!(sink.decoder.len(), 1);
+        assert_eq!(sink.file.as_ref().unwrap().rows, 2);
+        for _ in 0..3 {
+            Sink::write(&mut sink, &indicators("AAPL")).unwrap();
+        }
+        Sink::<PerformanceIndicators>::finish(&mut sink).unwrap();
+
+        let mut rows: Vec<usize> = read_dir(dir.path())
+            .unwrap()
+            .map(|entry| read(&entry.unwrap().path()))
+            .map(|batches| batches.iter().map(RecordBatch::num_rows).sum())
+            .collect();
+        rows.sort_unstable();
+        assert_eq!(rows, vec![2, 4]);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn numbered_names_files_by_when_they_were_opened() {
+        let at = DateTime::parse_from_rfc3339("2021-08-13T14:30:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            numbered(Path::new("out/indicators.parquet"), at, 2),
+            PathBuf::from("out/indicators-20210813T143000Z-2.parquet")
+        );
+    }
+}
@@ -0,0 +1,369 @@
+use super::Sink;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::{info, warn};
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use strum::EnumString;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum RotationPeriod {
+    /// Files named by the hour, e.g. `indicators-2021-08-13T14.csv`
+    Hourly,
+    /// Files named by the date, e.g. `indicators-2021-08-13.csv`
+    Daily,
+}
+
+impl RotationPeriod {
+    fn name(self, at: DateTime<Utc>) -> String {
+        let format = match self {
+            RotationPeriod::Hourly => "%Y-%m-%dT%H",
+            RotationPeriod::Daily => "%Y-%m-%d",
+        };
+        at.format(format).to_string()
+    }
+}
+
+/// How text output is written to its file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FileOptions {
+    /// Continues an existing file instead of truncating it
+    pub append: bool,
+    /// Bytes written to a file before the next record starts a new one
+    pub rotate_size: Option<u64>,
+    /// Writes to a file named by the current period, starting a new one when it changes
+    pub rotate_every: Option<RotationPeriod>,
+    /// Compresses files to `.gz` once they have been rotated out
+    pub gzip: bool,
+}
+
+/// Opens the sink writing one file, given the first line of the file if it is being appended to
+pub type OpenSink<T> = fn(Box<dyn Write + Send>, Option<String>) -> Box<dyn Sink<T>>;
+
+struct OpenFile<T> {
+    sink: Box<dyn Sink<T>>,
+    path: PathBuf,
+    period: Option<String>,
+    part: usize,
+    written: Arc<AtomicU64>,
+}
+
+/// Writes text output to a file, truncating or appending to it, and rotating to a new one once it
+/// is big enough or the period it is named by has passed. Each file gets a sink of its own, so a
+/// new file starts with a header again. Parts of a period are numbered, e.g.
+/// `indicators-2021-08-13.csv` is followed by `indicators-2021-08-13-1.csv`.
+pub struct FileSink<T> {
+    path: PathBuf,
+    options: FileOptions,
+    open_sink: OpenSink<T>,
+    file: OpenFile<T>,
+}
+
+impl<T> FileSink<T> {
+    pub fn open(
+        path: impl Into<PathBuf>,
+        options: FileOptions,
+        open_sink: OpenSink<T>,
+    ) -> Result<Self> {
+        Self::open_at(path.into(), options, open_sink, Utc::now())
+    }
+
+    fn open_at(
+        path: PathBuf,
+        options: FileOptions,
+        open_sink: OpenSink<T>,
+        now: DateTime<Utc>,
+    ) -> Result<Self> {
+        let period = options.rotate_every.map(|period| period.name(now));
+        let part = if options.append {
+            last_part(&path, period.as_deref())
+        } else {
+            0
+        };
+        let file = open_file(&path, period, part, options.append, open_sink)?;
+        Ok(FileSink {
+            path,
+            options,
+            open_sink,
+            file,
+        })
+    }
+
+    fn write_at(&mut self, record: &T, now: DateTime<Utc>) -> Result<()> {
+        let period = self.options.rotate_every.map(|period| period.name(now));
+        if period != self.file.period {
+            self.rotate(period, 0)?;
+        } else if matches!(self.options.rotate_size, Some(size) if self.file.written.load(Ordering::Relaxed) >= size)
+        {
+            self.rotate(period, self.file.part + 1)?;
+        }
+        self.file.sink.write(record)
+    }
+
+    fn rotate(&mut self, period: Option<String>, part: usize) -> Result<()> {
+        let file = open_file(&self.path, period, part, false, self.open_sink)?;
+        let mut rotated = std::mem::replace(&mut self.file, file);
+        rotated.sink.finish()?;
+        drop(rotated.sink);
+        if self.options.gzip {
+            if let Err(e) = gzip(&rotated.path) {
+                warn!("Failed to compress {}: {:?}", rotated.path.display(), e);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn open_file<T>(
+    path: &Path,
+    period: Option<String>,
+    part: usize,
+    append: bool,
+    open_sink: OpenSink<T>,
+) -> Result<OpenFile<T>> {
+    let path = part_path(path, period.as_deref(), part);
+    info!("Writing output to {}", path.display());
+    let (file, first_line) = if append {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        (file, first_line(&path)?)
+    } else {
+        (File::create(&path)?, None)
+    };
+    let written = Arc::new(AtomicU64::new(file.metadata()?.len()));
+    let writer = Counted {
+        inner: BufWriter::new(file),
+        written: written.clone(),
+    };
+    Ok(OpenFile {
+        sink: open_sink(Box::new(writer), first_line),
+        path,
+        period,
+        part,
+        written,
+    })
+}
+
+/// `dir/indicators.csv` as `dir/indicators-2021-08-13-1.csv` for the second part of a day, or
+/// `dir/indicators-1.csv` for the second part without a period
+fn part_path(path: &Path, period: Option<&str>, part: usize) -> PathBuf {
+    let mut name: OsString = path.file_stem().unwrap_or_default().to_owned();
+    if let Some(period) = period {
+        name.push(format!("-{}", period));
+    }
+    if part > 0 {
+        name.push(format!("-{}", part));
+    }
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
+fn gzipped(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".gz");
+    PathBuf::from(name)
+}
+
+/// The part of a period to append to: the last one written, unless it has already been rotated
+/// out and compressed
+fn last_part(path: &Path, period: Option<&str>) -> usize {
+    let exists = |part| {
+        let path = part_path(path, period, part);
+        path.exists() || gzipped(&path).exists()
+    };
+    let mut part = 0;
+    while exists(part + 1) {
+        part += 1;
+    }
+    if gzipped(&part_path(path, period, part)).exists() {
+        part + 1
+    } else {
+        part
+    }
+}
+
+/// The first line of a file without its line ending, if it has one
+fn first_line(path: &Path) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    BufReader::new(File::open(path)?).read_line(&mut line)?;
+    let line = line.trim_end_matches(&['\r', '\n'][..]);
+    Ok(Some(line.to_owned()).filter(|line| !line.is_empty()))
+}
+
+/// Replaces a file with a gzip compressed copy of it
+fn gzip(path: &Path) -> io::Result<()> {
+    let mut encoder = GzEncoder::new(
+        BufWriter::new(File::create(gzipped(path))?),
+        Compression::default(),
+    );
+    io::copy(&mut File::open(path)?, &mut encoder)?;
+    encoder.finish()?.flush()?;
+    fs::remove_file(path)
+}
+
+/// Counts the bytes written through to a file. Sinks buffer some of their output themselves, so a
+/// file can grow past its rotation size by a buffer before it is rotated.
+struct Counted<W> {
+    inner: W,
+    written: Arc<AtomicU64>,
+}
+
+impl<W: Write> Write for Counted<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written.fetch_add(written as u64, Ordering::Relaxed);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<T> Sink<T> for FileSink<T> {
+    fn write(&mut self, record: &T) -> Result<()> {
+        self.write_at(record, Utc::now())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.file.sink.flush()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.file.sink.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sinks::{CsvSink, JsonSink};
+    use chrono::TimeZone;
+    use flate2::read::GzDecoder;
+    use serde::Serialize;
+    use std::io::Read;
+
+    #[derive(Serialize)]
+    struct Record {
+        ticker: &'static str,
+        price: f64,
+    }
+
+    const AAPL: Record = Record {
+        ticker: "AAPL",
+        price: 146.95,
+    };
+
+    fn csv(writer: Box<dyn Write + Send>, header: Option<String>) -> Box<dyn Sink<Record>> {
+        match header {
+            Some(header) => Box::new(CsvSink::after_header(writer, header)),
+            None => Box::new(CsvSink::new(writer)),
+        }
+    }
+
+    fn json(writer: Box<dyn Write + Send>, _header: Option<String>) -> Box<dyn Sink<Record>> {
+        Box::new(JsonSink::new(writer))
+    }
+
+    fn read(path: &Path) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn file_sink_appends_without_writing_the_header_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("indicators.csv");
+        let options = FileOptions {
+            append: true,
+            ..FileOptions::default()
+        };
+        for _ in 0..2 {
+            let mut sink = FileSink::open(&path, options, csv).unwrap();
+            sink.write(&AAPL).unwrap();
+            sink.finish().unwrap();
+        }
+        assert_eq!(read(&path), "ticker,price\nAAPL,146.95\nAAPL,146.95\n");
+
+        let mut sink = FileSink::open(&path, FileOptions::default(), csv).unwrap();
+        sink.write(&AAPL).unwrap();
+        sink.finish().unwrap();
+        assert_eq!(read(&path), "ticker,price\nAAPL,146.95\n");
+    }
+
+    #[test]
+    fn file_sink_rotates_daily_files_and_compresses_rotated_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("indicators.json");
+        let options = FileOptions {
+            rotate_every: Some(RotationPeriod::Daily),
+            gzip: true,
+            ..FileOptions::default()
+        };
+        let day = Utc.with_ymd_and_hms(2021, 8, 13, 23, 59, 0).unwrap();
+        let mut sink = FileSink::open_at(path.clone(), options, json, day).unwrap();
+        sink.write_at(&AAPL, day).unwrap();
+        sink.write_at(&AAPL, day + chrono::Duration::minutes(2))
+            .unwrap();
+        sink.finish().unwrap();
+
+        let mut rotated = String::new();
+        GzDecoder::new(File::open(dir.path().join("indicators-2021-08-13.json.gz")).unwrap())
+            .read_to_string(&mut rotated)
+            .unwrap();
+        let rotated: serde_json::Value = serde_json::from_str(&rotated).unwrap();
+        assert_eq!(rotated[0]["ticker"], "AAPL");
+        assert!(!dir.path().join("indicators-2021-08-13.json").exists());
+        let current: serde_json::Value =
+            serde_json::from_str(&read(&dir.path().join("indicators-2021-08-14.json"))).unwrap();
+        assert_eq!(current.as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn file_sink_rotates_by_size_and_appends_to_the_last_part() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("indicators.csv");
+        let options = FileOptions {
+            append: true,
+            rotate_size: Some(1),
+            ..FileOptions::default()
+        };
+        let mut sink = FileSink::open(&path, options, csv).unwrap();
+        for _ in 0..2 {
+            sink.write(&AAPL).unwrap();
+            sink.flush().unwrap();
+        }
+        sink.finish().unwrap();
+        assert_eq!(read(&path), "ticker,price\nAAPL,146.95\n");
+        assert_eq!(
+            read(&dir.path().join("indicators-1.csv")),
+            "ticker,price\nAAPL,146.95\n"
+        );
+
+        assert_eq!(last_part(&path, None), 1);
+        gzip(&dir.path().join("indicators-1.csv")).unwrap();
+        assert_eq!(last_part(&path, None), 2);
+    }
+
+    #[test]
+    fn part_path_names_files_by_period_and_part() {
+        let path = Path::new("out/indicators.csv");
+        assert_eq!(part_path(path, None, 0), path);
+        assert_eq!(
+            part_path(path, Some("2021-08-13"), 2),
+            PathBuf::from("out/indicators-2021-08-13-2.csv")
+        );
+        assert_eq!(
+            part_path(Path::new("indicators"), Some("2021-08-13T14"), 0),
+            PathBuf::from("indicators-2021-08-13T14")
+        );
+    }
+}
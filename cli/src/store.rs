@@ -0,0 +1,466 @@
+use crate::actors::messages::{Bars, PerformanceIndicators};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use lib::formatter::{with_field_layout, with_number_format, FieldLayout, NumberFormat};
+use lib::quote_provider::{Bar, Interval};
+use lib::ticker::Ticker;
+use rusqlite::types::{Type, Value as SqlValue, ValueRef};
+use rusqlite::{params, params_from_iter, Connection, Row};
+use serde_json::{Map, Value};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Each migration moves the schema up one version, recorded in SQLite's `user_version`. Add new
+/// migrations to the end rather than editing released ones.
+const MIGRATIONS: [&str; 3] = [
+    "CREATE TABLE indicators (
+        ticker TEXT NOT NULL,
+        time TEXT NOT NULL,
+        current_price REAL,
+        min REAL,
+        max REAL,
+        n_window_sma REAL,
+        percentage_change REAL,
+        abs_change REAL,
+        market_status TEXT,
+        price_return REAL,
+        total_return REAL,
+        split_in_window INTEGER NOT NULL,
+        ex_dividend_in_window INTEGER NOT NULL,
+        quality TEXT NOT NULL,
+        name TEXT,
+        exchange TEXT,
+        currency TEXT,
+        sector TEXT,
+        industry TEXT
+    );
+    CREATE UNIQUE INDEX indicators_by_ticker_and_time ON indicators (ticker, time);
+    CREATE INDEX indicators_by_time ON indicators (time);",
+    "CREATE TABLE bars (
+        ticker TEXT NOT NULL,
+        interval TEXT NOT NULL,
+        time TEXT NOT NULL,
+        open REAL NOT NULL,
+        high REAL NOT NULL,
+        low REAL NOT NULL,
+        close REAL NOT NULL,
+        adjclose REAL NOT NULL,
+        volume INTEGER NOT NULL,
+        PRIMARY KEY (ticker, interval, time)
+    );",
+    "ALTER TABLE indicators ADD COLUMN price_currency TEXT;",
+];
+
+/// How a column of the indicators table maps to the JSON of `PerformanceIndicators`
+#[derive(Clone, Copy)]
+enum Column {
+    Text,
+    Real,
+    Bool,
+    /// Stored as JSON text
+    List,
+}
+
+const INDICATOR_COLUMNS: [(&str, Column); 20] = [
+    ("ticker", Column::Text),
+    ("time", Column::Text),
+    ("current_price", Column::Real),
+    ("min", Column::Real),
+    ("max", Column::Real),
+    ("n_window_sma", Column::Real),
+    ("percentage_change", Column::Real),
+    ("abs_change", Column::Real),
+    ("price_currency", Column::Text),
+    ("market_status", Column::Text),
+    ("price_return", Column::Real),
+    ("total_return", Column::Real),
+    ("split_in_window", Column::Bool),
+    ("ex_dividend_in_window", Column::Bool),
+    ("quality", Column::List),
+    ("name", Column::Text),
+    ("exchange", Column::Text),
+    ("currency", Column::Text),
+    ("sector", Column::Text),
+    ("industry", Column::Text),
+];
+
+pub type SharedStore = Arc<Store>;
+
+/// Runs `f` on a thread set aside for blocking, as every SQLite call blocks, so that it doesn't
+/// hold up the actors and the HTTP API
+pub async fn unblock<T, F>(f: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f).await?
+}
+
+/// Rows from `from` up to and including `until`, keeping the latest `limit`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HistoryRange {
+    pub from: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: usize,
+}
+
+/// An SQLite database of every indicator row, and optionally the raw bars they came from
+pub struct Store {
+    connection: Mutex<Connection>,
+}
+
+impl Store {
+    /// Opens or creates the database at `path`, migrating it to the latest schema
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let mut connection = Connection::open(path)?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        migrate(&mut connection)?;
+        Ok(Store {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Stores indicator rows in one transaction, replacing any already stored for the same ticker
+    /// and time
+    pub fn insert_indicators(&self, rows: &[PerformanceIndicators]) -> Result<()> {
+        let names: Vec<&str> = INDICATOR_COLUMNS.iter().map(|(name, _)| *name).collect();
+        let placeholders = vec!["?"; names.len()].join(", ");
+        let sql = format!(
+            "INSERT OR REPLACE INTO indicators ({}) VALUES ({})",
+            names.join(", "),
+            placeholders
+        );
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        {
+            let mut insert = transaction.prepare_cached(&sql)?;
+            for indicators in rows {
+                let json = with_number_format(NumberFormat::Raw, || {
+                    with_field_layout(FieldLayout::Nested, || serde_json::to_value(indicators))
+                })?;
+                let fields = json
+                    .as_object()
+                    .ok_or_else(|| anyhow!("Indicators didn't serialize to an object"))?;
+                let values = INDICATOR_COLUMNS.iter().map(|(name, column)| match *name {
+                    "time" => SqlValue::Text(timestamp(indicators.time())),
+                    _ => to_sql(fields.get(*name).unwrap_or(&Value::Null), *column),
+                });
+                insert.execute(params_from_iter(values))?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Stores bars, replacing any already stored for the same ticker, interval and time
+    pub fn insert_bars(&self, bars: &Bars) -> Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        {
+            let mut insert = transaction.prepare_cached(
+                "INSERT OR REPLACE INTO bars
+                 (ticker, interval, time, open, high, low, close, adjclose, volume)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )?;
+            let interval = bars.interval().as_str();
+            for bar in bars.bars() {
+                insert.execute(params![
+                    bars.ticker().as_str(),
+                    interval,
+                    timestamp(bar.timestamp),
+                    bar.open,
+                    bar.high,
+                    bar.low,
+                    bar.close,
+                    bar.adjclose,
+                    bar.volume as i64,
+                ])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// A ticker's indicator rows in `range`, oldest first, with plain numbers
+    pub fn indicators(&self, ticker: &Ticker, range: HistoryRange) -> Result<Vec<Value>> {
+        let names: Vec<&str> = INDICATOR_COLUMNS.iter().map(|(name, _)| *name).collect();
+        let sql = format!(
+            "SELECT * FROM (SELECT {} FROM indicators WHERE {} ORDER BY time DESC LIMIT ?4)
+             ORDER BY time",
+            names.join(", "),
+            RANGE_FILTER
+        );
+        let connection = self.connection.lock().unwrap();
+        let mut query = connection.prepare_cached(&sql)?;
+        let rows = query.query_map(range_params(ticker, range), |row| {
+            let mut fields = Map::new();
+            for (i, (name, column)) in INDICATOR_COLUMNS.iter().enumerate() {
+                fields.insert(name.to_string(), from_sql(row.get_ref(i)?, *column));
+            }
+            Ok(Value::Object(fields))
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// A ticker's stored bars of `interval` in `range`, oldest first
+    pub fn bars(
+        &self,
+        ticker: &Ticker,
+        interval: Interval,
+        range: HistoryRange,
+    ) -> Result<Vec<Bar>> {
+        let sql = format!(
+            "SELECT * FROM (SELECT time, open, high, low, close, adjclose, volume FROM bars
+             WHERE {} AND interval = ?5 ORDER BY time DESC LIMIT ?4) ORDER BY time",
+            RANGE_FILTER
+        );
+        let (ticker, from, until, limit) = range_params(ticker, range);
+        let connection = self.connection.lock().unwrap();
+        let mut query = connection.prepare_cached(&sql)?;
+        let rows = query.query_map((ticker, from, until, limit, interval.as_str()), bar)?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+}
+
+const RANGE_FILTER: &str =
+    "ticker = ?1 AND (?2 IS NULL OR time >= ?2) AND (?3 IS NULL OR time <= ?3)";
+
+/// Parameters `?1` to `?4` of a range query: the ticker, `from`, `until` and the row limit
+fn range_params(
+    ticker: &Ticker,
+    range: HistoryRange,
+) -> (String, Option<String>, Option<String>, i64) {
+    (
+        ticker.as_str().to_owned(),
+        range.from.map(timestamp),
+        range.until.map(timestamp),
+        range.limit as i64,
+    )
+}
+
+fn migrate(connection: &mut Connection) -> Result<()> {
+    let version: usize = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version > MIGRATIONS.len() {
+        return Err(anyhow!(
+            "Database schema version {} is newer than the latest known, {}",
+            version,
+            MIGRATIONS.len()
+        ));
+    }
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", i + 1)?;
+        transaction.commit()?;
+    }
+    Ok(())
+}
+
+/// Times are stored as fixed width RFC 3339 text in UTC, so they sort as text
+fn timestamp(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+fn to_sql(value: &Value, column: Column) -> SqlValue {
+    match (value, column) {
+        (Value::Null, _) => SqlValue::Null,
+        (Value::Bool(b), _) => SqlValue::Integer(i64::from(*b)),
+        (Value::Number(n), Column::Real) => n.as_f64().map_or(SqlValue::Null, SqlValue::Real),
+        (Value::String(s), _) => SqlValue::Text(s.clone()),
+        (value, _) => SqlValue::Text(value.to_string()),
+    }
+}
+
+fn from_sql(value: ValueRef, column: Column) -> Value {
+    match (value, column) {
+        (ValueRef::Null, _) => Value::Null,
+        (ValueRef::Integer(i), Column::Bool) => Value::Bool(i != 0),
+        (ValueRef::Integer(i), _) => Value::from(i),
+        (ValueRef::Real(r), _) => Value::from(r),
+        (ValueRef::Text(text), Column::List) => {
+            serde_json::from_slice(text).unwrap_or_else(|_| Value::Array(vec![]))
+        }
+        (ValueRef::Text(text), _) => Value::String(String::from_utf8_lossy(text).into_owned()),
+        (ValueRef::Blob(_), _) => Value::Null,
+    }
+}
+
+fn bar(row: &Row) -> rusqlite::Result<Bar> {
+    let time: String = row.get(0)?;
+    Ok(Bar {
+        timestamp: DateTime::parse_from_rfc3339(&time)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(e)))?
+            .with_timezone(&Utc),
+        open: row.get(1)?,
+        high: row.get(2)?,
+        low: row.get(3)?,
+        close: row.get(4)?,
+        adjclose: row.get(5)?,
+        volume: row.get::<_, i64>(6)? as u64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+    use lib::quality::{QualityFlag, QualityFlags};
+
+    fn indicators(ticker: &str, time: DateTime<Utc>) -> PerformanceIndicators {
+        PerformanceIndicators::new(30, &[100f64, 110f64], Ticker::from(ticker), time)
+            .with_quality(QualityFlags(vec![QualityFlag::Outlier]))
+    }
+
+    fn all() -> HistoryRange {
+        HistoryRange {
+            from: None,
+            until: None,
+            limit: 100,
+        }
+    }
+
+    #[test]
+    fn store_migrates_new_databases_and_reopens_existing_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tracker.db");
+        let store = Store::open(&path).unwrap();
+        let time = Utc.with_ymd_and_hms(2021, 8, 13, 14, 30, 0).unwrap();
+        store
+            .insert_indicators(&[indicators("MSFT", time)])
+            .unwrap();
+        drop(store);
+
+        let store = Store::open(&path).unwrap();
+        let version: usize = store
+            .connection
+            .lock()
+            .unwrap()
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+        assert_eq!(
+            store
+                .indicators(&Ticker::from("MSFT"), all())
+                .unwrap()
+                .len(),
+            1
+        );
+
+        store
+            .connection
+            .lock()
+            .unwrap()
+            .pragma_update(None, "user_version", MIGRATIONS.len() + 1)
+            .unwrap();
+        drop(store);
+        assert!(Store::open(&path).is_err());
+    }
+
+    #[test]
+    fn store_answers_history_queries_with_typed_fields() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::open(dir.path().join("tracker.db")).unwrap();
+        let start = Utc.with_ymd_and_hms(2021, 8, 13, 14, 30, 0).unwrap();
+        for minutes in 0..5 {
+            let time = start + Duration::minutes(minutes);
+            store
+                .insert_indicators(&[indicators("MSFT", time), indicators("AAPL", time)])
+                .unwrap();
+        }
+
+        let rows = store
+            .indicators(
+                &Ticker::from("MSFT"),
+                HistoryRange {
+                    from: Some(start + Duration::minutes(1)),
+                    until: Some(start + Duration::minutes(4)),
+                    limit: 2,
+                },
+            )
+            .unwrap();
+        let times: Vec<&str> = rows
+            .iter()
+            .map(|row| row["time"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            times,
+            vec!["2021-08-13T14:33:00.000000Z", "2021-08-13T14:34:00.000000Z"]
+        );
+        assert_eq!(rows[0]["ticker"], "MSFT");
+        assert_eq!(rows[0]["current_price"], 110f64);
+        assert_eq!(rows[0]["abs_change"], 10f64);
+        assert_eq!(rows[0]["price_currency"], "USD");
+        assert_eq!(rows[0]["split_in_window"], false);
+        assert_eq!(rows[0]["quality"], serde_json::json!(["outlier"]));
+        assert_eq!(rows[0]["sector"], Value::Null);
+    }
+
+    #[test]
+    fn store_replaces_bars_fetched_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::open(dir.path().join("tracker.db")).unwrap();
+        let bar = |minutes, close| Bar {
+            timestamp: Utc.with_ymd_and_hms(2021, 8, 13, 14, minutes, 0).unwrap(),
+            open: 1f64,
+            high: 2f64,
+            low: 0.5f64,
+            close,
+            adjclose: close,
+            volume: 100,
+        };
+        let ticker = Ticker::from("MSFT");
+        store
+            .insert_bars(&Bars::new(
+                ticker.clone(),
+                Interval::OneMinute,
+                vec![bar(30, 1f64), bar(31, 1.5f64)],
+            ))
+            .unwrap();
+        store
+            .insert_bars(&Bars::new(
+                ticker.clone(),
+                Interval::OneMinute,
+                vec![bar(31, 1.6f64), bar(32, 1.7f64)],
+            ))
+            .unwrap();
+
+        store
+            .insert_bars(&Bars::new(
+                ticker.clone(),
+                Interval::FiveMinutes,
+                vec![bar(30, 2f64)],
+            ))
+            .unwrap();
+
+        assert_eq!(
+            store.bars(&ticker, Interval::OneMinute, all()).unwrap(),
+            vec![bar(30, 1f64), bar(31, 1.6f64), bar(32, 1.7f64)]
+        );
+        assert_eq!(
+            store.bars(&ticker, Interval::FiveMinutes, all()).unwrap(),
+            vec![bar(30, 2f64)]
+        );
+    }
+
+    #[test]
+    fn store_replaces_indicators_for_the_same_ticker_and_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::open(dir.path().join("tracker.db")).unwrap();
+        let time = Utc.with_ymd_and_hms(2021, 8, 13, 14, 30, 0).unwrap();
+        store
+            .insert_indicators(&[indicators("MSFT", time)])
+            .unwrap();
+        store
+            .insert_indicators(&[PerformanceIndicators::new(
+                30,
+                &[100f64, 120f64],
+                Ticker::from("MSFT"),
+                time,
+            )])
+            .unwrap();
+
+        let rows = store.indicators(&Ticker::from("MSFT"), all()).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["current_price"], 120f64);
+    }
+}
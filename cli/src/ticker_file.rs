@@ -0,0 +1,322 @@
+use crate::config::DEFAULT_GROUP;
+use crate::schedule::TickerGroup;
+use csv::{Position, ReaderBuilder, StringRecord, Trim};
+use lib::ticker::{Ticker, TickerParseError};
+use std::collections::HashMap;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum TickerFileError {
+    #[error("line {0}: {1}")]
+    InvalidTicker(usize, TickerParseError),
+
+    #[error("line {0}: {1} was already listed on line {2}")]
+    Duplicate(usize, Ticker, usize),
+
+    #[error("line {0}: expected {1} columns but found {2}")]
+    ColumnCount(usize, usize, usize),
+
+    #[error("line {0}: empty section name")]
+    EmptySection(usize),
+
+    #[error("line {0}: {1}")]
+    Csv(usize, String),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct TickerEntry {
+    pub ticker: Ticker,
+    /// From a `group` column, or the `[section]` the ticker is listed under
+    pub group: Option<String>,
+}
+
+/// Column positions of a CSV ticker file, found from its `symbol` header
+struct Columns {
+    count: usize,
+    symbol: usize,
+    group: Option<usize>,
+}
+
+impl Columns {
+    fn from_header(header: &StringRecord) -> Option<Self> {
+        let position = |name: &str| {
+            header
+                .iter()
+                .position(|column| column.eq_ignore_ascii_case(name))
+        };
+        Some(Columns {
+            count: header.len(),
+            symbol: position("symbol")?,
+            group: position("group"),
+        })
+    }
+}
+
+/// The name of a `[name]` section header, if `line` is one
+fn section_name(line: &str, line_number: usize) -> Result<Option<String>, TickerFileError> {
+    match line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+        Some(name) if name.trim().is_empty() => Err(TickerFileError::EmptySection(line_number)),
+        Some(name) => Ok(Some(name.trim().to_owned())),
+        None => Ok(None),
+    }
+}
+
+/// Tickers and line numbers seen so far, rejecting duplicates
+#[derive(Default)]
+struct Entries {
+    entries: Vec<TickerEntry>,
+    seen: HashMap<Ticker, usize>,
+}
+
+impl Entries {
+    fn push(
+        &mut self,
+        symbol: &str,
+        group: Option<String>,
+        line_number: usize,
+    ) -> Result<(), TickerFileError> {
+        let ticker: Ticker = symbol
+            .parse()
+            .map_err(|e| TickerFileError::InvalidTicker(line_number, e))?;
+        if let Some(first) = self.seen.get(&ticker) {
+            return Err(TickerFileError::Duplicate(line_number, ticker, *first));
+        }
+        self.seen.insert(ticker.clone(), line_number);
+        self.entries.push(TickerEntry { ticker, group });
+        Ok(())
+    }
+}
+
+/// The line a record starts on. The reader's byte offsets point at any blank or comment lines it
+/// skipped before the record, so those are skipped here too.
+fn line_number(contents: &str, position: Option<&Position>) -> usize {
+    let mut offset = position.map_or(0, |position| position.byte() as usize);
+    while let Some(rest) = contents.get(offset..) {
+        let skipped = match rest.as_bytes().first() {
+            Some(b'\r' | b'\n') => 1,
+            Some(b'#') => rest.find('\n').map_or(rest.len(), |end| end + 1),
+            _ => break,
+        };
+        offset += skipped;
+    }
+    contents[..offset.min(contents.len())].matches('\n').count() + 1
+}
+
+fn csv_reader(contents: &str) -> csv::Reader<&[u8]> {
+    ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(Trim::All)
+        .comment(Some(b'#'))
+        .from_reader(contents.as_bytes())
+}
+
+/// Parses a ticker file. Tickers may be separated by commas or newlines, `#` starts a comment,
+/// and `[name]` starts a watchlist section whose tickers form a group. A first line with a
+/// `symbol` column makes the file a CSV, with quoted fields and `#` comment lines, whose tickers
+/// are grouped by an optional `group` column. Other columns are ignored.
+pub fn parse_ticker_file(contents: &str) -> Result<Vec<TickerEntry>, TickerFileError> {
+    let header = csv_reader(contents)
+        .records()
+        .next()
+        .and_then(Result::ok)
+        .and_then(|header| Columns::from_header(&header));
+    match header {
+        Some(columns) => parse_csv(contents, columns),
+        None => parse_list(contents),
+    }
+}
+
+fn parse_csv(contents: &str, columns: Columns) -> Result<Vec<TickerEntry>, TickerFileError> {
+    let mut entries = Entries::default();
+    let mut section: Option<String> = None;
+    for record in csv_reader(contents).records().skip(1) {
+        let record = record.map_err(|e| {
+            TickerFileError::Csv(line_number(contents, e.position()), e.to_string())
+        })?;
+        let line_number = line_number(contents, record.position());
+        if record.len() == 1 {
+            if let Some(name) = section_name(&record[0], line_number)? {
+                section = Some(name);
+                continue;
+            }
+        }
+        if record.len() != columns.count {
+            return Err(TickerFileError::ColumnCount(
+                line_number,
+                columns.count,
+                record.len(),
+            ));
+        }
+        let group = columns
+            .group
+            .map(|i| &record[i])
+            .filter(|group| !group.is_empty())
+            .map(str::to_owned)
+            .or_else(|| section.clone());
+        entries.push(&record[columns.symbol], group, line_number)?;
+    }
+    Ok(entries.entries)
+}
+
+fn parse_list(contents: &str) -> Result<Vec<TickerEntry>, TickerFileError> {
+    let mut entries = Entries::default();
+    let mut section: Option<String> = None;
+    for (index, line) in contents.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.split('#').next().unwrap().trim();
+        if let Some(name) = section_name(line, line_number)? {
+            section = Some(name);
+            continue;
+        }
+        for symbol in line.split(',').filter(|symbol| !symbol.trim().is_empty()) {
+            entries.push(symbol, section.clone(), line_number)?;
+        }
+    }
+    Ok(entries.entries)
+}
+
+/// Writes groups in the format read by `parse_ticker_file`, with the default group's tickers
+/// first and every other group as a `[section]`
+pub fn format_ticker_file(groups: &[TickerGroup]) -> String {
+    let mut contents = String::new();
+    let join = |tickers: &[Ticker]| {
+        tickers
+            .iter()
+            .map(Ticker::as_str)
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    for group in groups
+        .iter()
+        .filter(|group| group.name == DEFAULT_GROUP && !group.tickers.is_empty())
+    {
+        contents.push_str(&join(&group.tickers));
+        contents.push('\n');
+    }
+    for group in groups.iter().filter(|group| group.name != DEFAULT_GROUP) {
+        contents.push_str(&format!("[{}]\n{}\n", group.name, join(&group.tickers)));
+    }
+    contents
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(contents: &str) -> Vec<(String, Option<String>)> {
+        parse_ticker_file(contents)
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.ticker.to_string(), entry.group))
+            .collect()
+    }
+
+    #[test]
+    fn parse_ticker_file_accepts_comma_and_newline_lists_with_comments() {
+        assert_eq!(
+            entries("MSFT,goog,\nAAPL # Apple\n\n# IBM\nBRK.B\n"),
+            vec![
+                ("MSFT".to_owned(), None),
+                ("GOOG".to_owned(), None),
+                ("AAPL".to_owned(), None),
+                ("BRK.B".to_owned(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_ticker_file_groups_tickers_by_section() {
+        assert_eq!(
+            entries("IBM\n[tech]\nMSFT, AAPL\n[ energy ]\nXOM\n"),
+            vec![
+                ("IBM".to_owned(), None),
+                ("MSFT".to_owned(), Some("tech".to_owned())),
+                ("AAPL".to_owned(), Some("tech".to_owned())),
+                ("XOM".to_owned(), Some("energy".to_owned())),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_ticker_file_reads_csv_with_symbol_column() {
+        assert_eq!(
+            entries("name,Symbol,weight,group\nMicrosoft,MSFT,0.5,tech\nExxon,XOM,0.5,\n"),
+            vec![
+                ("MSFT".to_owned(), Some("tech".to_owned())),
+                ("XOM".to_owned(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_ticker_file_reads_quoted_csv_fields_and_sections() {
+        assert_eq!(
+            entries(
+                "# Holdings\nsymbol,name\nBRK.B,\"Berkshire Hathaway, Inc.\"\n[energy]\nXOM,\"Exxon, Mobil\"\n"
+            ),
+            vec![
+                ("BRK.B".to_owned(), None),
+                ("XOM".to_owned(), Some("energy".to_owned())),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_ticker_file_reports_errors_with_line_numbers() {
+        assert_eq!(
+            parse_ticker_file("MSFT\nAAPL\n# comment\nmsft\n"),
+            Err(TickerFileError::Duplicate(4, Ticker::from("MSFT"), 1))
+        );
+        assert_eq!(
+            parse_ticker_file("symbol,name\nMSFT,Microsoft\n\nAAPL\n"),
+            Err(TickerFileError::ColumnCount(4, 2, 1))
+        );
+        assert_eq!(
+            parse_ticker_file("symbol,name\nMSFT,Microsoft\n# comment\nMS FT,Microsoft\n"),
+            Err(TickerFileError::InvalidTicker(
+                4,
+                TickerParseError::InvalidCharacter("MS FT".to_owned(), ' ')
+            ))
+        );
+        assert_eq!(
+            parse_ticker_file("MSFT\nMS FT\n"),
+            Err(TickerFileError::InvalidTicker(
+                2,
+                TickerParseError::InvalidCharacter("MS FT".to_owned(), ' ')
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_ticker_file_accepts_bundled_sp500_list() {
+        let entries = parse_ticker_file(include_str!("../../sp500.txt")).unwrap();
+        assert!(entries.len() > 400);
+        assert!(entries.iter().all(|entry| entry.group.is_none()));
+    }
+
+    #[test]
+    fn format_ticker_file_round_trips_through_parse() {
+        use crate::schedule::Schedule;
+        let groups = vec![
+            TickerGroup::new(
+                DEFAULT_GROUP.to_owned(),
+                vec![Ticker::from("IBM"), Ticker::from("BRK.B")],
+                Schedule::default(),
+            ),
+            TickerGroup::new(
+                "tech".to_owned(),
+                vec![Ticker::from("MSFT")],
+                Schedule::default(),
+            ),
+        ];
+        assert_eq!(
+            entries(&format_ticker_file(&groups)),
+            vec![
+                ("IBM".to_owned(), None),
+                ("BRK.B".to_owned(), None),
+                ("MSFT".to_owned(), Some("tech".to_owned())),
+            ]
+        );
+    }
+}
@@ -10,7 +10,7 @@ pub fn benchmark_min(c: &mut Criterion) {
         group.bench_with_input(
             BenchmarkId::new("Array of values", vec_size),
             &vals,
-            |b, vals| b.iter(|| min(black_box(&vals))),
+            |b, vals| b.iter(|| min(black_box(vals))),
         );
     }
 }
@@ -23,7 +23,7 @@ pub fn benchmark_max(c: &mut Criterion) {
         group.bench_with_input(
             BenchmarkId::new("Array of values", vec_size),
             &vals,
-            |b, vals| b.iter(|| max(black_box(&vals))),
+            |b, vals| b.iter(|| max(black_box(vals))),
         );
     }
 }
@@ -36,7 +36,7 @@ pub fn benchmark_price_diff(c: &mut Criterion) {
         group.bench_with_input(
             BenchmarkId::new("Array of values", vec_size),
             &vals,
-            |b, vals| b.iter(|| price_diff(black_box(&vals))),
+            |b, vals| b.iter(|| price_diff(black_box(vals))),
         );
     }
 }
@@ -49,7 +49,7 @@ pub fn benchmark_n_window_sma(c: &mut Criterion) {
         group.bench_with_input(
             BenchmarkId::new("Array of values", vec_size),
             &vals,
-            |b, vals| b.iter(|| n_window_sma(10, black_box(&vals))),
+            |b, vals| b.iter(|| n_window_sma(10, black_box(vals))),
         );
     }
 }
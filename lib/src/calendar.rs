@@ -0,0 +1,280 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MarketStatus {
+    Open,
+    Closed,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct HalfDay {
+    pub date: NaiveDate,
+    pub close: NaiveTime,
+}
+
+/// Regular trading sessions of an exchange, Monday to Friday in the exchange's timezone,
+/// less holidays and with an early close on half days.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ExchangeCalendar {
+    pub name: String,
+    pub timezone: Tz,
+    pub open: NaiveTime,
+    pub close: NaiveTime,
+    #[serde(default)]
+    pub holidays: Vec<NaiveDate>,
+    #[serde(default)]
+    pub half_days: Vec<HalfDay>,
+}
+
+/// Years the built-in calendars have holidays and half days for
+const BUILT_IN_YEARS: RangeInclusive<i32> = 2000..=2099;
+
+impl ExchangeCalendar {
+    /// NYSE sessions with the exchange's scheduled holidays and 13:00 early closes. Unscheduled
+    /// closures, e.g. national days of mourning, aren't included.
+    pub fn nyse() -> Self {
+        let early_close = NaiveTime::from_hms_opt(13, 0, 0).unwrap();
+        ExchangeCalendar {
+            name: "NYSE".to_owned(),
+            timezone: chrono_tz::America::New_York,
+            open: NaiveTime::from_hms_opt(9, 30, 0).unwrap(),
+            close: NaiveTime::from_hms_opt(16, 0, 0).unwrap(),
+            holidays: BUILT_IN_YEARS.flat_map(nyse_holidays).collect(),
+            half_days: BUILT_IN_YEARS
+                .flat_map(nyse_half_days)
+                .map(|date| HalfDay {
+                    date,
+                    close: early_close,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn nasdaq() -> Self {
+        ExchangeCalendar {
+            name: "NASDAQ".to_owned(),
+            ..ExchangeCalendar::nyse()
+        }
+    }
+
+    /// Opening and closing time of the session on `date`, or `None` if the exchange doesn't trade
+    pub fn session(&self, date: NaiveDate) -> Option<(NaiveTime, NaiveTime)> {
+        if matches!(date.weekday(), Weekday::Sat | Weekday::Sun) || self.holidays.contains(&date) {
+            return None;
+        }
+        let close = self
+            .half_days
+            .iter()
+            .find(|half_day| half_day.date == date)
+            .map_or(self.close, |half_day| half_day.close);
+        Some((self.open, close))
+    }
+
+    pub fn status_at(&self, time: DateTime<Utc>) -> MarketStatus {
+        let local = time.with_timezone(&self.timezone);
+        match self.session(local.date_naive()) {
+            Some((open, close)) if local.time() >= open && local.time() < close => {
+                MarketStatus::Open
+            }
+            _ => MarketStatus::Closed,
+        }
+    }
+}
+
+fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+fn nth_weekday(year: i32, month: u32, weekday: Weekday, n: u8) -> NaiveDate {
+    NaiveDate::from_weekday_of_month_opt(year, month, weekday, n).unwrap()
+}
+
+fn last_weekday(year: i32, month: u32, weekday: Weekday) -> NaiveDate {
+    NaiveDate::from_weekday_of_month_opt(year, month, weekday, 5)
+        .unwrap_or_else(|| nth_weekday(year, month, weekday, 4))
+}
+
+/// Easter Sunday in the Gregorian calendar, by the anonymous Gregorian algorithm
+fn easter(year: i32) -> NaiveDate {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    date(year, month as u32, day as u32)
+}
+
+/// A fixed date holiday, observed on the Friday before when it falls on a Saturday and the
+/// Monday after when it falls on a Sunday
+fn observed(date: NaiveDate) -> NaiveDate {
+    match date.weekday() {
+        Weekday::Sat => date - Duration::days(1),
+        Weekday::Sun => date + Duration::days(1),
+        _ => date,
+    }
+}
+
+fn nyse_holidays(year: i32) -> Vec<NaiveDate> {
+    let mut holidays = vec![
+        nth_weekday(year, 1, Weekday::Mon, 3),
+        nth_weekday(year, 2, Weekday::Mon, 3),
+        easter(year) - Duration::days(2),
+        last_weekday(year, 5, Weekday::Mon),
+        observed(date(year, 7, 4)),
+        nth_weekday(year, 9, Weekday::Mon, 1),
+        nth_weekday(year, 11, Weekday::Thu, 4),
+        observed(date(year, 12, 25)),
+    ];
+    // New Year's Day on a Saturday isn't made up for on the last day of the previous year
+    if date(year, 1, 1).weekday() != Weekday::Sat {
+        holidays.push(observed(date(year, 1, 1)));
+    }
+    if year >= 2022 {
+        holidays.push(observed(date(year, 6, 19)));
+    }
+    holidays.sort_unstable();
+    holidays
+}
+
+/// The day after Thanksgiving, and the days before Independence Day and Christmas when they
+/// are weekdays that aren't holidays
+fn nyse_half_days(year: i32) -> Vec<NaiveDate> {
+    // On a Friday these are observed holidays themselves
+    let monday_to_thursday = |date: &NaiveDate| date.weekday().number_from_monday() <= 4;
+    let mut half_days = vec![nth_weekday(year, 11, Weekday::Thu, 4) + Duration::days(1)];
+    half_days.extend(
+        [date(year, 7, 3), date(year, 12, 24)]
+            .iter()
+            .copied()
+            .filter(monday_to_thursday),
+    );
+    half_days.sort_unstable();
+    half_days
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn calendar() -> ExchangeCalendar {
+        ExchangeCalendar {
+            holidays: vec![NaiveDate::from_ymd_opt(2021, 12, 24).unwrap()],
+            half_days: vec![HalfDay {
+                date: NaiveDate::from_ymd_opt(2021, 11, 26).unwrap(),
+                close: NaiveTime::from_hms_opt(13, 0, 0).unwrap(),
+            }],
+            ..ExchangeCalendar::nyse()
+        }
+    }
+
+    #[test]
+    fn nyse_lists_the_exchange_holidays_and_half_days() {
+        let nyse = ExchangeCalendar::nyse();
+        let in_year = |dates: &[NaiveDate], year| -> Vec<String> {
+            dates
+                .iter()
+                .filter(|date| date.year() == year)
+                .map(|date| date.to_string())
+                .collect()
+        };
+        assert_eq!(
+            in_year(&nyse.holidays, 2027),
+            [
+                "2027-01-01",
+                "2027-01-18",
+                "2027-02-15",
+                "2027-03-26",
+                "2027-05-31",
+                "2027-06-18",
+                "2027-07-05",
+                "2027-09-06",
+                "2027-11-25",
+                "2027-12-24",
+            ]
+        );
+        // New Year's Day 2022 was a Saturday, and Juneteenth was first observed that year
+        assert_eq!(
+            in_year(&nyse.holidays, 2022),
+            [
+                "2022-01-17",
+                "2022-02-21",
+                "2022-04-15",
+                "2022-05-30",
+                "2022-06-20",
+                "2022-07-04",
+                "2022-09-05",
+                "2022-11-24",
+                "2022-12-26",
+            ]
+        );
+        let half_days: Vec<NaiveDate> = nyse
+            .half_days
+            .iter()
+            .map(|half_day| half_day.date)
+            .collect();
+        assert_eq!(
+            in_year(&half_days, 2024),
+            ["2024-07-03", "2024-11-29", "2024-12-24"]
+        );
+        assert_eq!(in_year(&half_days, 2026), ["2026-11-27", "2026-12-24"]);
+    }
+
+    #[test]
+    fn status_at_is_open_during_regular_session() {
+        // 10:00 in New York
+        let time = Utc.with_ymd_and_hms(2021, 8, 10, 14, 0, 0).unwrap();
+        assert_eq!(calendar().status_at(time), MarketStatus::Open);
+    }
+
+    #[test]
+    fn status_at_is_closed_outside_regular_session() {
+        // 09:00 and 16:00 in New York
+        assert_eq!(
+            calendar().status_at(Utc.with_ymd_and_hms(2021, 8, 10, 13, 0, 0).unwrap()),
+            MarketStatus::Closed
+        );
+        assert_eq!(
+            calendar().status_at(Utc.with_ymd_and_hms(2021, 8, 10, 20, 0, 0).unwrap()),
+            MarketStatus::Closed
+        );
+    }
+
+    #[test]
+    fn status_at_is_closed_on_weekends_and_holidays() {
+        assert_eq!(
+            calendar().status_at(Utc.with_ymd_and_hms(2021, 8, 14, 15, 0, 0).unwrap()),
+            MarketStatus::Closed
+        );
+        assert_eq!(
+            calendar().status_at(Utc.with_ymd_and_hms(2021, 12, 24, 15, 0, 0).unwrap()),
+            MarketStatus::Closed
+        );
+    }
+
+    #[test]
+    fn status_at_respects_early_close_on_half_days() {
+        // 12:00 and 14:00 in New York
+        assert_eq!(
+            calendar().status_at(Utc.with_ymd_and_hms(2021, 11, 26, 17, 0, 0).unwrap()),
+            MarketStatus::Open
+        );
+        assert_eq!(
+            calendar().status_at(Utc.with_ymd_and_hms(2021, 11, 26, 19, 0, 0).unwrap()),
+            MarketStatus::Closed
+        );
+    }
+}
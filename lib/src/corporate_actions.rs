@@ -0,0 +1,134 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// A split or an ex-dividend date. Split ratios follow Yahoo's convention, so a 4-for-1 split
+/// has a numerator of 4 and a denominator of 1.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum CorporateAction {
+    Split {
+        date: DateTime<Utc>,
+        numerator: u64,
+        denominator: u64,
+    },
+    Dividend {
+        date: DateTime<Utc>,
+        amount: f64,
+    },
+}
+
+impl CorporateAction {
+    pub fn date(&self) -> DateTime<Utc> {
+        match self {
+            CorporateAction::Split { date, .. } | CorporateAction::Dividend { date, .. } => *date,
+        }
+    }
+
+    pub fn is_split(&self) -> bool {
+        matches!(self, CorporateAction::Split { .. })
+    }
+
+    pub fn is_dividend(&self) -> bool {
+        matches!(self, CorporateAction::Dividend { .. })
+    }
+}
+
+/// Dividend paid per bar, attributing each ex-dividend date to the first bar on or after it.
+/// Dividends before the first bar or after the last are dropped.
+pub fn dividends_per_bar(timestamps: &[DateTime<Utc>], actions: &[CorporateAction]) -> Vec<f64> {
+    let mut dividends = vec![0f64; timestamps.len()];
+    for action in actions {
+        if let CorporateAction::Dividend { date, amount } = action {
+            if timestamps.first().is_none_or(|first| date < first) {
+                continue;
+            }
+            if let Some(index) = timestamps.iter().position(|timestamp| timestamp >= date) {
+                dividends[index] += amount;
+            }
+        }
+    }
+    dividends
+}
+
+/// Growth in percent from the first to the last close, ignoring dividends
+pub fn price_return(closes: &[f64]) -> Option<f64> {
+    if closes.len() < 2 {
+        return None;
+    }
+    let first = closes.first()?;
+    let last = closes.last()?;
+    Some((last / first - 1f64) * 100f64)
+}
+
+/// Growth in percent from the first to the last close with every dividend reinvested at the
+/// close of the bar it goes ex on. `dividends` is aligned with `closes`.
+pub fn total_return(closes: &[f64], dividends: &[f64]) -> Option<f64> {
+    if closes.len() < 2 || closes.len() != dividends.len() {
+        return None;
+    }
+    let growth = closes
+        .windows(2)
+        .zip(dividends.iter().skip(1))
+        .map(|(pair, dividend)| (pair[1] + dividend) / pair[0])
+        .product::<f64>();
+    Some((growth - 1f64) * 100f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn dividends_per_bar_attributes_dividend_to_next_bar() {
+        let timestamps = [
+            Utc.with_ymd_and_hms(2021, 1, 4, 0, 0, 0).unwrap(),
+            Utc.with_ymd_and_hms(2021, 1, 5, 0, 0, 0).unwrap(),
+            Utc.with_ymd_and_hms(2021, 1, 6, 0, 0, 0).unwrap(),
+        ];
+        let actions = [
+            CorporateAction::Dividend {
+                date: Utc.with_ymd_and_hms(2021, 1, 4, 12, 0, 0).unwrap(),
+                amount: 0.5,
+            },
+            CorporateAction::Dividend {
+                date: Utc.with_ymd_and_hms(2020, 12, 1, 0, 0, 0).unwrap(),
+                amount: 1.0,
+            },
+            CorporateAction::Split {
+                date: Utc.with_ymd_and_hms(2021, 1, 6, 0, 0, 0).unwrap(),
+                numerator: 2,
+                denominator: 1,
+            },
+        ];
+        assert_eq!(
+            dividends_per_bar(&timestamps, &actions),
+            vec![0f64, 0.5f64, 0f64]
+        );
+    }
+
+    #[test]
+    fn price_return_returns_none_if_series_is_smaller_than_2() {
+        assert_eq!(price_return(&[1f64]), None);
+    }
+
+    #[test]
+    fn price_return_returns_growth_in_percent() {
+        assert_eq!(price_return(&[10f64, 5f64, 15f64]), Some(50f64));
+    }
+
+    #[test]
+    fn total_return_reinvests_dividends() {
+        let closes = [10f64, 10f64, 12f64];
+        let dividends = [0f64, 1f64, 0f64];
+        // 11/10 on the ex-dividend bar, then 12/10
+        let expected = (1.1f64 * 1.2f64 - 1f64) * 100f64;
+        assert!((total_return(&closes, &dividends).unwrap() - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn total_return_equals_price_return_without_dividends() {
+        let closes = [10f64, 8f64, 12f64];
+        let total = total_return(&closes, &[0f64; 3]).unwrap();
+        assert!((total - price_return(&closes).unwrap()).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,295 @@
+use crate::quote_provider::Bar;
+use crate::ticker::Ticker;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Currencies whose prices are prefixed with a symbol rather than suffixed with their code
+const SYMBOLS: [(&str, &str); 10] = [
+    ("USD", "$"),
+    ("GBP", "£"),
+    ("EUR", "€"),
+    ("JPY", "¥"),
+    ("CAD", "C$"),
+    ("AUD", "A$"),
+    ("HKD", "HK$"),
+    ("NZD", "NZ$"),
+    ("SGD", "S$"),
+    ("INR", "₹"),
+];
+
+/// An ISO 4217 currency code, plus `GBX` for prices quoted in pence
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Currency([u8; 3]);
+
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("Invalid currency code {0}")]
+pub struct ParseCurrencyError(String);
+
+impl Currency {
+    pub const USD: Currency = Currency(*b"USD");
+    pub const GBP: Currency = Currency(*b"GBP");
+    pub const GBX: Currency = Currency(*b"GBX");
+    pub const EUR: Currency = Currency(*b"EUR");
+    pub const CAD: Currency = Currency(*b"CAD");
+    pub const JPY: Currency = Currency(*b"JPY");
+
+    pub fn code(&self) -> &str {
+        // Only ever built from ASCII letters
+        std::str::from_utf8(&self.0).unwrap()
+    }
+
+    /// The symbol prices are prefixed with, or `None` if they are suffixed with the code instead
+    pub fn symbol(&self) -> Option<&'static str> {
+        SYMBOLS
+            .iter()
+            .find(|(code, _)| *code == self.code())
+            .map(|(_, symbol)| *symbol)
+    }
+
+    /// Splits a leading currency symbol off `s`, preferring the longest match so `C$` isn't `$`
+    pub fn strip_symbol(s: &str) -> Option<(Currency, &str)> {
+        SYMBOLS
+            .iter()
+            .filter(|(_, symbol)| s.starts_with(symbol))
+            .max_by_key(|(_, symbol)| symbol.len())
+            .map(|(code, symbol)| (code.parse().unwrap(), &s[symbol.len()..]))
+    }
+
+    /// The currency written with exactly `symbol`, e.g. `EUR` for `€`
+    pub fn from_symbol(symbol: &str) -> Option<Currency> {
+        SYMBOLS
+            .iter()
+            .find(|(_, s)| *s == symbol)
+            .map(|(code, _)| code.parse().unwrap())
+    }
+
+    /// The currency Yahoo quotes listings with an exchange suffix in, e.g. `GBX` for `VOD.L`
+    pub fn for_exchange_suffix(suffix: &str) -> Option<Currency> {
+        let code = match suffix {
+            "L" => "GBX",
+            "TO" | "V" | "NE" | "CN" => "CAD",
+            "AS" | "BR" | "DE" | "F" | "HE" | "IR" | "LS" | "MC" | "MI" | "PA" | "VI" => "EUR",
+            "AX" => "AUD",
+            "BO" | "NS" => "INR",
+            "CO" => "DKK",
+            "HK" => "HKD",
+            "JO" => "ZAR",
+            "KS" => "KRW",
+            "MX" => "MXN",
+            "NZ" => "NZD",
+            "OL" => "NOK",
+            "PR" => "CZK",
+            "SA" => "BRL",
+            "SI" => "SGD",
+            "SS" | "SZ" => "CNY",
+            "ST" => "SEK",
+            "SW" => "CHF",
+            "T" => "JPY",
+            "TA" => "ILS",
+            "TW" => "TWD",
+            _ => return None,
+        };
+        code.parse().ok()
+    }
+
+    /// The major currency and the factor to multiply by to convert into it, e.g. `GBX` is 0.01 `GBP`
+    fn major(self) -> (Currency, f64) {
+        match self {
+            Currency::GBX => (Currency::GBP, 0.01),
+            _ => (self, 1f64),
+        }
+    }
+}
+
+impl Default for Currency {
+    fn default() -> Self {
+        Currency::USD
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl FromStr for Currency {
+    type Err = ParseCurrencyError;
+
+    /// Accepts three letter codes in any case, and Yahoo's `GBp` for pence
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s == "GBp" {
+            return Ok(Currency::GBX);
+        }
+        let bytes = s.to_ascii_uppercase().into_bytes();
+        match <[u8; 3]>::try_from(bytes) {
+            Ok(code) if code.iter().all(u8::is_ascii_uppercase) => Ok(Currency(code)),
+            _ => Err(ParseCurrencyError(s.to_owned())),
+        }
+    }
+}
+
+impl Serialize for Currency {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.code())
+    }
+}
+
+impl<'de> Deserialize<'de> for Currency {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// How to convert prices from one currency to another
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Conversion {
+    /// The FX pair whose rate prices are multiplied by, if the major currencies differ
+    pub pair: Option<(Currency, Currency)>,
+    /// A fixed factor prices are multiplied by, for minor units such as pence
+    pub scale: f64,
+}
+
+impl Conversion {
+    pub fn new(from: Currency, to: Currency) -> Self {
+        let (from_major, from_scale) = from.major();
+        let (to_major, to_scale) = to.major();
+        Conversion {
+            pair: (from_major != to_major).then_some((from_major, to_major)),
+            scale: from_scale / to_scale,
+        }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.pair.is_none() && self.scale == 1f64
+    }
+}
+
+/// The ticker of an FX pair in Yahoo's format, e.g. `GBPUSD=X`
+pub fn fx_ticker(from: Currency, to: Currency) -> Ticker {
+    format!("{}{}=X", from, to)
+        .parse()
+        .expect("pairs of currency codes are valid tickers")
+}
+
+/// Converts `values` observed at `timestamps`, using the latest FX bar at or before each
+/// timestamp, or the earliest bar for timestamps before the FX series starts. `None` when a rate
+/// is needed but `fx` is empty. Both `timestamps` and `fx` are in time order, so they are walked
+/// together once.
+pub fn convert(
+    values: &[f64],
+    timestamps: &[DateTime<Utc>],
+    conversion: Conversion,
+    fx: &[Bar],
+) -> Option<Vec<f64>> {
+    if conversion.pair.is_none() {
+        return Some(values.iter().map(|v| v * conversion.scale).collect());
+    }
+    let mut rate = fx.first()?.close;
+    let mut bars = fx.iter().peekable();
+    Some(
+        values
+            .iter()
+            .zip(timestamps)
+            .map(|(value, timestamp)| {
+                while let Some(bar) = bars.next_if(|bar| bar.timestamp <= *timestamp) {
+                    rate = bar.close;
+                }
+                value * rate * conversion.scale
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn currency_parses_codes_and_pence() {
+        assert_eq!("usd".parse(), Ok(Currency::USD));
+        assert_eq!("GBp".parse(), Ok(Currency::GBX));
+        assert_eq!("GBP".parse(), Ok(Currency::GBP));
+        assert_eq!(
+            "US".parse::<Currency>(),
+            Err(ParseCurrencyError("US".to_owned()))
+        );
+        assert_eq!(
+            "U$D".parse::<Currency>(),
+            Err(ParseCurrencyError("U$D".to_owned()))
+        );
+    }
+
+    #[test]
+    fn currency_is_inferred_from_exchange_suffix() {
+        assert_eq!(Currency::for_exchange_suffix("L"), Some(Currency::GBX));
+        assert_eq!(Currency::for_exchange_suffix("TO"), Some(Currency::CAD));
+        assert_eq!(Currency::for_exchange_suffix("XX"), None);
+    }
+
+    #[test]
+    fn conversion_handles_minor_units_and_fx_pairs() {
+        assert!(Conversion::new(Currency::USD, Currency::USD).is_identity());
+        assert_eq!(
+            Conversion::new(Currency::GBX, Currency::GBP),
+            Conversion {
+                pair: None,
+                scale: 0.01
+            }
+        );
+        assert_eq!(
+            Conversion::new(Currency::GBX, Currency::USD),
+            Conversion {
+                pair: Some((Currency::GBP, Currency::USD)),
+                scale: 0.01
+            }
+        );
+        assert_eq!(
+            Conversion::new(Currency::USD, Currency::GBX),
+            Conversion {
+                pair: Some((Currency::USD, Currency::GBP)),
+                scale: 100f64
+            }
+        );
+        assert_eq!(fx_ticker(Currency::GBP, Currency::USD).as_str(), "GBPUSD=X");
+    }
+
+    #[test]
+    fn convert_uses_latest_rate_at_each_timestamp() {
+        let day = |d| Utc.with_ymd_and_hms(2021, 1, d, 0, 0, 0).unwrap();
+        let bar = |d, close| Bar {
+            timestamp: day(d),
+            open: close,
+            high: close,
+            low: close,
+            close,
+            adjclose: close,
+            volume: 0,
+        };
+        let fx = [bar(5, 1.25), bar(7, 1.5)];
+        let conversion = Conversion::new(Currency::GBX, Currency::USD);
+        let converted = convert(
+            &[100f64, 100f64, 100f64, 200f64],
+            &[day(4), day(5), day(6), day(8)],
+            conversion,
+            &fx,
+        )
+        .unwrap();
+        assert_eq!(converted, vec![1.25, 1.25, 1.25, 3.0]);
+        assert_eq!(convert(&[100f64], &[day(4)], conversion, &[]), None);
+    }
+}
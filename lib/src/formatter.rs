@@ -1,5 +1,141 @@
+mod options;
 mod percentage;
 mod price;
 
+pub use options::{
+    format_options, set_format_options, with_format_options, CurrencyPlacement, FormatOptions,
+    NegativeStyle, ParseFormatOptionError,
+};
 pub use percentage::Percentage;
 pub use price::Price;
+
+use serde::de::{self, Visitor};
+use std::cell::Cell;
+use std::fmt;
+use std::marker::PhantomData;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// How `Price` and `Percentage` serialize: as display strings like `"$405.27"` and `"172.41%"`,
+/// or as plain numbers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NumberFormat {
+    #[default]
+    Formatted,
+    Raw,
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("Invalid number format {0}, expected formatted or raw")]
+pub struct ParseNumberFormatError(String);
+
+impl FromStr for NumberFormat {
+    type Err = ParseNumberFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "formatted" => Ok(NumberFormat::Formatted),
+            "raw" => Ok(NumberFormat::Raw),
+            _ => Err(ParseNumberFormatError(s.to_owned())),
+        }
+    }
+}
+
+thread_local! {
+    static NUMBER_FORMAT: Cell<NumberFormat> = Cell::new(NumberFormat::default());
+}
+
+/// The number format in effect on this thread
+pub fn number_format() -> NumberFormat {
+    NUMBER_FORMAT.with(Cell::get)
+}
+
+/// Runs `f` with `format` in effect on this thread, so serializers called from `f` emit `Price`
+/// and `Percentage` in that format
+pub fn with_number_format<R>(format: NumberFormat, f: impl FnOnce() -> R) -> R {
+    struct Restore(NumberFormat);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            NUMBER_FORMAT.with(|cell| cell.set(self.0));
+        }
+    }
+    let _restore = Restore(NUMBER_FORMAT.with(|cell| cell.replace(format)));
+    f()
+}
+
+/// How fields holding several values serialize: joined into one string, so each fits in a CSV
+/// column, or as lists for formats that can nest them, e.g. JSON
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FieldLayout {
+    #[default]
+    Flat,
+    Nested,
+}
+
+thread_local! {
+    static FIELD_LAYOUT: Cell<FieldLayout> = Cell::new(FieldLayout::default());
+}
+
+/// The field layout in effect on this thread
+pub fn field_layout() -> FieldLayout {
+    FIELD_LAYOUT.with(Cell::get)
+}
+
+/// Runs `f` with `layout` in effect on this thread
+pub fn with_field_layout<R>(layout: FieldLayout, f: impl FnOnce() -> R) -> R {
+    struct Restore(FieldLayout);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            FIELD_LAYOUT.with(|cell| cell.set(self.0));
+        }
+    }
+    let _restore = Restore(FIELD_LAYOUT.with(|cell| cell.replace(layout)));
+    f()
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("Invalid {0}: {1}")]
+pub struct ParseFormattedError(&'static str, String);
+
+/// Deserializes a `T` from either a plain number or its formatted string
+struct NumberOrFormattedVisitor<T>(PhantomData<T>);
+
+impl<'de, T> Visitor<'de> for NumberOrFormattedVisitor<T>
+where
+    T: FromStr<Err = ParseFormattedError> + From<f64>,
+{
+    type Value = T;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a number or formatted string")
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<T, E> {
+        Ok(T::from(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<T, E> {
+        Ok(T::from(v as f64))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<T, E> {
+        Ok(T::from(v as f64))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<T, E> {
+        v.parse().map_err(E::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn with_number_format_restores_previous_format() {
+        assert_eq!(number_format(), NumberFormat::Formatted);
+        let inner = with_number_format(NumberFormat::Raw, number_format);
+        assert_eq!(inner, NumberFormat::Raw);
+        assert_eq!(number_format(), NumberFormat::Formatted);
+    }
+}
@@ -0,0 +1,250 @@
+use crate::currency::Currency;
+use std::cell::Cell;
+use std::str::FromStr;
+use std::sync::RwLock;
+use thiserror::Error;
+
+/// Where the sign of a negative price goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NegativeStyle {
+    /// `$-1.49`
+    #[default]
+    SignAfterSymbol,
+    /// `-$1.49`
+    SignBeforeSymbol,
+    /// `($1.49)`
+    Parentheses,
+}
+
+/// Where a price's currency goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CurrencyPlacement {
+    /// Symbols before the number and codes after it, e.g. `$1.49` and `1.49 CHF`
+    #[default]
+    Native,
+    /// `$1.49` and `CHF 1.49`
+    Prefix,
+    /// `1.49 $` and `1.49 CHF`
+    Suffix,
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("Invalid {0} {1}")]
+pub struct ParseFormatOptionError(&'static str, String);
+
+impl FromStr for NegativeStyle {
+    type Err = ParseFormatOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "after-symbol" => Ok(NegativeStyle::SignAfterSymbol),
+            "before-symbol" => Ok(NegativeStyle::SignBeforeSymbol),
+            "parentheses" => Ok(NegativeStyle::Parentheses),
+            _ => Err(ParseFormatOptionError("negative style", s.to_owned())),
+        }
+    }
+}
+
+impl FromStr for CurrencyPlacement {
+    type Err = ParseFormatOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "native" => Ok(CurrencyPlacement::Native),
+            "prefix" => Ok(CurrencyPlacement::Prefix),
+            "suffix" => Ok(CurrencyPlacement::Suffix),
+            _ => Err(ParseFormatOptionError("currency placement", s.to_owned())),
+        }
+    }
+}
+
+/// How `Price` and `Percentage` are written by `Display` and formatted `Serialize`, and read
+/// back by `FromStr`. The default writes `$-1234.50` and `-12.34%`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FormatOptions {
+    decimals: usize,
+    thousands_separator: Option<char>,
+    decimal_mark: char,
+    negative_style: NegativeStyle,
+    currency_placement: CurrencyPlacement,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions::new()
+    }
+}
+
+impl FormatOptions {
+    pub const fn new() -> Self {
+        FormatOptions {
+            decimals: 2,
+            thousands_separator: None,
+            decimal_mark: '.',
+            negative_style: NegativeStyle::SignAfterSymbol,
+            currency_placement: CurrencyPlacement::Native,
+        }
+    }
+
+    pub fn with_decimals(mut self, decimals: usize) -> Self {
+        self.decimals = decimals;
+        self
+    }
+
+    pub fn with_thousands_separator(mut self, separator: Option<char>) -> Self {
+        self.thousands_separator = separator;
+        self
+    }
+
+    pub fn with_decimal_mark(mut self, mark: char) -> Self {
+        self.decimal_mark = mark;
+        self
+    }
+
+    pub fn with_negative_style(mut self, style: NegativeStyle) -> Self {
+        self.negative_style = style;
+        self
+    }
+
+    pub fn with_currency_placement(mut self, placement: CurrencyPlacement) -> Self {
+        self.currency_placement = placement;
+        self
+    }
+
+    /// Writes the magnitude of `value`, returning whether it rounded to a negative number
+    fn magnitude(&self, value: f64) -> (bool, String) {
+        let rounded = format!("{:.*}", self.decimals, value);
+        let negative = rounded.starts_with('-');
+        let rounded = rounded.trim_start_matches('-');
+        let (integer, fraction) = match rounded.split_once('.') {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (rounded, None),
+        };
+
+        let mut number = String::new();
+        for (i, digit) in integer.chars().enumerate() {
+            if i > 0 && (integer.len() - i) % 3 == 0 {
+                number.extend(self.thousands_separator);
+            }
+            number.push(digit);
+        }
+        if let Some(fraction) = fraction {
+            number.push(self.decimal_mark);
+            number.push_str(fraction);
+        }
+        (negative, number)
+    }
+
+    pub(super) fn format_price(&self, value: f64, currency: Currency) -> String {
+        let (negative, number) = self.magnitude(value);
+        let (prefix, suffix) = match (self.currency_placement, currency.symbol()) {
+            (_, _) if currency == Currency::GBX => (String::new(), "p".to_owned()),
+            (CurrencyPlacement::Native, Some(symbol))
+            | (CurrencyPlacement::Prefix, Some(symbol)) => (symbol.to_owned(), String::new()),
+            (CurrencyPlacement::Prefix, None) => (format!("{} ", currency), String::new()),
+            (_, symbol) => (
+                String::new(),
+                format!(" {}", symbol.unwrap_or_else(|| currency.code())),
+            ),
+        };
+        match (negative, self.negative_style) {
+            (false, _) => format!("{}{}{}", prefix, number, suffix),
+            (true, NegativeStyle::SignAfterSymbol) => format!("{}-{}{}", prefix, number, suffix),
+            (true, NegativeStyle::SignBeforeSymbol) => format!("-{}{}{}", prefix, number, suffix),
+            (true, NegativeStyle::Parentheses) => format!("({}{}{})", prefix, number, suffix),
+        }
+    }
+
+    pub(super) fn format_percentage(&self, value: f64) -> String {
+        let (negative, number) = self.magnitude(value);
+        match (negative, self.negative_style) {
+            (false, _) => format!("{}%", number),
+            (true, NegativeStyle::Parentheses) => format!("({}%)", number),
+            (true, _) => format!("-{}%", number),
+        }
+    }
+
+    /// Parses a number written with these options' separators, without sign or currency
+    pub(super) fn parse_number(&self, s: &str) -> Option<f64> {
+        let mut number: String = s
+            .trim()
+            .chars()
+            .filter(|c| Some(*c) != self.thousands_separator)
+            .collect();
+        if self.decimal_mark != '.' {
+            number = number.replace(self.decimal_mark, ".");
+        }
+        number.parse().ok()
+    }
+}
+
+/// Splits the sign off a formatted number in any `NegativeStyle`, returning whether it was
+/// negative
+pub(super) fn strip_sign(s: &str) -> (bool, &str) {
+    let s = s.trim();
+    if let Some(inner) = s.strip_prefix('(').and_then(|s| s.strip_suffix(')')) {
+        (true, inner.trim())
+    } else if let Some(inner) = s.strip_prefix('-') {
+        (true, inner.trim())
+    } else {
+        (false, s)
+    }
+}
+
+static FORMAT_OPTIONS: RwLock<FormatOptions> = RwLock::new(FormatOptions::new());
+
+thread_local! {
+    static SCOPED_FORMAT_OPTIONS: Cell<Option<FormatOptions>> = const { Cell::new(None) };
+}
+
+/// The format options in effect on this thread
+pub fn format_options() -> FormatOptions {
+    SCOPED_FORMAT_OPTIONS
+        .with(Cell::get)
+        .unwrap_or_else(|| *FORMAT_OPTIONS.read().unwrap())
+}
+
+/// Sets the format options used on every thread outside `with_format_options`
+pub fn set_format_options(options: FormatOptions) {
+    *FORMAT_OPTIONS.write().unwrap() = options;
+}
+
+/// Runs `f` with `options` in effect on this thread
+pub fn with_format_options<R>(options: FormatOptions, f: impl FnOnce() -> R) -> R {
+    struct Restore(Option<FormatOptions>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            SCOPED_FORMAT_OPTIONS.with(|cell| cell.set(self.0));
+        }
+    }
+    let _restore = Restore(SCOPED_FORMAT_OPTIONS.with(|cell| cell.replace(Some(options))));
+    f()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_options_group_thousands_and_use_decimal_mark() {
+        let options = FormatOptions::default()
+            .with_thousands_separator(Some('.'))
+            .with_decimal_mark(',');
+        assert_eq!(
+            options.magnitude(1234567.891),
+            (false, "1.234.567,89".to_owned())
+        );
+        assert_eq!(options.magnitude(-123.4), (true, "123,40".to_owned()));
+        assert_eq!(options.parse_number("1.234.567,89"), Some(1234567.89));
+
+        let options = FormatOptions::default().with_decimals(0);
+        assert_eq!(options.magnitude(999.6), (false, "1000".to_owned()));
+    }
+
+    #[test]
+    fn with_format_options_overrides_on_this_thread_only() {
+        let options = FormatOptions::default().with_decimals(4);
+        assert_eq!(with_format_options(options, format_options), options);
+        assert_eq!(format_options(), FormatOptions::default());
+    }
+}
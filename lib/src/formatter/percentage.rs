@@ -1,11 +1,36 @@
-use serde::{Serialize, Serializer};
+use super::options::strip_sign;
+use super::{
+    format_options, number_format, NumberFormat, NumberOrFormattedVisitor, ParseFormattedError,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt;
+use std::marker::PhantomData;
+use std::str::FromStr;
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Percentage(pub f64);
 
 impl fmt::Display for Percentage {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:.2}%", self.0)
+        f.write_str(&format_options().format_percentage(self.0))
+    }
+}
+
+impl From<f64> for Percentage {
+    fn from(value: f64) -> Self {
+        Percentage(value)
+    }
+}
+
+impl FromStr for Percentage {
+    type Err = ParseFormattedError;
+
+    /// Parses `"172.41%"`, `"(1.49%)"` or `"172.41"` under the current format options
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negative, rest) = strip_sign(s);
+        let value = format_options()
+            .parse_number(rest.strip_suffix('%').unwrap_or(rest))
+            .ok_or_else(|| ParseFormattedError("percentage", s.trim().to_owned()))?;
+        Ok(Percentage(if negative { -value } else { value }))
     }
 }
 
@@ -14,6 +39,57 @@ impl Serialize for Percentage {
     where
         S: Serializer,
     {
-        serializer.serialize_str(&format!("{:.2}%", self.0))
+        match number_format() {
+            NumberFormat::Formatted => serializer.serialize_str(&self.to_string()),
+            NumberFormat::Raw => serializer.serialize_f64(self.0),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Percentage {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(NumberOrFormattedVisitor(PhantomData))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatter::{with_format_options, with_number_format, FormatOptions, NegativeStyle};
+
+    #[test]
+    fn percentage_formats_and_parses_with_format_options() {
+        let options = FormatOptions::default()
+            .with_decimals(1)
+            .with_decimal_mark(',')
+            .with_negative_style(NegativeStyle::Parentheses);
+        with_format_options(options, || {
+            assert_eq!(Percentage(-12.34).to_string(), "(12,3%)");
+            assert_eq!(Percentage(172.41).to_string(), "172,4%");
+            assert_eq!("(12,3%)".parse(), Ok(Percentage(-12.3)));
+        });
+        assert_eq!(Percentage(-12.34).to_string(), "-12.34%");
+    }
+
+    #[test]
+    fn percentage_serializes_in_number_format_and_deserializes_both_forms() {
+        let percentage = Percentage(172.41);
+        assert_eq!(serde_json::to_string(&percentage).unwrap(), "\"172.41%\"");
+        assert_eq!(
+            with_number_format(NumberFormat::Raw, || serde_json::to_string(&percentage)).unwrap(),
+            "172.41"
+        );
+        assert_eq!(
+            serde_json::from_str::<Percentage>("\"172.41%\"").unwrap(),
+            percentage
+        );
+        assert_eq!(
+            serde_json::from_str::<Percentage>("172.41").unwrap(),
+            percentage
+        );
+        assert!(serde_json::from_str::<Percentage>("\"lots\"").is_err());
     }
 }
@@ -1,11 +1,71 @@
-use serde::{Serialize, Serializer};
+use super::options::strip_sign;
+use super::{
+    format_options, number_format, NumberFormat, NumberOrFormattedVisitor, ParseFormattedError,
+};
+use crate::currency::Currency;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt;
+use std::marker::PhantomData;
+use std::str::FromStr;
 #[derive(Debug, PartialEq, Clone, Copy)]
-pub struct Price(pub f64);
+pub struct Price(pub f64, pub Currency);
+
+impl Price {
+    pub fn usd(value: f64) -> Self {
+        Price(value, Currency::USD)
+    }
+}
 
 impl fmt::Display for Price {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "${:.2}", self.0)
+        f.write_str(&format_options().format_price(self.0, self.1))
+    }
+}
+
+/// Plain numbers are taken to be USD
+impl From<f64> for Price {
+    fn from(value: f64) -> Self {
+        Price::usd(value)
+    }
+}
+
+impl FromStr for Price {
+    type Err = ParseFormattedError;
+
+    /// Parses any form `Price` displays as under the current format options, e.g. `"$405.27"`,
+    /// `"-$1.49"`, `"($1.49)"`, `"245.10p"`, `"CHF 12.50"`, `"12,50 €"`, or a plain number as USD
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseFormattedError("price", s.to_owned());
+        let (outer_negative, rest) = strip_sign(s);
+        let (currency, rest) = if let Some((currency, rest)) = Currency::strip_symbol(rest) {
+            (Some(currency), rest)
+        } else if let Some((currency, rest)) = rest
+            .split_once(' ')
+            .and_then(|(code, rest)| Some((code.parse().ok()?, rest)))
+        {
+            (Some(currency), rest)
+        } else {
+            (None, rest)
+        };
+        let (inner_negative, rest) = strip_sign(rest);
+        let (currency, value) = match currency {
+            Some(currency) => (currency, rest),
+            None => {
+                if let Some(value) = rest.strip_suffix('p') {
+                    (Currency::GBX, value)
+                } else if let Some((value, suffix)) = rest.rsplit_once(' ') {
+                    let currency = Currency::from_symbol(suffix)
+                        .or_else(|| suffix.parse().ok())
+                        .ok_or_else(invalid)?;
+                    (currency, value)
+                } else {
+                    (Currency::USD, rest)
+                }
+            }
+        };
+        let value = format_options().parse_number(value).ok_or_else(invalid)?;
+        let negative = outer_negative || inner_negative;
+        Ok(Price(if negative { -value } else { value }, currency))
     }
 }
 
@@ -14,6 +74,109 @@ impl Serialize for Price {
     where
         S: Serializer,
     {
-        serializer.serialize_str(&format!("${:.2}", self.0))
+        match number_format() {
+            NumberFormat::Formatted => serializer.serialize_str(&self.to_string()),
+            NumberFormat::Raw => serializer.serialize_f64(self.0),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Price {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(NumberOrFormattedVisitor(PhantomData))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatter::{
+        with_format_options, with_number_format, CurrencyPlacement, FormatOptions, NegativeStyle,
+    };
+
+    #[test]
+    fn price_formats_with_currency_symbol_or_code() {
+        assert_eq!(Price::usd(286.514).to_string(), "$286.51");
+        assert_eq!(Price::usd(-1.49).to_string(), "$-1.49");
+        assert_eq!(Price(12.5, Currency::GBP).to_string(), "£12.50");
+        assert_eq!(Price(12.5, Currency::CAD).to_string(), "C$12.50");
+        assert_eq!(Price(245.1, Currency::GBX).to_string(), "245.10p");
+        assert_eq!(Price(12.5, "CHF".parse().unwrap()).to_string(), "12.50 CHF");
+    }
+
+    #[test]
+    fn price_formats_with_format_options() {
+        let european = FormatOptions::default()
+            .with_thousands_separator(Some('.'))
+            .with_decimal_mark(',')
+            .with_currency_placement(CurrencyPlacement::Suffix)
+            .with_negative_style(NegativeStyle::SignBeforeSymbol);
+        with_format_options(european, || {
+            assert_eq!(Price(1234.5, Currency::EUR).to_string(), "1.234,50 €");
+            assert_eq!(Price(-1.49, Currency::EUR).to_string(), "-1,49 €");
+            assert_eq!(Price(245.1, Currency::GBX).to_string(), "245,10p");
+        });
+
+        let accounting = FormatOptions::default()
+            .with_decimals(4)
+            .with_thousands_separator(Some(','))
+            .with_currency_placement(CurrencyPlacement::Prefix)
+            .with_negative_style(NegativeStyle::Parentheses);
+        with_format_options(accounting, || {
+            assert_eq!(Price::usd(-1.49).to_string(), "($1.4900)");
+            assert_eq!(Price::usd(0.000123).to_string(), "$0.0001");
+            assert_eq!(
+                Price(-12345.5, "CHF".parse().unwrap()).to_string(),
+                "(CHF 12,345.5000)"
+            );
+            assert_eq!(
+                serde_json::to_string(&Price::usd(-1.49)).unwrap(),
+                "\"($1.4900)\""
+            );
+        });
+    }
+
+    #[test]
+    fn price_parses_every_displayed_form() {
+        for price in [
+            Price::usd(286.51),
+            Price::usd(-1.49),
+            Price(12.5, Currency::GBP),
+            Price(12.5, Currency::CAD),
+            Price(245.1, Currency::GBX),
+            Price(12.5, "CHF".parse().unwrap()),
+        ]
+        .iter()
+        {
+            assert_eq!(price.to_string().parse::<Price>(), Ok(*price));
+        }
+        assert_eq!("405.27".parse::<Price>(), Ok(Price::usd(405.27)));
+        assert_eq!("-$1.49".parse::<Price>(), Ok(Price::usd(-1.49)));
+        assert_eq!("($1.49)".parse::<Price>(), Ok(Price::usd(-1.49)));
+        assert_eq!(
+            "CHF 12.50".parse::<Price>(),
+            Ok(Price(12.5, "CHF".parse().unwrap()))
+        );
+        assert_eq!("12.50 €".parse::<Price>(), Ok(Price(12.5, Currency::EUR)));
+        assert!("$lots".parse::<Price>().is_err());
+    }
+
+    #[test]
+    fn price_serializes_in_number_format_and_deserializes_both_forms() {
+        let price = Price::usd(405.27);
+        assert_eq!(serde_json::to_string(&price).unwrap(), "\"$405.27\"");
+        assert_eq!(
+            with_number_format(NumberFormat::Raw, || serde_json::to_string(&price)).unwrap(),
+            "405.27"
+        );
+        assert_eq!(serde_json::from_str::<Price>("\"$405.27\"").unwrap(), price);
+        assert_eq!(serde_json::from_str::<Price>("405.27").unwrap(), price);
+        assert_eq!(
+            serde_json::from_str::<Price>("405").unwrap(),
+            Price::usd(405f64)
+        );
     }
 }
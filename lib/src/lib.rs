@@ -1,3 +1,8 @@
+pub mod calendar;
+pub mod corporate_actions;
+pub mod currency;
 pub mod formatter;
 pub mod performance_indicators;
+pub mod quality;
+pub mod quote_provider;
 pub mod ticker;
@@ -0,0 +1,239 @@
+use crate::formatter::{field_layout, FieldLayout};
+use crate::quote_provider::Interval;
+use chrono::{DateTime, Datelike, Duration, Utc, Weekday};
+use serde::{Serialize, Serializer};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QualityFlag {
+    /// The latest bar hasn't changed for several intervals while the market was open
+    Stale,
+    /// Consecutive bars are further apart than the interval allows
+    Gapped,
+    /// The latest bar's return is far outside the distribution of earlier returns
+    Outlier,
+}
+
+impl QualityFlag {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QualityFlag::Stale => "stale",
+            QualityFlag::Gapped => "gapped",
+            QualityFlag::Outlier => "outlier",
+        }
+    }
+}
+
+/// Quality annotations on a series. Serializes as a `|` separated string, so it fits in one CSV
+/// column, or as a list of flags in `FieldLayout::Nested`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QualityFlags(pub Vec<QualityFlag>);
+
+impl QualityFlags {
+    pub fn is_healthy(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Display for QualityFlags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flags: Vec<&str> = self.0.iter().map(QualityFlag::as_str).collect();
+        write!(f, "{}", flags.join("|"))
+    }
+}
+
+impl Serialize for QualityFlags {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match field_layout() {
+            FieldLayout::Flat => serializer.serialize_str(&self.to_string()),
+            FieldLayout::Nested => self.0.serialize(serializer),
+        }
+    }
+}
+
+/// Whether any two consecutive bars are more than `max_gap_bars` intervals apart. Intraday
+/// series only count gaps within a day, so overnight and weekend closes aren't gaps.
+pub fn is_gapped(timestamps: &[DateTime<Utc>], interval: Interval, max_gap_bars: i32) -> bool {
+    let max_gap = interval.duration() * max_gap_bars;
+    timestamps.windows(2).any(|pair| {
+        let intraday = interval.duration() < Duration::days(1);
+        let same_day = pair[0].date_naive() == pair[1].date_naive();
+        (!intraday || same_day) && pair[1] - pair[0] > max_gap
+    })
+}
+
+fn is_weekend(weekday: Weekday) -> bool {
+    matches!(weekday, Weekday::Sat | Weekday::Sun)
+}
+
+/// Whether `at` falls within the hours of the day that `timestamps` has bars in, and on a weekday
+/// unless the series has weekend bars too, standing in for market hours when there's no calendar
+pub fn in_observed_session(
+    timestamps: &[DateTime<Utc>],
+    interval: Interval,
+    at: DateTime<Utc>,
+) -> bool {
+    let trades_on_day =
+        !is_weekend(at.weekday()) || timestamps.iter().any(|t| is_weekend(t.weekday()));
+    let first = timestamps.iter().map(|t| t.time()).min();
+    let last = timestamps.iter().map(|t| t.time()).max();
+    match (first, last) {
+        (Some(first), Some(last)) => {
+            trades_on_day
+                && first <= at.time()
+                && at.time().signed_duration_since(last) < interval.duration()
+        }
+        _ => false,
+    }
+}
+
+/// Daily or weekly bars that should have followed the bar at `last_bar` by `at`. Only weekdays
+/// count for daily bars, so weekends are never missed bars.
+pub fn missed_bars(last_bar: DateTime<Utc>, at: DateTime<Utc>, interval: Interval) -> i64 {
+    let (from, to) = (last_bar.date_naive(), at.date_naive());
+    match interval {
+        Interval::OneWeek => ((to - from).num_weeks() - 1).max(0),
+        _ => from
+            .iter_days()
+            .skip(1)
+            .take_while(|day| *day < to)
+            .filter(|day| !is_weekend(day.weekday()))
+            .count() as i64,
+    }
+}
+
+/// Whether the last return of `series` is more than `threshold` standard deviations from the
+/// mean of the earlier returns. Needs at least `min_returns` earlier returns to judge.
+pub fn is_outlier(series: &[f64], threshold: f64, min_returns: usize) -> bool {
+    let returns: Vec<f64> = series
+        .windows(2)
+        .map(|pair| pair[1] / pair[0] - 1f64)
+        .collect();
+    let (last, earlier) = match returns.split_last() {
+        Some((last, earlier)) if earlier.len() >= min_returns => (last, earlier),
+        _ => return false,
+    };
+    let mean = earlier.iter().sum::<f64>() / earlier.len() as f64;
+    let variance =
+        earlier.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (earlier.len() - 1) as f64;
+    let std_dev = variance.sqrt();
+    if std_dev == 0f64 {
+        return *last != mean;
+    }
+    ((last - mean) / std_dev).abs() > threshold
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatter::with_field_layout;
+    use chrono::TimeZone;
+
+    #[test]
+    fn quality_flags_serializes_as_separated_string() {
+        let flags = QualityFlags(vec![QualityFlag::Stale, QualityFlag::Outlier]);
+        assert_eq!(flags.to_string(), "stale|outlier");
+        assert_eq!(QualityFlags::default().to_string(), "");
+    }
+
+    #[test]
+    fn quality_flags_serializes_as_list_in_nested_layout() {
+        let flags = QualityFlags(vec![QualityFlag::Stale, QualityFlag::Outlier]);
+        assert_eq!(serde_json::to_string(&flags).unwrap(), "\"stale|outlier\"");
+        assert_eq!(
+            with_field_layout(FieldLayout::Nested, || serde_json::to_string(&flags)).unwrap(),
+            "[\"stale\",\"outlier\"]"
+        );
+    }
+
+    #[test]
+    fn is_gapped_ignores_weekends_in_daily_series() {
+        // Friday, then Monday
+        let timestamps = [
+            Utc.with_ymd_and_hms(2021, 8, 13, 0, 0, 0).unwrap(),
+            Utc.with_ymd_and_hms(2021, 8, 16, 0, 0, 0).unwrap(),
+        ];
+        assert!(!is_gapped(&timestamps, Interval::OneDay, 5));
+    }
+
+    #[test]
+    fn is_gapped_flags_missing_daily_bars() {
+        let timestamps = [
+            Utc.with_ymd_and_hms(2021, 8, 2, 0, 0, 0).unwrap(),
+            Utc.with_ymd_and_hms(2021, 8, 16, 0, 0, 0).unwrap(),
+        ];
+        assert!(is_gapped(&timestamps, Interval::OneDay, 5));
+    }
+
+    #[test]
+    fn is_gapped_only_counts_intraday_gaps_within_a_day() {
+        let overnight = [
+            Utc.with_ymd_and_hms(2021, 8, 13, 20, 0, 0).unwrap(),
+            Utc.with_ymd_and_hms(2021, 8, 14, 13, 30, 0).unwrap(),
+        ];
+        assert!(!is_gapped(&overnight, Interval::OneMinute, 5));
+        let within_day = [
+            Utc.with_ymd_and_hms(2021, 8, 13, 14, 0, 0).unwrap(),
+            Utc.with_ymd_and_hms(2021, 8, 13, 15, 0, 0).unwrap(),
+        ];
+        assert!(is_gapped(&within_day, Interval::OneMinute, 5));
+    }
+
+    #[test]
+    fn in_observed_session_follows_the_hours_a_series_has_bars_in() {
+        // Thursday and Friday, 13:30 to 19:59
+        let timestamps = [
+            Utc.with_ymd_and_hms(2021, 8, 12, 13, 30, 0).unwrap(),
+            Utc.with_ymd_and_hms(2021, 8, 12, 19, 59, 0).unwrap(),
+            Utc.with_ymd_and_hms(2021, 8, 13, 13, 30, 0).unwrap(),
+        ];
+        let at = |d, h, m| Utc.with_ymd_and_hms(2021, 8, d, h, m, 0).unwrap();
+        assert!(in_observed_session(
+            &timestamps,
+            Interval::OneMinute,
+            at(13, 15, 0)
+        ));
+        assert!(!in_observed_session(
+            &timestamps,
+            Interval::OneMinute,
+            at(13, 23, 0)
+        ));
+        assert!(!in_observed_session(
+            &timestamps,
+            Interval::OneMinute,
+            at(14, 15, 0)
+        ));
+        assert!(!in_observed_session(
+            &[],
+            Interval::OneMinute,
+            at(13, 15, 0)
+        ));
+    }
+
+    #[test]
+    fn missed_bars_skips_weekends() {
+        let friday = Utc.with_ymd_and_hms(2021, 8, 13, 0, 0, 0).unwrap();
+        let day = |d| Utc.with_ymd_and_hms(2021, 8, d, 15, 0, 0).unwrap();
+        assert_eq!(missed_bars(friday, day(16), Interval::OneDay), 0);
+        assert_eq!(missed_bars(friday, day(18), Interval::OneDay), 2);
+        assert_eq!(missed_bars(friday, day(22), Interval::OneWeek), 0);
+        assert_eq!(missed_bars(friday, day(30), Interval::OneWeek), 1);
+    }
+
+    #[test]
+    fn is_outlier_flags_jump_in_last_bar() {
+        let mut series: Vec<f64> = (0..20).map(|i| 100f64 + (i % 2) as f64).collect();
+        assert!(!is_outlier(&series, 4f64, 10));
+        series.push(150f64);
+        assert!(is_outlier(&series, 4f64, 10));
+    }
+
+    #[test]
+    fn is_outlier_needs_enough_history() {
+        assert!(!is_outlier(&[100f64, 101f64, 200f64], 4f64, 10));
+    }
+}
@@ -0,0 +1,259 @@
+use crate::corporate_actions::CorporateAction;
+use crate::ticker::Ticker;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::sync::Arc;
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Bar {
+    pub timestamp: DateTime<Utc>,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub adjclose: f64,
+    pub volume: u64,
+}
+
+/// Spacing between consecutive bars. Window-based indicators count bars, so a 30 bar window
+/// is 30 minutes of `OneMinute` bars or 30 trading days of `OneDay` bars.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum Interval {
+    #[serde(rename = "1m")]
+    OneMinute,
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "15m")]
+    FifteenMinutes,
+    #[serde(rename = "1h")]
+    OneHour,
+    #[serde(rename = "1d")]
+    #[default]
+    OneDay,
+    #[serde(rename = "1wk")]
+    OneWeek,
+}
+
+impl Interval {
+    pub const ALL: [Interval; 6] = [
+        Interval::OneMinute,
+        Interval::FiveMinutes,
+        Interval::FifteenMinutes,
+        Interval::OneHour,
+        Interval::OneDay,
+        Interval::OneWeek,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Interval::OneMinute => "1m",
+            Interval::FiveMinutes => "5m",
+            Interval::FifteenMinutes => "15m",
+            Interval::OneHour => "1h",
+            Interval::OneDay => "1d",
+            Interval::OneWeek => "1wk",
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        match self {
+            Interval::OneMinute => Duration::minutes(1),
+            Interval::FiveMinutes => Duration::minutes(5),
+            Interval::FifteenMinutes => Duration::minutes(15),
+            Interval::OneHour => Duration::hours(1),
+            Interval::OneDay => Duration::days(1),
+            Interval::OneWeek => Duration::weeks(1),
+        }
+    }
+
+    /// How far back bars of this interval are served, following Yahoo's limits on intraday
+    /// history
+    pub fn max_lookback(&self) -> Option<Duration> {
+        match self {
+            Interval::OneMinute => Some(Duration::days(7)),
+            Interval::FiveMinutes | Interval::FifteenMinutes => Some(Duration::days(60)),
+            Interval::OneHour => Some(Duration::days(730)),
+            Interval::OneDay | Interval::OneWeek => None,
+        }
+    }
+
+    /// `start`, or the earliest start that is served for bars up to `end`
+    pub fn clamp_start(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> DateTime<Utc> {
+        match self.max_lookback() {
+            Some(lookback) => start.max(end - lookback),
+            None => start,
+        }
+    }
+}
+
+impl Display for Interval {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("Unknown interval {0}, expected one of 1m, 5m, 15m, 1h, 1d, 1wk")]
+pub struct ParseIntervalError(String);
+
+impl FromStr for Interval {
+    type Err = ParseIntervalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Interval::ALL
+            .iter()
+            .find(|interval| interval.as_str() == s)
+            .copied()
+            .ok_or_else(|| ParseIntervalError(s.to_owned()))
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ProviderError {
+    #[error("Failed to connect to provider: {0}")]
+    ConnectionFailed(String),
+
+    #[error("Provider returned an invalid response: {0}")]
+    InvalidResponse(String),
+
+    #[error("No data available for {0}")]
+    NoData(String),
+}
+
+/// Bars with the corporate actions between the same dates. Bars are needed for indicators, so
+/// only their failure fails a fetch.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct History {
+    pub bars: Vec<Bar>,
+    pub corporate_actions: Result<Vec<CorporateAction>, ProviderError>,
+}
+
+#[async_trait]
+pub trait QuoteProvider {
+    async fn get_quote_history(
+        &self,
+        ticker: &Ticker,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        interval: Interval,
+    ) -> Result<Vec<Bar>, ProviderError>;
+
+    /// Splits and ex-dividend dates between `start` and `end`. Providers without
+    /// corporate action data report none.
+    async fn get_corporate_actions(
+        &self,
+        _ticker: &Ticker,
+        _start: DateTime<Utc>,
+        _end: DateTime<Utc>,
+    ) -> Result<Vec<CorporateAction>, ProviderError> {
+        Ok(vec![])
+    }
+
+    /// Bars and corporate actions between `start` and `end`, requested side by side. Providers
+    /// that get both in one response should serve them from it.
+    async fn get_history(
+        &self,
+        ticker: &Ticker,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        interval: Interval,
+    ) -> Result<History, ProviderError> {
+        let (bars, corporate_actions) = futures::join!(
+            self.get_quote_history(ticker, start, end, interval),
+            self.get_corporate_actions(ticker, start, end)
+        );
+        Ok(History {
+            bars: bars?,
+            corporate_actions,
+        })
+    }
+}
+
+#[async_trait]
+impl<P: QuoteProvider + Send + Sync + ?Sized> QuoteProvider for Box<P> {
+    async fn get_quote_history(
+        &self,
+        ticker: &Ticker,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        interval: Interval,
+    ) -> Result<Vec<Bar>, ProviderError> {
+        (**self)
+            .get_quote_history(ticker, start, end, interval)
+            .await
+    }
+
+    async fn get_corporate_actions(
+        &self,
+        ticker: &Ticker,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<CorporateAction>, ProviderError> {
+        (**self).get_corporate_actions(ticker, start, end).await
+    }
+
+    async fn get_history(
+        &self,
+        ticker: &Ticker,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        interval: Interval,
+    ) -> Result<History, ProviderError> {
+        (**self).get_history(ticker, start, end, interval).await
+    }
+}
+
+#[async_trait]
+impl<P: QuoteProvider + Send + Sync + ?Sized> QuoteProvider for Arc<P> {
+    async fn get_quote_history(
+        &self,
+        ticker: &Ticker,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        interval: Interval,
+    ) -> Result<Vec<Bar>, ProviderError> {
+        (**self)
+            .get_quote_history(ticker, start, end, interval)
+            .await
+    }
+
+    async fn get_corporate_actions(
+        &self,
+        ticker: &Ticker,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<CorporateAction>, ProviderError> {
+        (**self).get_corporate_actions(ticker, start, end).await
+    }
+
+    async fn get_history(
+        &self,
+        ticker: &Ticker,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        interval: Interval,
+    ) -> Result<History, ProviderError> {
+        (**self).get_history(ticker, start, end, interval).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interval_round_trips_through_from_str() {
+        for interval in Interval::ALL.iter() {
+            assert_eq!(interval.as_str().parse::<Interval>(), Ok(*interval));
+        }
+    }
+
+    #[test]
+    fn interval_from_str_rejects_unknown_intervals() {
+        assert!("2d".parse::<Interval>().is_err());
+    }
+}
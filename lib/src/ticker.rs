@@ -1,11 +1,176 @@
-use serde::Serialize;
-use std::convert::Infallible;
+use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Error, Formatter};
 use std::str::FromStr;
-#[derive(Debug, Serialize, Clone, PartialEq, Eq, Hash)]
+use thiserror::Error;
+
+/// Longest root symbol accepted, excluding share class and exchange suffix
+const MAX_ROOT_LEN: usize = 6;
+
+/// Longest exchange suffix, quote currency or `=` suffix accepted
+const MAX_SUFFIX_LEN: usize = 3;
+
+/// Yahoo-style exchange suffixes with a single letter, which would otherwise be read as share
+/// classes, e.g. `VOD.L` or `7203.T`. Longer suffixes, e.g. `SHOP.TO` or `BBCA.JK`, are accepted
+/// whether listed here or not, and left to the provider to reject if it can't serve them.
+const SINGLE_LETTER_SUFFIXES: [&str; 4] = ["F", "L", "T", "V"];
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum TickerParseError {
+    #[error("Ticker is empty")]
+    Empty,
+
+    #[error("Invalid character {1:?} in ticker {0}")]
+    InvalidCharacter(String, char),
+
+    #[error("Ticker {0} is longer than {} characters", MAX_ROOT_LEN)]
+    TooLong(String),
+
+    #[error("Invalid suffix {1} in ticker {0}")]
+    InvalidSuffix(String, String),
+
+    #[error("Malformed ticker {0}")]
+    Malformed(String),
+}
+
+/// A ticker split into its components, e.g. `BBD.B.TO` is root `BBD`, class `B`, suffix `TO`,
+/// `BTC-USD` is root `BTC` quoted in `USD` and `ES=F` is root `ES` with Yahoo's futures suffix
+#[derive(Debug, PartialEq)]
+struct Symbol<'a> {
+    index: bool,
+    root: &'a str,
+    share_class: Option<&'a str>,
+    exchange_suffix: Option<&'a str>,
+    quote_currency: Option<&'a str>,
+    market_suffix: Option<&'a str>,
+}
+
+impl<'a> Symbol<'a> {
+    /// Parses an upper-cased, trimmed symbol. `-` and `/` are accepted as share class separators,
+    /// and `-` followed by more than one letter as a quote currency.
+    fn parse(s: &'a str) -> Result<Self, TickerParseError> {
+        if s.is_empty() {
+            return Err(TickerParseError::Empty);
+        }
+        if let Some(c) = s
+            .chars()
+            .find(|c| !(c.is_ascii_uppercase() || c.is_ascii_digit() || ".-/^=".contains(*c)))
+        {
+            return Err(TickerParseError::InvalidCharacter(s.to_owned(), c));
+        }
+        let malformed = || TickerParseError::Malformed(s.to_owned());
+        let invalid_suffix =
+            |suffix: &str| TickerParseError::InvalidSuffix(s.to_owned(), suffix.to_owned());
+
+        let (index, body) = match s.strip_prefix('^') {
+            Some(body) => (true, body),
+            None => (false, s),
+        };
+        let (body, market_suffix) = match body.split_once('=') {
+            Some(_) if index => return Err(malformed()),
+            Some((body, suffix)) if is_suffix(suffix) => (body, Some(suffix)),
+            Some((_, suffix)) => return Err(invalid_suffix(suffix)),
+            None => (body, None),
+        };
+        let mut parts = body.split('.');
+        let first = parts.next().unwrap();
+        let (root, mut share_class, quote_currency) = match first.split_once(['-', '/']) {
+            Some((root, currency)) if first.contains('-') && currency.len() > 1 => {
+                if !is_suffix(currency) {
+                    return Err(invalid_suffix(currency));
+                }
+                (root, None, Some(currency))
+            }
+            Some((root, class)) => (root, Some(class), None),
+            None => (first, None, None),
+        };
+        let rest: Vec<&str> = parts.collect();
+        let mut exchange_suffix = None;
+        match rest.as_slice() {
+            [] => {}
+            [part]
+                if share_class.is_none()
+                    && part.len() == 1
+                    && !SINGLE_LETTER_SUFFIXES.contains(part) =>
+            {
+                share_class = Some(part)
+            }
+            [part] => exchange_suffix = Some(*part),
+            [class, suffix] if share_class.is_none() => {
+                share_class = Some(class);
+                exchange_suffix = Some(*suffix);
+            }
+            _ => return Err(malformed()),
+        }
+
+        if root.is_empty() || !root.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(malformed());
+        }
+        if root.len() > MAX_ROOT_LEN {
+            return Err(TickerParseError::TooLong(s.to_owned()));
+        }
+        if share_class
+            .is_some_and(|class| class.len() != 1 || !class.chars().all(|c| c.is_ascii_uppercase()))
+        {
+            return Err(malformed());
+        }
+        if let Some(suffix) = exchange_suffix {
+            if index || quote_currency.is_some() || market_suffix.is_some() {
+                return Err(malformed());
+            }
+            if !is_suffix(suffix) {
+                return Err(invalid_suffix(suffix));
+            }
+        }
+        if market_suffix.is_some() && (share_class.is_some() || quote_currency.is_some()) {
+            return Err(malformed());
+        }
+        Ok(Symbol {
+            index,
+            root,
+            share_class,
+            exchange_suffix,
+            quote_currency,
+            market_suffix,
+        })
+    }
+
+    fn to_string_with(&self, share_class_separator: char) -> String {
+        let mut symbol = String::new();
+        if self.index {
+            symbol.push('^');
+        }
+        symbol.push_str(self.root);
+        if let Some(class) = self.share_class {
+            symbol.push(share_class_separator);
+            symbol.push_str(class);
+        }
+        if let Some(currency) = self.quote_currency {
+            symbol.push('-');
+            symbol.push_str(currency);
+        }
+        if let Some(suffix) = self.exchange_suffix {
+            symbol.push('.');
+            symbol.push_str(suffix);
+        }
+        if let Some(suffix) = self.market_suffix {
+            symbol.push('=');
+            symbol.push_str(suffix);
+        }
+        symbol
+    }
+}
+
+/// Whether `s` could be an exchange suffix, quote currency or `=` suffix: up to
+/// `MAX_SUFFIX_LEN` letters
+fn is_suffix(s: &str) -> bool {
+    !s.is_empty() && s.len() <= MAX_SUFFIX_LEN && s.chars().all(|c| c.is_ascii_uppercase())
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct Ticker(String);
 
 impl Ticker {
+    /// Creates a ticker without validating or normalising it; prefer `str::parse`
     pub fn new(ticker: String) -> Self {
         Ticker(ticker)
     }
@@ -13,6 +178,21 @@ impl Ticker {
     pub fn as_str(&self) -> &str {
         &self.0
     }
+
+    /// The exchange suffix of a validated ticker, e.g. `L` for `VOD.L`
+    pub fn exchange_suffix(&self) -> Option<&str> {
+        Symbol::parse(&self.0)
+            .ok()
+            .and_then(|symbol| symbol.exchange_suffix)
+    }
+
+    /// The symbol with its share class joined by `share_class_separator` instead of `.`, for
+    /// providers that spell `BRK.B` as `BRK-B` or `BRK/B`. Unvalidated tickers are returned as is.
+    pub fn to_symbol(&self, share_class_separator: char) -> String {
+        Symbol::parse(&self.0)
+            .map(|symbol| symbol.to_string_with(share_class_separator))
+            .unwrap_or_else(|_| self.0.clone())
+    }
 }
 
 impl Display for Ticker {
@@ -28,10 +208,15 @@ impl From<&str> for Ticker {
 }
 
 impl FromStr for Ticker {
-    type Err = Infallible;
+    type Err = TickerParseError;
 
+    /// Trims and upper-cases `s`, then validates it as a symbol with an optional single letter
+    /// share class and exchange suffix, e.g. `msft`, `BRK.B`, `brk-b`, `VOD.L`, `^GSPC`, or a
+    /// crypto pair, future or FX pair in Yahoo's format, e.g. `BTC-USD`, `ES=F`, `EURUSD=X`.
+    /// Whether a provider has data for it is left to the provider.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Ticker::from(s))
+        let s = s.trim().to_uppercase();
+        Symbol::parse(&s).map(|symbol| Ticker(symbol.to_string_with('.')))
     }
 }
 
@@ -40,3 +225,108 @@ impl AsRef<str> for Ticker {
         &self.0
     }
 }
+
+/// Optional reference data about a ticker
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TickerMetadata {
+    pub name: Option<String>,
+    pub exchange: Option<String>,
+    pub currency: Option<String>,
+    pub sector: Option<String>,
+    pub industry: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Result<String, TickerParseError> {
+        s.parse::<Ticker>().map(|ticker| ticker.as_str().to_owned())
+    }
+
+    #[test]
+    fn ticker_parsing_trims_and_upper_cases() {
+        assert_eq!(parse(" msft\n"), Ok("MSFT".to_owned()));
+        assert_eq!(parse("^gspc"), Ok("^GSPC".to_owned()));
+    }
+
+    #[test]
+    fn ticker_parsing_accepts_share_classes_and_exchange_suffixes() {
+        assert_eq!(parse("BRK.B"), Ok("BRK.B".to_owned()));
+        assert_eq!(parse("bf-b"), Ok("BF.B".to_owned()));
+        assert_eq!(parse("BRK/A"), Ok("BRK.A".to_owned()));
+        assert_eq!(parse("VOD.L"), Ok("VOD.L".to_owned()));
+        assert_eq!(parse("shop.to"), Ok("SHOP.TO".to_owned()));
+        assert_eq!(parse("BBD.B.TO"), Ok("BBD.B.TO".to_owned()));
+        assert_eq!(parse("0700.HK"), Ok("0700.HK".to_owned()));
+        assert_eq!(parse("bbca.jk"), Ok("BBCA.JK".to_owned()));
+        assert_eq!(parse("1155.kl"), Ok("1155.KL".to_owned()));
+        assert_eq!(parse("PKO.WA"), Ok("PKO.WA".to_owned()));
+        assert_eq!(parse("THYAO.IS"), Ok("THYAO.IS".to_owned()));
+        assert_eq!(parse("7203.T"), Ok("7203.T".to_owned()));
+    }
+
+    #[test]
+    fn ticker_parsing_accepts_crypto_futures_and_fx_pairs() {
+        assert_eq!(parse("btc-usd"), Ok("BTC-USD".to_owned()));
+        assert_eq!(parse("ES=F"), Ok("ES=F".to_owned()));
+        assert_eq!(parse("EURUSD=X"), Ok("EURUSD=X".to_owned()));
+        assert_eq!(parse("JPY=X"), Ok("JPY=X".to_owned()));
+        assert_eq!(Ticker::from("BTC-USD").to_symbol('/'), "BTC-USD".to_owned());
+    }
+
+    #[test]
+    fn ticker_parsing_rejects_invalid_symbols() {
+        assert_eq!(parse(" \n"), Err(TickerParseError::Empty));
+        assert_eq!(
+            parse("MS FT"),
+            Err(TickerParseError::InvalidCharacter("MS FT".to_owned(), ' '))
+        );
+        assert_eq!(
+            parse("ABCDEFGH"),
+            Err(TickerParseError::TooLong("ABCDEFGH".to_owned()))
+        );
+        assert_eq!(
+            parse("VOD.XXXX"),
+            Err(TickerParseError::InvalidSuffix(
+                "VOD.XXXX".to_owned(),
+                "XXXX".to_owned()
+            ))
+        );
+        assert_eq!(
+            parse("ES=F1"),
+            Err(TickerParseError::InvalidSuffix(
+                "ES=F1".to_owned(),
+                "F1".to_owned()
+            ))
+        );
+        assert_eq!(
+            parse("BTC-USD.L"),
+            Err(TickerParseError::Malformed("BTC-USD.L".to_owned()))
+        );
+        assert_eq!(
+            parse("^GSPC=X"),
+            Err(TickerParseError::Malformed("^GSPC=X".to_owned()))
+        );
+        assert_eq!(
+            parse("BRK..B"),
+            Err(TickerParseError::Malformed("BRK..B".to_owned()))
+        );
+        assert_eq!(
+            parse("BRK-B.C.TO"),
+            Err(TickerParseError::Malformed("BRK-B.C.TO".to_owned()))
+        );
+    }
+
+    #[test]
+    fn ticker_maps_share_class_separator_for_providers() {
+        let ticker: Ticker = "BRK.B".parse().unwrap();
+        assert_eq!(ticker.to_symbol('-'), "BRK-B");
+        let ticker: Ticker = "BBD.B.TO".parse().unwrap();
+        assert_eq!(ticker.to_symbol('-'), "BBD-B.TO");
+        assert_eq!(Ticker::from("VOD.L").to_symbol('-'), "VOD.L");
+        assert_eq!(Ticker::from("not a ticker").to_symbol('-'), "not a ticker");
+        assert_eq!(Ticker::from("BBD.B.TO").exchange_suffix(), Some("TO"));
+        assert_eq!(Ticker::from("BRK.B").exchange_suffix(), None);
+    }
+}